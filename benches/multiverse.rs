@@ -0,0 +1,71 @@
+//! Benchmarks for the multiverse operations which dominate the running time of large games.
+//!
+//! Run using `cargo bench`, and again using `cargo bench --features=parallel` to compare.
+
+use {
+    criterion::{
+        BatchSize,
+        BenchmarkId,
+        Criterion,
+        criterion_group,
+        criterion_main,
+    },
+    rand::prelude::*,
+    rand_chacha::ChaCha20Rng,
+    quantum_werewolf::game::{
+        self,
+        LazyMultiverse,
+        Multiverse,
+        MultiverseBackend,
+        Op,
+        RevealRule,
+    },
+};
+
+const PLAYER_COUNTS: [usize; 2] = [10, 12];
+
+/// A multiverse for the given number of players with the default role distribution, after the werewolves have chosen their first kills.
+fn first_night<M: MultiverseBackend>(num_players: usize) -> M {
+    let mut multiverse = M::new(game::default_roles(num_players), num_players);
+    for player_idx in 0..num_players {
+        multiverse.apply(Op::Kill(player_idx, (player_idx + 1) % num_players));
+    }
+    multiverse
+}
+
+fn bench_backend<M: MultiverseBackend>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10);
+    for num_players in PLAYER_COUNTS {
+        group.bench_with_input(BenchmarkId::new("new", num_players), &num_players, |b, &num_players| b.iter(|| M::new(game::default_roles(num_players), num_players)));
+        group.bench_with_input(BenchmarkId::new("resolve_night", num_players), &num_players, |b, &num_players| b.iter_batched(
+            || first_night::<M>(num_players),
+            |mut multiverse| {
+                multiverse.apply(Op::Investigate(0, 1, game::Faction::Village));
+                multiverse.apply(Op::EndNight);
+                multiverse.collapse_roles(&mut ChaCha20Rng::seed_from_u64(0), RevealRule::Role).expect("paradox");
+                multiverse
+            },
+            BatchSize::LargeInput,
+        ));
+        let mut multiverse = first_night::<M>(num_players);
+        multiverse.apply(Op::EndNight);
+        multiverse.collapse_roles(&mut ChaCha20Rng::seed_from_u64(0), RevealRule::Role).expect("paradox");
+        // deserialize a fresh copy for each iteration so results cached by the `LazyMultiverse` aren't reused
+        let json = serde_json::to_string(&multiverse).expect("failed to serialize multiverse");
+        group.bench_with_input(BenchmarkId::new("probability_table", num_players), &json, |b, json| b.iter_batched(
+            || serde_json::from_str::<M>(json).expect("failed to deserialize multiverse"),
+            |multiverse| multiverse.probability_table().expect("paradox"),
+            BatchSize::LargeInput,
+        ));
+    }
+    group.finish();
+}
+
+fn multiverse(c: &mut Criterion) {
+    bench_backend::<Multiverse>(c, "Multiverse");
+    bench_backend::<LazyMultiverse>(c, "LazyMultiverse");
+}
+
+criterion_group!(benches, multiverse);
+criterion_main!(benches);
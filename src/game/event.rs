@@ -0,0 +1,288 @@
+//! A structured log of everything that happens in a game, which can be used to reconstruct every intermediate game state.
+
+use {
+    std::{
+        cell::Cell,
+        collections::HashSet,
+        fmt,
+        hash::Hash,
+    },
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    crate::game::{
+        MultiverseBackend,
+        NightAction,
+        NightActionResult,
+        Reveal,
+        Role,
+        state::{
+            GameError,
+            NightChoices,
+            Rules,
+            Signups,
+            StartGameError,
+            State,
+        },
+    },
+};
+
+/// Something that happened in a game.
+///
+/// Events are reported to the `Handler` in the order in which they happen. Together, the events of a game contain all information required to `replay` it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Event<P> {
+    /// A player has signed up.
+    SignUp(P),
+    /// The game has been started with the given roles and rules. All random decisions in the game will be derived from the seed.
+    Start {
+        /// The seed of the game's random number generator.
+        seed: u64,
+        /// The roles as passed to `Signups::start`.
+        roles: Vec<Role>,
+        /// The rules as passed to `Signups::start`.
+        #[serde(default)]
+        rules: Rules,
+        /// How the night actions in this game are resolved.
+        #[serde(default)]
+        resolution: Resolution,
+    },
+    /// Secret player IDs have been assigned. Contains the player list, sorted by secret player ID.
+    SecretIds(Vec<P>),
+    /// A player who may be a mason has been told who the other masons are if they are one.
+    MasonPartners(P, Vec<P>),
+    /// A player has chosen a night action.
+    NightAction(NightAction<P>),
+    /// All night actions for the current night have been chosen and resolved.
+    Dawn,
+    /// A hunter who has died has chosen a player to shoot, or not to shoot anyone.
+    ///
+    /// This is reported before the `Dawn` or `Lynch` event which resolves the death of the hunter.
+    HunterShot(P, Option<P>),
+    /// A player has received the result of a night action.
+    NightActionResult(P, NightActionResult<P>),
+    /// The town has voted on whom to lynch. Contains each voter and the player they voted to lynch, or `None` for a no-lynch vote, in the order in which the votes were cast.
+    ///
+    /// This is reported by handlers which collect individual votes, before the `Lynch` event with the outcome of the vote. It doesn't affect the game state.
+    Votes(Vec<(P, Option<P>)>),
+    /// The town has decided to lynch the given player, or not to lynch anyone.
+    Lynch(Option<P>),
+    /// A player has died in all universes. Contains what has been revealed about them according to the rules.
+    Death(P, Reveal),
+    /// The game has ended with the given winners.
+    GameOver(Vec<P>),
+}
+
+/// How the night actions in a game are resolved, as recorded in the `Start` event.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Players are asked for their night actions as they become relevant, using `Night::resolve_tar`. This is what `game::run` does.
+    #[default]
+    Temporal,
+    /// All night actions are submitted in advance and resolved together, using `Night::resolve_nar`.
+    Natural,
+}
+
+/// The possible errors returned by `replay`.
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    /// The event log does not contain a `Start` event.
+    #[error("the event log does not contain the start of the game")]
+    NotStarted,
+    /// The game could not be started.
+    #[error(transparent)]
+    Start(#[from] StartGameError),
+    /// The reconstructed game could not be continued.
+    #[error(transparent)]
+    Game(#[from] GameError),
+    /// An event occurred which is not possible in the current game state, for example a lynch at night.
+    #[error("unexpected event in event log: {0}")]
+    UnexpectedEvent(String),
+    /// An event which is determined by the game itself, such as a death, does not match the reconstructed game.
+    #[error("the event log does not match the reconstructed game: {0}")]
+    Mismatch(String),
+    /// A werewolf or serial killer kill which was required to resolve a night is missing from the event log.
+    #[error("the event log is missing a kill")]
+    MissingKill,
+    /// A choice of lovers which was required to resolve the first night is missing from the event log.
+    #[error("the event log is missing a choice of lovers")]
+    MissingLovers,
+}
+
+/// Reconstructs a game from its event log.
+///
+/// `on_state` is called with every intermediate game state, starting with the one right after the game was started. Events which are determined by the game itself, like deaths and night action results, are checked against the reconstructed game.
+///
+/// The game is reconstructed using the given representation for the set of possible universes. Since all representations visit universes in the same order, this does not need to match the one used to play the game.
+///
+/// Returns the last game state. If the log ends in the middle of a night, the night actions recorded for that night are not resolved.
+pub fn replay<P: Eq + Hash + Clone + fmt::Debug, M: MultiverseBackend, I: IntoIterator<Item = Event<P>>, F: FnMut(&State<P, M>)>(events: I, mut on_state: F) -> Result<State<P, M>, ReplayError> {
+    let mut players = Vec::default();
+    let mut resolution = Resolution::default();
+    let mut game_state = None::<State<P, M>>;
+    let mut night_actions = Vec::default();
+    let mut hunter_shots = Vec::default();
+    for event in events {
+        match (event, &mut game_state) {
+            (Event::SignUp(player), None) => { players.push(player); }
+            (Event::Start { seed, roles, rules, resolution: start_resolution }, None) => {
+                let mut signups = Signups::with_seed(seed);
+                for player in players.drain(..) {
+                    signups.sign_up(player);
+                }
+                resolution = start_resolution;
+                let new_state = signups.start_with_backend(roles, rules)?;
+                on_state(&new_state);
+                game_state = Some(new_state);
+            }
+            (Event::SecretIds(secret_ids), Some(game_state)) => if game_state.secret_ids() != Some(&secret_ids[..]) {
+                return Err(ReplayError::Mismatch(format!("secret IDs {:?}", secret_ids)))
+            },
+            (Event::MasonPartners(player, partners), Some(State::Night(night))) => if !night.mason_partners().into_iter().any(|(iter_player, iter_partners)| *iter_player == player && iter_partners.into_iter().eq(&partners)) {
+                return Err(ReplayError::Mismatch(format!("mason partners {:?} for {:?}", partners, player)))
+            },
+            (Event::NightAction(action), Some(State::Night(_))) => { night_actions.push(action); }
+            (Event::HunterShot(hunter, target), Some(State::Night(_) | State::Day(_))) => { hunter_shots.push((hunter, target)); }
+            (Event::Dawn, Some(State::Night(_))) => {
+                let Some(State::Night(night)) = game_state.take() else { unreachable!() };
+                let choices = LoggedChoices { night_actions: &night_actions, hunter_shots: &hunter_shots, missing_kill: Cell::new(false), missing_lovers: Cell::new(false) };
+                let new_state = match resolution {
+                    Resolution::Temporal => night.resolve_tar(&choices),
+                    Resolution::Natural => night.resolve_nar(&night_actions, |p, targets| choices.hunter_target(p, targets)),
+                };
+                if choices.missing_kill.get() { return Err(ReplayError::MissingKill) }
+                if choices.missing_lovers.get() { return Err(ReplayError::MissingLovers) }
+                let new_state = new_state?;
+                night_actions.clear();
+                hunter_shots.clear();
+                on_state(&new_state);
+                game_state = Some(new_state);
+            }
+            (Event::NightActionResult(player, result), Some(State::Day(day))) => if !day.night_action_results().into_iter().any(|(iter_player, iter_result)| *iter_player == player && iter_result.cloned() == result) {
+                return Err(ReplayError::Mismatch(format!("night action result {:?} for {:?}", result, player)))
+            },
+            (Event::Votes(votes), Some(State::Day(day))) => {
+                let alive = day.alive();
+                if let Some((voter, target)) = votes.iter().find(|(voter, target)| !alive.contains(voter) || target.as_ref().is_some_and(|target| !alive.contains(target))) {
+                    return Err(ReplayError::Mismatch(format!("vote of {:?} for {:?}", voter, target)))
+                }
+            }
+            (Event::Lynch(target), Some(State::Day(_))) => {
+                let Some(State::Day(day)) = game_state.take() else { unreachable!() };
+                let new_state = if let Some(target) = target {
+                    if !day.can_lynch(&target) {
+                        return Err(ReplayError::UnexpectedEvent(format!("lynch of {:?}", target)))
+                    }
+                    let choices = LoggedChoices { night_actions: &[], hunter_shots: &hunter_shots, missing_kill: Cell::new(false), missing_lovers: Cell::new(false) };
+                    day.lynch(target, |p, targets| choices.hunter_target(p, targets))?
+                } else {
+                    day.no_lynch()?
+                };
+                hunter_shots.clear();
+                on_state(&new_state);
+                game_state = Some(new_state);
+            }
+            (Event::Death(player, reveal), Some(game_state @ (State::Night(_) | State::Day(_)))) => if game_state.secret_ids().is_some_and(|secret_ids| !secret_ids.contains(&player)) || game_state.alive().is_some_and(|alive| alive.contains(&player)) || game_state.reveal(&player) != Some(reveal) {
+                return Err(ReplayError::Mismatch(format!("death of {:?} as {:?}", player, reveal)))
+            },
+            (Event::GameOver(winners), Some(State::Complete(complete))) => if winners.into_iter().collect::<HashSet<_>>() != complete.winners {
+                return Err(ReplayError::Mismatch(format!("winners {:?}", complete.winners)))
+            },
+            (event, _) => { return Err(ReplayError::UnexpectedEvent(format!("{:?}", event))) }
+        }
+    }
+    game_state.ok_or(ReplayError::NotStarted)
+}
+
+/// Takes the choices made during a night from an event log.
+///
+/// If a required choice is missing, this is recorded so `replay` can report it, and the player themselves is returned as a placeholder, since the resulting game state is discarded anyway.
+struct LoggedChoices<'a, P> {
+    night_actions: &'a [NightAction<P>],
+    hunter_shots: &'a [(P, Option<P>)],
+    missing_kill: Cell<bool>,
+    missing_lovers: Cell<bool>,
+}
+
+impl<P: Eq + Clone> NightChoices<P> for LoggedChoices<'_, P> {
+    fn lovers(&self, player: &P, _: Vec<&P>) -> (P, P) {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::Link(src, lover1, lover2) if src == player => Some((lover1.clone(), lover2.clone())),
+            _ => None,
+        }).unwrap_or_else(|| {
+            self.missing_lovers.set(true);
+            (player.clone(), player.clone())
+        })
+    }
+
+    fn heal_target(&self, player: &P, _: Vec<&P>) -> Option<P> {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::Heal(src, tgt) if src == player => Some(tgt.clone()),
+            _ => None,
+        })
+    }
+
+    fn guard_target(&self, player: &P, _: Vec<&P>) -> Option<P> {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::Guard(src, tgt) if src == player => Some(tgt.clone()),
+            _ => None,
+        })
+    }
+
+    fn investigation_target(&self, player: &P, _: Vec<&P>) -> Option<P> {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::Investigate(src, tgt) if src == player => Some(tgt.clone()),
+            _ => None,
+        })
+    }
+
+    fn seer_target(&self, player: &P, _: Vec<&P>) -> Option<P> {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::See(src, tgt) if src == player => Some(tgt.clone()),
+            _ => None,
+        })
+    }
+
+    fn medium_target(&self, player: &P, _: Vec<&P>) -> Option<P> {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::Seance(src, tgt) if src == player => Some(tgt.clone()),
+            _ => None,
+        })
+    }
+
+    fn werewolf_kill_target(&self, player: &P, _: Vec<&P>, mandatory: bool) -> Option<P> {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::Kill(src, tgt) if src == player => Some(tgt.clone()),
+            _ => None,
+        }).unwrap_or_else(|| mandatory.then(|| {
+            self.missing_kill.set(true);
+            player.clone()
+        }))
+    }
+
+    fn serial_killer_target(&self, player: &P, _: Vec<&P>) -> P {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::SerialKill(src, tgt) if src == player => Some(tgt.clone()),
+            _ => None,
+        }).unwrap_or_else(|| {
+            self.missing_kill.set(true);
+            player.clone()
+        })
+    }
+
+    fn witch_save(&self, player: &P) -> bool {
+        self.night_actions.iter().any(|action| matches!(action, NightAction::Save(src, true) if src == player))
+    }
+
+    fn poison_target(&self, player: &P, _: Vec<&P>) -> Option<P> {
+        self.night_actions.iter().find_map(|action| match action {
+            NightAction::Poison(src, tgt) if src == player => tgt.clone(),
+            _ => None,
+        })
+    }
+
+    fn hunter_target(&self, player: &P, _: Vec<&P>) -> Option<P> {
+        self.hunter_shots.iter().find_map(|(src, tgt)| if src == player { tgt.clone() } else { None })
+    }
+}
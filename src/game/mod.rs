@@ -1,9 +1,12 @@
 //! Core game logic.
 
+pub mod event;
 pub mod state;
+mod multiverse;
 mod types;
 
 use std::{
+    cell::RefCell,
     collections::HashSet,
     hash::Hash
 };
@@ -11,71 +14,247 @@ use crate::{
     handler::Handler,
     player::Player
 };
-pub use self::types::*;
+use self::{
+    event::Event,
+    state::NightChoices as _
+};
+pub use self::{
+    multiverse::*,
+    types::*
+};
 
 /// Generate a basic role distribution for the signed-up players, and moderate a game of Quantum Werewolf.
 ///
-/// The number of werewolves will be the 0.4 times the number of players, rounded down. There will also be one detective.
-///
 /// Returns the winners of the game.
-pub fn run<P: Eq + Hash + Clone + Player, H: Handler<P>>(handler: H, game_state: state::Signups<P>) -> Result<HashSet<P>, state::StartGameError> {
-    let num_ww = game_state.num_players() * 2 / 5;
-    let mut roles = (0..num_ww).map(|i| Role::Werewolf(i)).collect::<Vec<_>>();
+pub fn run<P: Eq + Hash + Clone + Player, H: Handler<P>>(handler: H, game_state: state::Signups<P>) -> Result<HashSet<P>, state::GameError> {
+    let roles = default_roles(game_state.num_players());
+    run_with_roles(handler, game_state, roles, state::Rules::default())
+}
+
+/// The basic role distribution used by `run`.
+///
+/// The number of werewolves will be the 0.4 times the number of players, rounded down. There will also be one detective.
+pub fn default_roles(num_players: usize) -> Vec<Role> {
+    let num_ww = num_players * 2 / 5;
+    let mut roles = (0..num_ww).map(Role::Werewolf).collect::<Vec<_>>();
     roles.push(Role::Detective);
-    run_with_roles(handler, game_state, roles)
+    roles
 }
 
-/// Moderate a game of Quantum Werewolf with the given players and roles.
+/// Moderate a game of Quantum Werewolf with the given players, roles, and rules.
 ///
 /// If fewer roles than players are given, a number of Villagers equal to the difference will be added.
 ///
 /// Returns the winners of the game.
-pub fn run_with_roles<P: Eq + Hash + Clone + Player, H: Handler<P>>(mut handler: H, game_state: state::Signups<P>, roles: Vec<Role>) -> Result<HashSet<P>, state::StartGameError> {
-    let mut game_state = game_state.start(roles)?;
-    let mut alive = game_state.alive().expect("failed to get list of living players").into_iter().cloned().collect::<HashSet<_>>();
+pub fn run_with_roles<P: Eq + Hash + Clone + Player, H: Handler<P>>(handler: H, game_state: state::Signups<P>, roles: Vec<Role>, rules: state::Rules) -> Result<HashSet<P>, state::GameError> {
+    run_with_backend::<P, H, Multiverse>(handler, game_state, roles, rules)
+}
+
+/// Moderate a game of Quantum Werewolf with the given players and roles, using the given representation for the set of possible universes.
+///
+/// Otherwise, this is the same as `run_with_roles`.
+pub fn run_with_backend<P: Eq + Hash + Clone + Player, H: Handler<P>, M: MultiverseBackend>(mut handler: H, game_state: state::Signups<P>, roles: Vec<Role>, rules: state::Rules) -> Result<HashSet<P>, state::GameError> {
+    for player in game_state.players() {
+        handler.record_event(&Event::SignUp(player.clone()));
+    }
+    handler.record_event(&Event::Start { seed: game_state.seed(), roles: roles.clone(), rules, resolution: event::Resolution::Temporal });
+    let game_state = game_state.start_with_backend::<M>(roles, rules)?;
     // assign secret player IDs
-    for (i, player) in game_state.secret_ids().expect("failed to get secred player IDs").into_iter().enumerate() {
-        player.recv_id(i);
+    if let Some(secret_ids) = game_state.secret_ids() {
+        handler.record_event(&Event::SecretIds(secret_ids.to_owned()));
+        for (i, player) in secret_ids.iter().enumerate() {
+            player.recv_id(i);
+        }
+    }
+    // tell players who may be masons about the other masons
+    if let state::State::Night(ref night) = game_state {
+        for (player, partners) in night.mason_partners() {
+            handler.record_event(&Event::MasonPartners(player.clone(), partners.iter().map(|&partner| partner.clone()).collect()));
+            player.recv_mason_partners(partners);
+        }
     }
+    handler.checkpoint(&game_state);
+    resume(handler, game_state)
+}
+
+/// Continue moderating a game of Quantum Werewolf from the given state, for example one that was previously saved using `Handler::checkpoint`.
+///
+/// If the game has not been started yet, a basic role distribution is generated as in `run`.
+///
+/// Returns the winners of the game.
+pub fn resume<P: Eq + Hash + Clone + Player, H: Handler<P>, M: MultiverseBackend>(mut handler: H, game_state: state::State<P, M>) -> Result<HashSet<P>, state::GameError> {
+    let mut game_state = match game_state {
+        state::State::Signups(signups) => {
+            let roles = default_roles(signups.num_players());
+            return run_with_backend::<P, H, M>(handler, signups, roles, state::Rules::default());
+        }
+        game_state => game_state
+    };
+    if let Some(budget) = handler.sample_budget() {
+        game_state.set_sample_budget(budget);
+    }
+    let mut alive = game_state.alive().map_or_else(HashSet::default, |alive| alive.into_iter().cloned().collect::<HashSet<_>>());
+    // events about several players list them in secret player ID order, so the event log doesn't depend on the iteration order of hash sets
+    let secret_ids = game_state.secret_ids().map_or_else(Vec::default, <[_]>::to_vec);
     Ok(loop {
         if let Some(new_alive) = game_state.alive() {
-            let new_alive = new_alive.into_iter().cloned().collect();
-            handler.announce_deaths((&alive - &new_alive).into_iter()
-                .filter_map(|player| game_state.role(&player).map(|role| (player, role)))
-            );
+            let new_alive = new_alive.into_iter().cloned().collect::<HashSet<_>>();
+            let deaths = secret_ids.iter()
+                .filter(|&player| alive.contains(player) && !new_alive.contains(player))
+                .filter_map(|player| game_state.reveal(player).map(|reveal| (player.clone(), reveal)))
+                .collect::<Vec<_>>();
+            for (player, reveal) in &deaths {
+                handler.record_event(&Event::Death(player.clone(), *reveal));
+            }
+            handler.announce_deaths(deaths);
             alive = new_alive;
         }
         game_state = match game_state {
             state::State::Signups(_) => unreachable!(),
             state::State::Night(night) => {
-                night.resolve_tar(
-                    |p, targets| p.choose_heal_target(targets),
-                    |p, targets| p.choose_investigation_target(targets),
-                    |p, targets| p.choose_werewolf_kill_target(targets)
-                )
+                let choices = PlayerChoices::default();
+                let game_state = night.resolve_tar(&choices);
+                choices.record(&mut handler);
+                handler.record_event(&Event::Dawn);
+                game_state?
             }
             state::State::Day(day) => {
                 // send night action results
                 for (player, result) in day.night_action_results() {
+                    handler.record_event(&Event::NightActionResult(player.clone(), result.cloned()));
                     match result {
                         NightActionResult::Investigation(target, faction) => { player.recv_investigation(target, faction); }
+                        NightActionResult::Vision(target, role) => { player.recv_vision(target, role); }
+                        NightActionResult::Seance(target, cause) => { player.recv_seance(target, cause); }
                     }
                 }
                 // announce probability table
-                handler.announce_probability_table(day.probability_table());
+                if let Some(budget) = handler.sample_budget() {
+                    handler.announce_approximate_probability_table(day.approximate_probability_table(budget));
+                } else {
+                    handler.announce_probability_table(day.probability_table()?);
+                    handler.announce_role_probability_table(day.role_probability_table());
+                }
                 // vote
                 loop {
                     if let Some(target) = handler.choose_lynch_target(day.alive()) {
                         if day.can_lynch(&target) {
-                            break day.lynch(target);
+                            let choices = PlayerChoices::default();
+                            let game_state = day.lynch(target.clone(), |p, targets| choices.hunter_target(p, targets));
+                            choices.record(&mut handler);
+                            handler.record_event(&Event::Lynch(Some(target)));
+                            break game_state?;
                         }
                         handler.cannot_lynch(target);
+                    } else if day.can_skip_lynch() {
+                        handler.record_event(&Event::Lynch(None));
+                        break day.no_lynch()?;
                     } else {
-                        break day.no_lynch();
+                        handler.cannot_skip_lynch();
                     }
                 }
             }
-            state::State::Complete(state::Complete { winners }) => { break winners; }
+            state::State::Complete(state::Complete { winners }) => {
+                let mut sorted_winners = secret_ids.iter().filter(|&player| winners.contains(player)).cloned().collect::<Vec<_>>();
+                if sorted_winners.len() < winners.len() { sorted_winners = winners.iter().cloned().collect() } // resumed after the game was over, so the secret player IDs are unknown
+                handler.record_event(&Event::GameOver(sorted_winners));
+                break winners;
+            }
         };
+        handler.checkpoint(&game_state);
     })
 }
+
+/// Asks the players themselves for their choices, and remembers them so they can be recorded as events.
+struct PlayerChoices<P> {
+    night_actions: RefCell<Vec<NightAction<P>>>,
+    hunter_shots: RefCell<Vec<(P, Option<P>)>>
+}
+
+impl<P: Player> PlayerChoices<P> {
+    fn record<H: Handler<P>>(self, handler: &mut H) where P: Eq + Hash {
+        for action in self.night_actions.into_inner() {
+            handler.record_event(&Event::NightAction(action));
+        }
+        for (hunter, target) in self.hunter_shots.into_inner() {
+            handler.record_event(&Event::HunterShot(hunter, target));
+        }
+    }
+}
+
+impl<P> Default for PlayerChoices<P> {
+    fn default() -> PlayerChoices<P> {
+        PlayerChoices {
+            night_actions: RefCell::default(),
+            hunter_shots: RefCell::default()
+        }
+    }
+}
+
+impl<P: Clone + Player> state::NightChoices<P> for PlayerChoices<P> {
+    fn lovers(&self, player: &P, possible_targets: Vec<&P>) -> (P, P) {
+        let (lover1, lover2) = player.choose_lovers(possible_targets);
+        self.night_actions.borrow_mut().push(NightAction::Link(player.clone(), lover1.clone(), lover2.clone()));
+        (lover1, lover2)
+    }
+
+    fn heal_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P> {
+        let target = player.choose_heal_target(possible_targets);
+        if let Some(ref target) = target { self.night_actions.borrow_mut().push(NightAction::Heal(player.clone(), target.clone())); }
+        target
+    }
+
+    fn guard_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P> {
+        let target = player.choose_guard_target(possible_targets);
+        if let Some(ref target) = target { self.night_actions.borrow_mut().push(NightAction::Guard(player.clone(), target.clone())); }
+        target
+    }
+
+    fn investigation_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P> {
+        let target = player.choose_investigation_target(possible_targets);
+        if let Some(ref target) = target { self.night_actions.borrow_mut().push(NightAction::Investigate(player.clone(), target.clone())); }
+        target
+    }
+
+    fn seer_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P> {
+        let target = player.choose_seer_target(possible_targets);
+        if let Some(ref target) = target { self.night_actions.borrow_mut().push(NightAction::See(player.clone(), target.clone())); }
+        target
+    }
+
+    fn medium_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P> {
+        let target = player.choose_medium_target(possible_targets);
+        if let Some(ref target) = target { self.night_actions.borrow_mut().push(NightAction::Seance(player.clone(), target.clone())); }
+        target
+    }
+
+    fn werewolf_kill_target(&self, player: &P, possible_targets: Vec<&P>, mandatory: bool) -> Option<P> {
+        let target = player.choose_werewolf_kill_target(possible_targets, mandatory);
+        self.night_actions.borrow_mut().push(NightAction::Kill(player.clone(), target.clone()));
+        target
+    }
+
+    fn serial_killer_target(&self, player: &P, possible_targets: Vec<&P>) -> P {
+        let target = player.choose_serial_killer_target(possible_targets);
+        self.night_actions.borrow_mut().push(NightAction::SerialKill(player.clone(), target.clone()));
+        target
+    }
+
+    fn witch_save(&self, player: &P) -> bool {
+        let save = player.choose_witch_save();
+        self.night_actions.borrow_mut().push(NightAction::Save(player.clone(), save));
+        save
+    }
+
+    fn poison_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P> {
+        let target = player.choose_poison_target(possible_targets);
+        self.night_actions.borrow_mut().push(NightAction::Poison(player.clone(), target.clone()));
+        target
+    }
+
+    fn hunter_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P> {
+        let target = player.choose_hunter_target(possible_targets);
+        self.hunter_shots.borrow_mut().push((player.clone(), target.clone()));
+        target
+    }
+}
@@ -0,0 +1,932 @@
+//! The set of possible universes in a game, and different ways to represent it.
+
+use {
+    std::{
+        collections::{
+            BTreeMap,
+            BTreeSet,
+            HashMap,
+        },
+        fmt,
+        slice,
+        sync::OnceLock,
+        vec,
+    },
+    rand::prelude::*,
+    rand_chacha::ChaCha20Rng,
+    serde::{
+        Deserialize,
+        Serialize,
+        de::DeserializeOwned,
+    },
+    crate::game::{
+        Faction,
+        Op,
+        PlayerSet,
+        RevealRule,
+        Role,
+        Universe,
+        state::GameError,
+    },
+};
+#[cfg(feature = "parallel")] use rayon::prelude::*;
+
+/// A representation of the set of possible universes in a game.
+///
+/// The game state is generic over this trait, so `Night` and `Day` can use either the `Multiverse`, which stores every universe, or the `LazyMultiverse`, which only stores constraints on the role distribution along with what has happened, and reconstructs or samples the universes when needed.
+///
+/// Implementations must visit universes in the same order, sorted by role distribution, so that a game with a given seed plays out identically regardless of representation.
+pub trait MultiverseBackend: Sized + Serialize + DeserializeOwned {
+    /// Constructs a multiverse with all possible role distributions.
+    ///
+    /// If fewer roles than players are given, the remaining role slots will be populated with villagers.
+    fn new(roles: Vec<Role>, num_players: usize) -> Self;
+
+    /// The total number of players for which this multiverse was created.
+    fn num_players(&self) -> usize;
+
+    /// Applies an operation to every universe, removing those which have become impossible.
+    fn apply(&mut self, op: Op);
+
+    /// Allows queries to be answered from a random sample of universes, drawn using the given seed, if going through all possible universes would mean looking at more than `budget` of them.
+    ///
+    /// Answers based on a sample are estimates, so a game may play out differently after this has been called. By default, this does nothing, so all queries are answered exactly.
+    fn set_sample_budget(&mut self, _budget: usize, _seed: u64) {}
+
+    /// Calls the given function with every possible universe, sorted by role distribution.
+    fn for_each<F: FnMut(&Universe)>(&self, f: F);
+
+    /// Combines all possible universes into a single value, for example a count.
+    ///
+    /// Universes are added to accumulators starting at `identity()` using `fold`, and the accumulators are merged using `combine`. The order of universes is preserved, but `combine` must be associative. This allows implementations to split the universes into parts and process them in parallel, as is done if the `parallel` feature is enabled. By default, all universes are folded into a single accumulator using `for_each`.
+    fn fold<T, I, F, C>(&self, identity: I, fold: F, _: C) -> T
+    where T: Send, I: Fn() -> T + Sync + Send, F: Fn(T, &Universe) -> T + Sync + Send, C: Fn(T, T) -> T + Sync + Send {
+        let mut acc = Some(identity());
+        self.for_each(|universe| acc = acc.take().map(|acc| fold(acc, universe)));
+        acc.expect("accumulator lost during fold")
+    }
+
+    /// Returns the set of players which are still alive in at least one possible universe.
+    fn alive(&self) -> Vec<usize> {
+        self.alive_set().iter().collect()
+    }
+
+    /// Returns the set of players which are still alive in at least one possible universe, as a `PlayerSet`.
+    fn alive_set(&self) -> PlayerSet {
+        PlayerSet(self.fold(|| 0, |alive, universe| alive | universe.alive.0, |alive1, alive2| alive1 | alive2))
+    }
+
+    /// Chooses a random universe among those matching the filter, taking universe weights into account.
+    fn choose<R: Rng, F: Fn(&Universe) -> bool + Sync>(&self, rng: &mut R, filter: F) -> Option<Universe> {
+        let total = self.fold(|| 0, |total, universe| if filter(universe) { total + universe.weight() } else { total }, |total1, total2| total1 + total2);
+        if total == 0 { return None }
+        let mut remaining = rng.gen_range(0..total);
+        let mut chosen = None;
+        self.for_each(|universe| if chosen.is_none() && filter(universe) {
+            if remaining < universe.weight() {
+                chosen = Some(universe.clone());
+            } else {
+                remaining -= universe.weight();
+            }
+        });
+        chosen
+    }
+
+    /// Determines what is revealed about each dead player according to the reveal rule, and removes all universes inconsistent with it.
+    ///
+    /// With `RevealRule::Role`, this determines a single role for each dead player, and with `RevealRule::Faction`, a single faction. With `RevealRule::Nothing`, this does nothing.
+    ///
+    /// Returns an error if there are no possible universes.
+    fn collapse_roles<R: Rng>(&mut self, rng: &mut R, reveal: RevealRule) -> Result<(), GameError> {
+        if reveal == RevealRule::Nothing { return Ok(()) }
+        let mut start_weight = self.total_weight();
+        loop {
+            let alive = self.alive_set();
+            let collapse_universe = self.choose(rng, |_| true).ok_or(GameError::Paradox)?;
+            for player_idx in 0..self.num_players() {
+                if !alive.contains(player_idx) {
+                    self.apply(match reveal {
+                        RevealRule::Role => Op::Collapse(player_idx, collapse_universe.role(player_idx)),
+                        RevealRule::Faction => Op::CollapseFaction(player_idx, collapse_universe.faction(player_idx)),
+                        RevealRule::Nothing => unreachable!("nothing is revealed about dead players"),
+                    });
+                }
+            }
+            let weight = self.total_weight();
+            if weight == start_weight {
+                break Ok(());
+            } else {
+                start_weight = weight;
+            }
+        }
+    }
+
+    /// Returns the faction of the given player, if that faction is unambiguous.
+    fn faction(&self, player_idx: usize) -> Option<Faction> {
+        unambiguous(self, |universe| universe.faction(player_idx))
+    }
+
+    /// Checks if the game has ended in all universes.
+    fn game_over(&self, night: bool) -> bool {
+        self.fold(|| true, |game_over, universe| game_over && universe.game_over(night), |game_over1, game_over2| game_over1 && game_over2)
+    }
+
+    /// Produces the anonymized probability table shown to players at the start of the day.
+    ///
+    /// For players who may belong to a third-party faction, like the serial killer, the village and werewolf probabilities add up to less than 1. Dead players whose faction is known are listed with their faction.
+    ///
+    /// Returns an error if there are no possible universes.
+    fn probability_table(&self) -> Result<ProbabilityTable, GameError> {
+        let num_players = self.num_players();
+        let counts = self.fold(|| Counts::new(num_players), Counts::add, Counts::merge);
+        let Some(ref example) = counts.example else { return Err(GameError::Paradox) };
+        let total = counts.total as f64;
+        Ok((0..num_players).map(|player_idx| {
+            if counts.alive.contains(player_idx) || counts.ambiguous.contains(player_idx) {
+                Ok((
+                    counts.village[player_idx] as f64 / total,
+                    counts.werewolves[player_idx] as f64 / total,
+                    counts.dead[player_idx] as f64 / total
+                ))
+            } else {
+                // the faction of this dead player is the same in all universes
+                Err(example.faction(player_idx))
+            }
+        }).collect())
+    }
+
+    /// Estimates the probability table shown to players at the start of the day from a random sample of universes, see `sample`.
+    ///
+    /// This can be used if computing the exact `probability_table` would take too long.
+    fn approximate_probability_table<R: Rng>(&self, rng: &mut R, budget: usize) -> Vec<Result<(Estimate, Estimate, Estimate), Faction>> {
+        let num_players = self.num_players();
+        let sample = self.sample(rng, budget);
+        let mut village_universes = vec![0; num_players];
+        let mut werewolf_universes = vec![0; num_players];
+        let mut dead_universes = vec![0; num_players];
+        for universe in &sample {
+            for player_idx in 0..num_players {
+                match universe.faction(player_idx) {
+                    Faction::Village => { village_universes[player_idx] += 1; }
+                    Faction::Werewolves => { werewolf_universes[player_idx] += 1; }
+                    Faction::SerialKiller | Faction::Tanner | Faction::Lovers => {}
+                }
+                if !universe.is_alive(player_idx) {
+                    dead_universes[player_idx] += 1;
+                }
+            }
+        }
+        let alive = self.alive_set();
+        (0..num_players).map(|player_idx| {
+            if !alive.contains(player_idx) {
+                // list dead players with their faction if it is the same in all (sampled) universes
+                let faction = if sample.is_empty() {
+                    self.faction(player_idx)
+                } else {
+                    sample.iter()
+                        .map(|universe| Some(universe.faction(player_idx)))
+                        .reduce(|faction1, faction2| if faction1 == faction2 { faction1 } else { None })
+                        .flatten()
+                };
+                if let Some(faction) = faction { return Err(faction) }
+            }
+            Ok((
+                Estimate::new(village_universes[player_idx], sample.len()),
+                Estimate::new(werewolf_universes[player_idx], sample.len()),
+                Estimate::new(dead_universes[player_idx], sample.len())
+            ))
+        }).collect()
+    }
+
+    /// Produces a more detailed version of the probability table, with the probability of each role instead of each faction.
+    ///
+    /// For each player in secret player ID order, returns the probabilities of the roles that player may have, if that player can still be alive or their role is still undetermined. Otherwise, returns that player's role.
+    fn role_probability_table(&self) -> Vec<Result<Vec<(Role, f64)>, Role>> {
+        let num_players = self.num_players();
+        self.fold(|| RoleCounts::new(num_players), RoleCounts::add, RoleCounts::merge).role_probability_table()
+    }
+
+    /// Returns the role of the given player, if that role is unambiguous.
+    fn role(&self, player_idx: usize) -> Option<Role> {
+        unambiguous(self, |universe| universe.role(player_idx))
+    }
+
+    /// Whether or not there is at least one player who may be alive and may have this role.
+    ///
+    /// Note that this may return true even if this role is dead in all universes, namely if there are multiple players who may have that role.
+    fn role_alive(&self, role: Role) -> bool {
+        // the set of players whose role is not the same in all universes
+        let ambiguous = self.fold(
+            || None,
+            |acc, universe| merge_ambiguous(acc, Some((universe.roles.clone(), PlayerSet::default()))),
+            merge_ambiguous,
+        ).map_or_else(PlayerSet::default, |(_, ambiguous)| ambiguous);
+        self.fold(
+            || false,
+            |role_alive, universe| role_alive || universe.roles().enumerate().any(|(player_idx, iter_role)|
+                role == iter_role && (
+                    universe.is_alive(player_idx) ||
+                    ambiguous.contains(player_idx)
+                )
+            ),
+            |role_alive1, role_alive2| role_alive1 || role_alive2,
+        )
+    }
+
+    /// Draws universes at random, with replacement, taking universe weights into account.
+    ///
+    /// The budget is the number of attempts. Implementations which cannot draw a possible universe directly may discard some attempts, so fewer universes than the budget may be returned.
+    fn sample<R: Rng>(&self, rng: &mut R, budget: usize) -> Vec<Universe> {
+        let mut cumulative_weights = Vec::default();
+        let mut universes = Vec::default();
+        let mut total = 0;
+        self.for_each(|universe| {
+            total += universe.weight();
+            cumulative_weights.push(total);
+            universes.push(universe.clone());
+        });
+        if total == 0 { return Vec::default() }
+        (0..budget).map(|_| {
+            let target = rng.gen_range(0..total);
+            universes[cumulative_weights.partition_point(|&weight| weight <= target)].clone()
+        }).collect()
+    }
+
+    /// The total weight of all universes, i.e. the number of original universes which are still possible.
+    fn total_weight(&self) -> u64 {
+        self.fold(|| 0, |total, universe| total + universe.weight(), |total1, total2| total1 + total2)
+    }
+}
+
+/// The anonymized probability table as returned by `MultiverseBackend::probability_table`.
+pub type ProbabilityTable = Vec<Result<(f64, f64, f64), Faction>>;
+
+/// An estimated probability, together with a 95% confidence interval.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// The most likely value of the probability.
+    pub value: f64,
+    /// The lower bound of the confidence interval.
+    pub low: f64,
+    /// The upper bound of the confidence interval.
+    pub high: f64,
+}
+
+impl Estimate {
+    /// Estimates a probability from the number of universes in a random sample with some property, using the Wilson score interval.
+    pub fn new(successes: usize, sample_size: usize) -> Estimate {
+        if sample_size == 0 {
+            return Estimate { value: 0.5, low: 0.0, high: 1.0 }
+        }
+        const Z: f64 = 1.96; // 95% confidence
+        let n = sample_size as f64;
+        let p = successes as f64 / n;
+        let center = (p + Z * Z / (2.0 * n)) / (1.0 + Z * Z / n);
+        let margin = Z / (1.0 + Z * Z / n) * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt();
+        Estimate {
+            value: p,
+            low: (center - margin).max(0.0),
+            high: (center + margin).min(1.0),
+        }
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}% ({}-{}%)", (self.value * 100.0).round() as u8, (self.low * 100.0).round() as u8, (self.high * 100.0).round() as u8)
+    }
+}
+
+/// The total weight of the universes in which each player is alive, is part of each faction, and is dead, used to compute the probability table.
+struct Counts {
+    total: u64,
+    alive: PlayerSet,
+    ambiguous: PlayerSet, // players whose faction is not the same in all universes
+    example: Option<Universe>,
+    village: Vec<u64>,
+    werewolves: Vec<u64>,
+    dead: Vec<u64>,
+}
+
+impl Counts {
+    fn new(num_players: usize) -> Counts {
+        Counts {
+            total: 0,
+            alive: PlayerSet::default(),
+            ambiguous: PlayerSet::default(),
+            example: None,
+            village: vec![0; num_players],
+            werewolves: vec![0; num_players],
+            dead: vec![0; num_players],
+        }
+    }
+
+    fn add(mut self, universe: &Universe) -> Counts {
+        self.total += universe.weight();
+        self.alive.0 |= universe.alive.0;
+        if self.example.is_none() {
+            self.example = Some(universe.clone());
+        }
+        for player_idx in 0..self.village.len() {
+            if self.example.as_ref().is_some_and(|example| example.faction(player_idx) != universe.faction(player_idx)) {
+                self.ambiguous.insert(player_idx);
+            }
+            match universe.faction(player_idx) {
+                Faction::Village => { self.village[player_idx] += universe.weight(); }
+                Faction::Werewolves => { self.werewolves[player_idx] += universe.weight(); }
+                Faction::SerialKiller | Faction::Tanner => {}
+                Faction::Lovers => unreachable!("no role belongs to the lovers by default"),
+            }
+            if !universe.is_alive(player_idx) {
+                self.dead[player_idx] += universe.weight();
+            }
+        }
+        self
+    }
+
+    fn merge(mut self, other: Counts) -> Counts {
+        self.total += other.total;
+        self.alive.0 |= other.alive.0;
+        self.ambiguous.0 |= other.ambiguous.0;
+        for player_idx in 0..self.village.len() {
+            if let (Some(example1), Some(example2)) = (&self.example, &other.example) {
+                if example1.faction(player_idx) != example2.faction(player_idx) {
+                    self.ambiguous.insert(player_idx);
+                }
+            }
+            self.village[player_idx] += other.village[player_idx];
+            self.werewolves[player_idx] += other.werewolves[player_idx];
+            self.dead[player_idx] += other.dead[player_idx];
+        }
+        self.example = self.example.or(other.example);
+        self
+    }
+}
+
+/// The total weight of the universes in which each player has each role, used to compute the role probability table.
+#[derive(Debug, Clone)]
+struct RoleCounts {
+    total: u64,
+    alive: PlayerSet,
+    roles: Vec<BTreeMap<u8, u64>>,
+}
+
+impl RoleCounts {
+    fn new(num_players: usize) -> RoleCounts {
+        RoleCounts {
+            total: 0,
+            alive: PlayerSet::default(),
+            roles: vec![BTreeMap::default(); num_players],
+        }
+    }
+
+    fn add(mut self, universe: &Universe) -> RoleCounts {
+        self.total += universe.weight();
+        self.alive.0 |= universe.alive.0;
+        for (roles, &role) in self.roles.iter_mut().zip(&*universe.roles) {
+            *roles.entry(role).or_default() += universe.weight();
+        }
+        self
+    }
+
+    fn merge(mut self, other: RoleCounts) -> RoleCounts {
+        self.total += other.total;
+        self.alive.0 |= other.alive.0;
+        for (roles, other_roles) in self.roles.iter_mut().zip(other.roles) {
+            for (role, weight) in other_roles {
+                *roles.entry(role).or_default() += weight;
+            }
+        }
+        self
+    }
+
+    fn role_probability_table(&self) -> Vec<Result<Vec<(Role, f64)>, Role>> {
+        let total = self.total as f64;
+        self.roles.iter().enumerate().map(|(player_idx, roles)| {
+            if let (false, [(&role, _)]) = (self.alive.contains(player_idx), &roles.iter().collect::<Vec<_>>()[..]) {
+                Err(Role::unpack(role))
+            } else {
+                Ok(roles.iter().map(|(&role, &weight)| (Role::unpack(role), weight as f64 / total)).collect())
+            }
+        }).collect()
+    }
+}
+
+/// Everything about the possible universes of a `LazyMultiverse` which is cached until the next operation.
+#[derive(Debug, Clone)]
+struct Summary {
+    counts: RoleCounts,
+    /// For each player, the roles they have in the universes in which they are alive.
+    alive_roles: Vec<BTreeSet<u8>>,
+    /// For each player, the total weight of the universes in which they are dead.
+    dead: Vec<u64>,
+    game_over_night: bool,
+    game_over_day: bool,
+}
+
+impl Summary {
+    fn new(num_players: usize) -> Summary {
+        Summary {
+            counts: RoleCounts::new(num_players),
+            alive_roles: vec![BTreeSet::default(); num_players],
+            dead: vec![0; num_players],
+            game_over_night: true,
+            game_over_day: true,
+        }
+    }
+
+    fn add(mut self, universe: &Universe) -> Summary {
+        self.counts = self.counts.add(universe);
+        for (player_idx, (alive_roles, dead)) in self.alive_roles.iter_mut().zip(&mut self.dead).enumerate() {
+            if universe.is_alive(player_idx) {
+                alive_roles.insert(universe.roles[player_idx]);
+            } else {
+                *dead += universe.weight();
+            }
+        }
+        self.game_over_night &= universe.game_over(true);
+        self.game_over_day &= universe.game_over(false);
+        self
+    }
+
+    fn merge(mut self, other: Summary) -> Summary {
+        self.counts = self.counts.merge(other.counts);
+        for (alive_roles, other_alive_roles) in self.alive_roles.iter_mut().zip(other.alive_roles) {
+            alive_roles.extend(other_alive_roles);
+        }
+        for (dead, other_dead) in self.dead.iter_mut().zip(other.dead) {
+            *dead += other_dead;
+        }
+        self.game_over_night &= other.game_over_night;
+        self.game_over_day &= other.game_over_day;
+        self
+    }
+
+    /// Returns the faction of the given player, if it is the same in all universes.
+    fn faction(&self, player_idx: usize) -> Option<Faction> {
+        let mut factions = self.counts.roles[player_idx].keys().map(|&role| Role::unpack(role).default_faction());
+        let faction = factions.next()?;
+        factions.all(|iter_faction| iter_faction == faction).then_some(faction)
+    }
+}
+
+/// Returns the value of `f` if it is the same in all universes.
+fn unambiguous<M: MultiverseBackend, T: PartialEq + Send, F: Fn(&Universe) -> T + Sync + Send>(multiverse: &M, f: F) -> Option<T> {
+    // `None` if there are no universes, `Some(None)` if the value is ambiguous
+    let merge = |value1: Option<Option<T>>, value2: Option<Option<T>>| match (value1, value2) {
+        (None, value) | (value, None) => value,
+        (Some(Some(value1)), Some(Some(value2))) if value1 == value2 => Some(Some(value1)),
+        (Some(_), Some(_)) => Some(None),
+    };
+    multiverse.fold(|| None, |value, universe| merge(value, Some(Some(f(universe)))), merge).flatten()
+}
+
+/// Merges the roles of two groups of universes, along with the players whose roles differ within each group.
+fn merge_ambiguous(acc1: Option<(Box<[u8]>, PlayerSet)>, acc2: Option<(Box<[u8]>, PlayerSet)>) -> Option<(Box<[u8]>, PlayerSet)> {
+    match (acc1, acc2) {
+        (None, acc) | (acc, None) => acc,
+        (Some((roles1, mut ambiguous)), Some((roles2, ambiguous2))) => {
+            ambiguous.0 |= ambiguous2.0;
+            for (player_idx, (role1, role2)) in roles1.iter().zip(&*roles2).enumerate() {
+                if role1 != role2 {
+                    ambiguous.insert(player_idx);
+                }
+            }
+            Some((roles1, ambiguous))
+        }
+    }
+}
+
+/// A multiverse which stores every possible universe. Universes which have become indistinguishable are merged.
+///
+/// Operations on this multiverse are fast, but the number of universes at the start of the game grows exponentially with the number of special roles.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Multiverse(Vec<Universe>);
+
+impl Multiverse {
+    /// Merges universes which have become indistinguishable, adding up their weights.
+    pub fn dedup(&mut self) {
+        let cmp = |u1: &Universe, u2: &Universe| u1.roles.cmp(&u2.roles)
+            .then_with(|| u1.alive.cmp(&u2.alive))
+            .then_with(|| u1.heals.cmp(&u2.heals))
+            .then_with(|| u1.kills.cmp(&u2.kills))
+            .then_with(|| u1.attacks.cmp(&u2.attacks))
+            .then_with(|| u1.guards.cmp(&u2.guards))
+            .then_with(|| u1.werewolf_votes.cmp(&u2.werewolf_votes))
+            .then_with(|| u1.saves_used.cmp(&u2.saves_used))
+            .then_with(|| u1.poisons_used.cmp(&u2.poisons_used))
+            .then_with(|| u1.lovers.cmp(&u2.lovers))
+            .then_with(|| u1.lynched.cmp(&u2.lynched))
+            .then_with(|| u1.deaths.cmp(&u2.deaths));
+        #[cfg(feature = "parallel")] self.0.par_sort_unstable_by(cmp);
+        #[cfg(not(feature = "parallel"))] self.0.sort_unstable_by(cmp);
+        self.0.dedup_by(|universe, kept| if universe.same_state(kept) {
+            kept.weight += universe.weight;
+            true
+        } else {
+            false
+        });
+    }
+
+    /// Iterates over all universes, sorted by role distribution.
+    pub fn iter(&self) -> slice::Iter<'_, Universe> {
+        self.0.iter()
+    }
+
+    /// Iterates over all universes, sorted by role distribution.
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, Universe> {
+        self.0.iter_mut()
+    }
+}
+
+impl MultiverseBackend for Multiverse {
+    fn new(roles: Vec<Role>, num_players: usize) -> Multiverse {
+        let villager = Role::Villager.pack();
+        let mut permutations = vec![vec![villager; num_players].into_boxed_slice()];
+        for role in roles {
+            let role = role.pack();
+            permutations = permutations.into_iter().flat_map(|perm| {
+                (0..perm.len()).filter_map(|i| {
+                    if perm[i] == villager {
+                        let mut new_perm = perm.clone();
+                        new_perm[i] = role;
+                        Some(new_perm)
+                    } else {
+                        None
+                    }
+                }).collect::<Vec<_>>()
+            }).collect();
+        }
+        let mut multiverse = Multiverse(permutations.into_iter().map(|roles| Universe::from_packed(roles, 1)).collect());
+        multiverse.dedup();
+        multiverse
+    }
+
+    fn num_players(&self) -> usize {
+        self.0.first().map_or(0, |universe| universe.roles.len())
+    }
+
+    fn apply(&mut self, op: Op) {
+        #[cfg(feature = "parallel")] {
+            let mut keep = self.0.par_iter_mut().map(|universe| universe.apply(op)).collect::<Vec<_>>().into_iter();
+            self.0.retain(|_| keep.next().expect("number of universes changed"));
+        }
+        #[cfg(not(feature = "parallel"))] self.0.retain_mut(|universe| universe.apply(op));
+        if let Op::EndNight | Op::Lynch(_) | Op::Shoot(_) = op {
+            self.dedup();
+        }
+    }
+
+    fn for_each<F: FnMut(&Universe)>(&self, f: F) {
+        self.0.iter().for_each(f);
+    }
+
+    #[cfg(feature = "parallel")]
+    fn fold<T, I, F, C>(&self, identity: I, fold: F, combine: C) -> T
+    where T: Send, I: Fn() -> T + Sync + Send, F: Fn(T, &Universe) -> T + Sync + Send, C: Fn(T, T) -> T + Sync + Send {
+        self.0.par_iter().fold(&identity, &fold).reduce(&identity, &combine)
+    }
+}
+
+impl IntoIterator for Multiverse {
+    type Item = Universe;
+    type IntoIter = vec::IntoIter<Universe>;
+
+    /// Iterates over all universes, sorted by role distribution.
+    fn into_iter(self) -> vec::IntoIter<Universe> {
+        self.0.into_iter()
+    }
+}
+
+impl FromIterator<Universe> for Multiverse {
+    fn from_iter<I: IntoIterator<Item = Universe>>(iter: I) -> Multiverse {
+        Multiverse(iter.into_iter().collect())
+    }
+}
+
+/// A multiverse which does not store any universes. Instead, it stores the roles in the game, the players who may have each role (the candidates), and a history of all operations which don't simply rule out roles for a player.
+///
+/// Revealing a role or faction, or that a player was not a hunter, only narrows down the candidates. After a pass over the universes, the candidates are also narrowed down to the roles each player has in at least one possible universe. A pass goes through the role distributions allowed by the candidates, counting them and replaying the history for each of them, so it takes time proportional to the number of allowed role distributions times the length of the history. Since the candidates only take into account what is certain for each player individually, this is still slow in large games in which few roles have been revealed.
+///
+/// If a sample budget has been set using `set_sample_budget` and the candidates allow more role distributions than the budget, queries are answered from a random sample of universes instead. The sample is drawn using rejection sampling: as many role distributions as the budget are drawn uniformly at random among those allowed by the candidates, and discarded if the history rules them out. Later operations are applied to the sampled universes, and a new sample is drawn once fewer than half of them remain. Answers based on a sample are estimates, for example a player who is alive in only a few universes may be considered dead, so a game played with a sample budget does not play out the same way as with the `Multiverse`.
+///
+/// The information needed by most queries (which players may be alive, which roles each player may have, whether the game is over, and the probability tables) is collected in a single pass and cached until the next operation is applied. Other queries, like `choose`, always make a new pass.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LazyMultiverse {
+    /// The distinct roles in the game including villagers, packed using `Role::pack` and sorted, along with the number of players who have each of them.
+    roles: Vec<(u8, usize)>,
+    /// For each of the `roles`, the players who may have it.
+    candidates: Vec<PlayerSet>,
+    /// The number of identical universes represented by each role distribution.
+    weight: u64,
+    /// All operations other than role and faction reveals, in order.
+    history: Vec<Op>,
+    /// The sample budget and the seed used for sampling, if set using `set_sample_budget`.
+    #[serde(default)]
+    sampling: Option<(usize, u64)>,
+    /// The number of operations applied so far, used to draw a different sample each time a new one is needed.
+    #[serde(default)]
+    version: u64,
+    /// `None` if queries go through all allowed role distributions.
+    #[serde(skip)]
+    sample: OnceLock<Option<Sample>>,
+    #[serde(skip)]
+    summary: OnceLock<Summary>,
+}
+
+/// The universes sampled by a `LazyMultiverse`.
+#[derive(Debug, Clone)]
+struct Sample {
+    /// The sampled universes which are still possible, sorted by role distribution. Universes may have been sampled more than once.
+    universes: Vec<Universe>,
+    /// The number of universes when the sample was drawn.
+    drawn: usize,
+}
+
+impl LazyMultiverse {
+    /// The number of players who have each of the `roles`.
+    fn role_counts(&self) -> Vec<usize> {
+        self.roles.iter().map(|&(_, count)| count).collect()
+    }
+
+    /// Returns the sampled universes, drawing a sample if necessary, or `None` if queries go through all allowed role distributions.
+    fn sampled_universes(&self) -> Option<&[Universe]> {
+        self.sample.get_or_init(|| {
+            let (budget, seed) = self.sampling?;
+            if Distributions::new(&self.candidates).count(0, &self.role_counts()) <= budget as f64 { return None }
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            rng.set_stream(self.version);
+            let mut universes = self.draw(&mut rng, budget);
+            // if every attempt was discarded, go through all role distributions rather than reporting a paradox
+            if universes.is_empty() { return None }
+            universes.sort_by(|universe1, universe2| universe1.roles.cmp(&universe2.roles));
+            Some(Sample { drawn: universes.len(), universes })
+        }).as_ref().map(|sample| &*sample.universes)
+    }
+
+    /// Draws role distributions uniformly at random among those allowed by the candidates, and returns the universes for those which the history doesn't rule out.
+    fn draw<R: Rng>(&self, rng: &mut R, attempts: usize) -> Vec<Universe> {
+        let mut distributions = Distributions::new(&self.candidates);
+        let role_counts = self.role_counts();
+        if distributions.count(0, &role_counts) == 0.0 { return Vec::default() }
+        let mut universes = Vec::default();
+        for _ in 0..attempts {
+            let mut remaining = role_counts.clone();
+            let mut roles = Vec::with_capacity(self.num_players());
+            for player_idx in 0..self.num_players() {
+                // choose each role with probability proportional to the number of ways to distribute the remaining roles
+                let mut target = rng.gen::<f64>() * distributions.count(player_idx, &remaining);
+                let mut chosen = None;
+                for role_idx in 0..self.roles.len() {
+                    if remaining[role_idx] == 0 || !self.candidates[role_idx].contains(player_idx) { continue }
+                    remaining[role_idx] -= 1;
+                    let count = distributions.count(player_idx + 1, &remaining);
+                    remaining[role_idx] += 1;
+                    if count == 0.0 { continue }
+                    chosen = Some(role_idx);
+                    if target < count { break }
+                    target -= count;
+                }
+                let role_idx = chosen.expect("no role left for player");
+                remaining[role_idx] -= 1;
+                roles.push(self.roles[role_idx].0);
+            }
+            let mut universe = Universe::from_packed(roles.into_boxed_slice(), self.weight);
+            if self.history.iter().all(|&op| universe.apply(op)) {
+                universes.push(universe);
+            }
+        }
+        universes
+    }
+
+    /// Calls the given function with every possible universe whose role distribution starts with the given roles and is allowed by the candidates, sorted by role distribution.
+    fn for_each_with_prefix<F: FnMut(&Universe)>(&self, prefix: &mut Vec<u8>, remaining: &mut [usize], f: &mut F) {
+        let player_idx = prefix.len();
+        if player_idx == self.num_players() {
+            let mut universe = Universe::from_packed(prefix.clone().into_boxed_slice(), self.weight);
+            if self.history.iter().all(|&op| universe.apply(op)) {
+                f(&universe);
+            }
+            return
+        }
+        for role_idx in 0..self.roles.len() {
+            if remaining[role_idx] == 0 || !self.candidates[role_idx].contains(player_idx) { continue }
+            remaining[role_idx] -= 1;
+            // a quick check which rules out most role distributions that can't be completed, without counting them
+            if self.candidates.iter().zip(&*remaining).all(|(candidates, &count)| candidates.0.checked_shr(player_idx as u32 + 1).unwrap_or(0).count_ones() as usize >= count) {
+                prefix.push(self.roles[role_idx].0);
+                self.for_each_with_prefix(prefix, remaining, f);
+                prefix.pop();
+            }
+            remaining[role_idx] += 1;
+        }
+    }
+
+    /// Returns the cached summary of the possible universes, computing it if necessary.
+    fn summary(&self) -> &Summary {
+        let num_players = self.num_players();
+        self.summary.get_or_init(|| self.fold(|| Summary::new(num_players), Summary::add, Summary::merge))
+    }
+
+    /// Removes players from the candidates for roles they don't have in any possible universe, if this is known from a pass over all allowed role distributions.
+    fn narrow_candidates(&mut self) {
+        let (Some(summary), Some(None)) = (self.summary.get(), self.sample.get()) else { return };
+        for (&(role, _), candidates) in self.roles.iter().zip(&mut self.candidates) {
+            for (player_idx, roles) in summary.counts.roles.iter().enumerate() {
+                if !roles.contains_key(&role) {
+                    candidates.remove(player_idx);
+                }
+            }
+        }
+    }
+}
+
+impl MultiverseBackend for LazyMultiverse {
+    fn new(roles: Vec<Role>, num_players: usize) -> LazyMultiverse {
+        let mut roles = roles.into_iter().map(Role::pack).collect::<Vec<_>>();
+        roles.resize(num_players, Role::Villager.pack());
+        roles.sort_unstable();
+        // universes which differ only in the order in which identical roles have been assigned are merged
+        let weight = roles.chunk_by(|r1, r2| r1 == r2)
+            .filter(|chunk| chunk[0] != Role::Villager.pack())
+            .map(|chunk| (1..=chunk.len() as u64).product::<u64>())
+            .product();
+        let roles = roles.chunk_by(|r1, r2| r1 == r2).map(|chunk| (chunk[0], chunk.len())).collect::<Vec<_>>();
+        LazyMultiverse {
+            candidates: vec![PlayerSet::all(num_players); roles.len()],
+            roles, weight,
+            history: Vec::default(),
+            sampling: None,
+            version: 0,
+            sample: OnceLock::default(),
+            summary: OnceLock::default(),
+        }
+    }
+
+    fn num_players(&self) -> usize {
+        self.roles.iter().map(|&(_, count)| count).sum()
+    }
+
+    fn apply(&mut self, op: Op) {
+        self.narrow_candidates();
+        self.summary = OnceLock::default();
+        self.version += 1;
+        match op {
+            Op::Collapse(player_idx, role) => for (&(iter_role, _), candidates) in self.roles.iter().zip(&mut self.candidates) {
+                if iter_role != role.pack() {
+                    candidates.remove(player_idx);
+                }
+            },
+            Op::CollapseFaction(player_idx, faction) => for (&(role, _), candidates) in self.roles.iter().zip(&mut self.candidates) {
+                if Role::unpack(role).default_faction() != faction {
+                    candidates.remove(player_idx);
+                }
+            },
+            Op::NotHunter(player_idx) => for (&(role, _), candidates) in self.roles.iter().zip(&mut self.candidates) {
+                if role == Role::Hunter.pack() {
+                    candidates.remove(player_idx);
+                }
+            },
+            op => { self.history.push(op); }
+        }
+        if let Some(Some(mut sample)) = self.sample.take() {
+            sample.universes.retain_mut(|universe| universe.apply(op));
+            let budget = self.sampling.map_or(0, |(budget, _)| budget);
+            // keep the sample unless too many of its universes have been ruled out or there are now few enough role distributions to go through all of them
+            if sample.universes.len() * 2 >= sample.drawn && Distributions::new(&self.candidates).count(0, &self.role_counts()) > budget as f64 {
+                self.sample = OnceLock::from(Some(sample));
+            }
+        } else {
+            self.sample = OnceLock::default();
+        }
+    }
+
+    fn set_sample_budget(&mut self, budget: usize, seed: u64) {
+        self.sampling = Some((budget, seed));
+        self.sample = OnceLock::default();
+        self.summary = OnceLock::default();
+    }
+
+    fn alive_set(&self) -> PlayerSet {
+        self.summary().counts.alive
+    }
+
+    fn total_weight(&self) -> u64 {
+        self.summary().counts.total
+    }
+
+    fn role(&self, player_idx: usize) -> Option<Role> {
+        match self.summary().counts.roles[player_idx].keys().collect::<Vec<_>>()[..] {
+            [&role] => Some(Role::unpack(role)),
+            _ => None,
+        }
+    }
+
+    fn faction(&self, player_idx: usize) -> Option<Faction> {
+        self.summary().faction(player_idx)
+    }
+
+    fn role_alive(&self, role: Role) -> bool {
+        let role = role.pack();
+        let summary = self.summary();
+        summary.counts.roles.iter().zip(&summary.alive_roles).any(|(roles, alive_roles)|
+            alive_roles.contains(&role) || roles.len() > 1 && roles.contains_key(&role)
+        )
+    }
+
+    fn game_over(&self, night: bool) -> bool {
+        let summary = self.summary();
+        if night { summary.game_over_night } else { summary.game_over_day }
+    }
+
+    fn probability_table(&self) -> Result<ProbabilityTable, GameError> {
+        let summary = self.summary();
+        if summary.counts.total == 0 { return Err(GameError::Paradox) }
+        let total = summary.counts.total as f64;
+        Ok(summary.counts.roles.iter().enumerate().map(|(player_idx, roles)| {
+            match summary.faction(player_idx) {
+                Some(faction) if !summary.counts.alive.contains(player_idx) => Err(faction),
+                _ => {
+                    let faction_weight = |faction| roles.iter().filter(|&(&role, _)| Role::unpack(role).default_faction() == faction).map(|(_, weight)| weight).sum::<u64>() as f64;
+                    Ok((
+                        faction_weight(Faction::Village) / total,
+                        faction_weight(Faction::Werewolves) / total,
+                        summary.dead[player_idx] as f64 / total
+                    ))
+                }
+            }
+        }).collect())
+    }
+
+    fn role_probability_table(&self) -> Vec<Result<Vec<(Role, f64)>, Role>> {
+        self.summary().counts.role_probability_table()
+    }
+
+    fn for_each<F: FnMut(&Universe)>(&self, mut f: F) {
+        if let Some(universes) = self.sampled_universes() {
+            universes.iter().for_each(f);
+        } else {
+            self.for_each_with_prefix(&mut Vec::with_capacity(self.num_players()), &mut self.role_counts(), &mut f);
+        }
+    }
+
+    /// If the `parallel` feature is enabled, the sampled universes are split up, or the role distributions are split up by the role of the first player.
+    #[cfg(feature = "parallel")]
+    fn fold<T, I, F, C>(&self, identity: I, fold: F, combine: C) -> T
+    where T: Send, I: Fn() -> T + Sync + Send, F: Fn(T, &Universe) -> T + Sync + Send, C: Fn(T, T) -> T + Sync + Send {
+        if let Some(universes) = self.sampled_universes() {
+            return universes.par_iter().fold(&identity, &fold).reduce(&identity, &combine)
+        }
+        if self.num_players() == 0 {
+            let mut acc = Some(identity());
+            self.for_each(|universe| acc = acc.take().map(|acc| fold(acc, universe)));
+            return acc.expect("accumulator lost during fold")
+        }
+        let role_counts = self.role_counts();
+        (0..self.roles.len()).into_par_iter().map(|role_idx| {
+            let mut acc = Some(identity());
+            if role_counts[role_idx] > 0 && self.candidates[role_idx].contains(0) {
+                let mut remaining = role_counts.clone();
+                remaining[role_idx] -= 1;
+                self.for_each_with_prefix(&mut vec![self.roles[role_idx].0], &mut remaining, &mut |universe| acc = acc.take().map(|acc| fold(acc, universe)));
+            }
+            acc.expect("accumulator lost during fold")
+        }).reduce(&identity, &combine)
+    }
+
+    /// Uses rejection sampling: for each attempt, a role distribution is drawn uniformly at random among those allowed by the candidates and the history is replayed. If this makes the universe impossible, the attempt is discarded.
+    fn sample<R: Rng>(&self, rng: &mut R, budget: usize) -> Vec<Universe> {
+        self.draw(rng, budget)
+    }
+}
+
+/// Counts the role distributions allowed by the candidates of a `LazyMultiverse`, remembering intermediate results.
+///
+/// Counts are floating-point numbers since they can exceed the range of any integer type in large games.
+struct Distributions<'a> {
+    candidates: &'a [PlayerSet],
+    counts: HashMap<Vec<usize>, f64>,
+}
+
+impl<'a> Distributions<'a> {
+    fn new(candidates: &'a [PlayerSet]) -> Distributions<'a> {
+        Distributions { candidates, counts: HashMap::default() }
+    }
+
+    /// The number of ways to distribute the remaining roles, given as the number of players who have each role, to the players starting at the given one.
+    fn count(&mut self, player_idx: usize, remaining: &[usize]) -> f64 {
+        if remaining.iter().all(|&count| count == 0) { return 1.0 }
+        // the remaining roles determine the player
+        if let Some(&count) = self.counts.get(remaining) { return count }
+        let mut after = remaining.to_owned();
+        let mut count = 0.0;
+        for role_idx in 0..remaining.len() {
+            if remaining[role_idx] == 0 || !self.candidates[role_idx].contains(player_idx) { continue }
+            after[role_idx] -= 1;
+            count += self.count(player_idx + 1, &after);
+            after[role_idx] += 1;
+        }
+        self.counts.insert(remaining.to_owned(), count);
+        count
+    }
+}
@@ -4,43 +4,91 @@ use {
     std::{
         collections::HashSet,
         hash::Hash,
+        mem,
     },
     rand::prelude::*,
+    rand_chacha::ChaCha20Rng,
     serde::{
         Deserialize,
         Serialize,
+        de::DeserializeOwned,
     },
     crate::{
         game::{
+            Estimate,
             Faction,
+            KillRule,
+            MAX_PLAYERS,
             Multiverse,
+            MultiverseBackend,
             NightAction,
             NightActionResult,
+            Op,
+            PlayerSet,
+            ProbabilityTable,
+            Reveal,
+            RevealRule,
             Role,
         },
         util::QwwIteratorExt as _,
     },
 };
 
-/// The minimum number of players required to start a game.
+/// The minimum number of players required to start a game, unless configured otherwise using `Rules`.
 pub const MIN_PLAYERS: usize = 3;
 
+/// Configurable game rules, passed to `Signups::start`.
+///
+/// The default rules are those of the original game. When deserializing, missing fields are set to their default values.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Rules {
+    /// The minimum number of players required to start a game. Defaults to `MIN_PLAYERS`.
+    pub min_players: usize,
+    /// Whether a healer may heal the same player two nights in a row. Defaults to `false`.
+    pub repeat_heals: bool,
+    /// Whether a healer may heal themselves. Defaults to `true`.
+    pub self_heals: bool,
+    /// Whether the town may decide not to lynch anyone. Defaults to `true`.
+    pub no_lynch: bool,
+    /// Whether the werewolves must choose a player to kill each night. If this is `false`, werewolves may decide not to kill, and missing werewolf kills are not randomized. Defaults to `true`.
+    pub mandatory_werewolf_kill: bool,
+    /// What is revealed about players when they die. Defaults to `RevealRule::Role`.
+    pub reveal: RevealRule,
+    /// Which werewolves kill each night. Defaults to `KillRule::Dominant`.
+    pub kill_rule: KillRule,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            min_players: MIN_PLAYERS,
+            repeat_heals: false,
+            self_heals: true,
+            no_lynch: true,
+            mandatory_werewolf_kill: true,
+            reveal: RevealRule::default(),
+            kill_rule: KillRule::default(),
+        }
+    }
+}
+
 /// This enum represents the state of the game. Each variant contains relevant methods to observe or progress the game state, refer to their documentation for details.
 ///
-/// The type parameter `P` is used for player identifiers.
+/// The type parameter `P` is used for player identifiers. The type parameter `M` determines how the set of possible universes is represented, see `MultiverseBackend`.
 #[derive(Debug, Serialize, Deserialize)]
-pub enum State<P: Eq + Hash> {
+pub enum State<P: Eq + Hash, M = Multiverse> {
     /// A game which has not been started. The moderator may sign up players, or start the game.
     Signups(Signups<P>),
     /// A running game which is currently in night time, waiting for the players' night actions.
-    Night(Night<P>),
+    Night(Night<P, M>),
     /// A running game which is currently in day time, waiting for the result of the lynch vote.
-    Day(Day<P>),
+    Day(Day<P, M>),
     /// A completed game.
     Complete(Complete<P>)
 }
 
-impl<P: Eq + Hash> State<P> {
+impl<P: Eq + Hash, M: MultiverseBackend> State<P, M> {
     /// If the game is onging, returns the set of players which are still alive in at least one possible universe.
     pub fn alive(&self) -> Option<HashSet<&P>> {
         match *self {
@@ -63,12 +111,25 @@ impl<P: Eq + Hash> State<P> {
         }
     }
 
+    /// Returns what has been publicly revealed about the given player according to `Rules::reveal`, if they are dead in all universes.
+    ///
+    /// Returns `None` if the given player may still be alive, or is not in this game.
+    pub fn reveal(&self, player: &P) -> Option<Reveal> {
+        match *self {
+            State::Signups(_) | State::Complete(_) => None,
+            State::Night(ref night) => reveal(&night.secret_ids, &night.multiverse, night.rules.reveal, player),
+            State::Day(ref day) => reveal(&day.secret_ids, &day.multiverse, day.rules.reveal, player)
+        }
+    }
+
     /// Returns the role of the given player, if that role is unambiguous.
+    ///
+    /// Returns `None` if the given player is not in this game.
     pub fn role(&self, player: &P) -> Option<Role> {
         match *self {
             State::Signups(_) => None,
-            State::Night(ref night) => night.multiverse.role(night.secret_ids.iter().position(|iter_player| player == iter_player).expect("no such player")),
-            State::Day(ref day) => day.multiverse.role(day.secret_ids.iter().position(|iter_player| player == iter_player).expect("no such player")),
+            State::Night(ref night) => night.multiverse.role(secret_id(&night.secret_ids, player).ok()?),
+            State::Day(ref day) => day.multiverse.role(secret_id(&day.secret_ids, player).ok()?),
             State::Complete(_) => None
         }
     }
@@ -82,18 +143,48 @@ impl<P: Eq + Hash> State<P> {
             State::Complete(_) => None
         }
     }
+
+    /// If the game is ongoing, allows the multiverse to answer queries from a random sample of universes, see `MultiverseBackend::set_sample_budget`.
+    pub fn set_sample_budget(&mut self, budget: usize) {
+        match *self {
+            State::Signups(_) | State::Complete(_) => {}
+            State::Night(Night { ref mut multiverse, ref rng, .. }) | State::Day(Day { ref mut multiverse, ref rng, .. }) => {
+                // use a separate stream so the game's future random decisions are not affected
+                let mut rng = rng.clone();
+                rng.set_stream(2);
+                multiverse.set_sample_budget(budget, rng.gen());
+            }
+        }
+    }
 }
 
-impl<P: Eq + Hash> Default for State<P> {
-    fn default() -> State<P> {
+impl<P: Eq + Hash + Serialize, M: MultiverseBackend> State<P, M> {
+    /// Serializes the game state as JSON, for example to save a game in progress.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+}
+
+impl<P: Eq + Hash + DeserializeOwned, M: MultiverseBackend> State<P, M> {
+    /// Deserializes a game state previously serialized using `to_json`.
+    ///
+    /// The game state must have been serialized using the same multiverse representation.
+    pub fn from_json(json: &str) -> serde_json::Result<State<P, M>> {
+        serde_json::from_str(json)
+    }
+}
+
+impl<P: Eq + Hash, M> Default for State<P, M> {
+    fn default() -> State<P, M> {
         State::Signups(Signups::default())
     }
 }
 
 /// A game which has not been started. The moderator may sign up players, or start the game.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Signups<P: Eq + Hash> {
-    player_names: HashSet<P>
+    player_names: Vec<P>,
+    seed: u64
 }
 
 /// The possible errors returned by `Signups::start`.
@@ -115,14 +206,72 @@ pub enum StartGameError {
         /// But this many roles have been given.
         found: usize,
     },
+    /// More players than the multiverse can represent have signed up.
+    #[error("failed to start game: too many players ({max} allowed, {found} signed up)")]
+    TooManyPlayers {
+        /// At most this many players can take part in a game.
+        max: usize,
+        /// But this many have signed up.
+        found: usize,
+    },
+    /// More than one cupid has been specified. All lovers are linked to each other, so a second cupid could only add to the first cupid's group of lovers.
+    #[error("failed to start game: at most one cupid is allowed ({found} given)")]
+    MultipleCupids {
+        /// This many cupids have been given.
+        found: usize,
+    },
+}
+
+/// The possible errors returned by state transitions of a running game, such as `Night::resolve_tar` or `Day::lynch`.
+///
+/// A state transition which returns an error consumes the game state, so the game cannot be continued. Methods like `Day::can_lynch` can be used to check input beforehand.
+#[derive(Debug, thiserror::Error)]
+pub enum GameError {
+    /// The game could not be started.
+    #[error(transparent)]
+    Start(#[from] StartGameError),
+    /// A player who is not part of this game has been given.
+    #[error("no such player in this game")]
+    UnknownPlayer,
+    /// A player who is dead in all universes has been chosen as the target of an action which requires a living target.
+    #[error("the target is already dead")]
+    DeadTarget,
+    /// An action which is not allowed by the rules has been chosen.
+    #[error("illegal action: {0}")]
+    IllegalAction(&'static str),
+    /// There are no possible universes left. This indicates a bug in the game logic or a corrupted save file.
+    #[error("paradox: no possible universes left")]
+    Paradox,
 }
 
 impl<P: Eq + Hash> Signups<P> {
+    /// Creates an empty signup list for a game whose random decisions are all derived from the given seed.
+    ///
+    /// Given the same seed, the same signups in the same order, and the same player input, the game will play out identically.
+    ///
+    /// `Signups::default()` is equivalent to this with a random seed.
+    pub fn with_seed(seed: u64) -> Signups<P> {
+        Signups {
+            player_names: Vec::default(),
+            seed
+        }
+    }
+
+    /// Returns the seed from which all random decisions in this game will be derived.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     /// Sign up a player. The `player_id` must be unique.
     ///
     /// Returns `true` if the player has been successfully signed up, or `false` if a player with that ID already exists.
     pub fn sign_up(&mut self, player_id: P) -> bool {
-        self.player_names.insert(player_id)
+        if self.player_names.contains(&player_id) {
+            false
+        } else {
+            self.player_names.push(player_id);
+            true
+        }
     }
 
     /// Returns `true` if the given player is already signed up.
@@ -135,27 +284,49 @@ impl<P: Eq + Hash> Signups<P> {
         self.player_names.len()
     }
 
+    /// Returns the players that have been signed up so far, in signup order.
+    pub fn players(&self) -> &[P] {
+        &self.player_names
+    }
+
     /// Removes a player from the signups.
     ///
     /// Returns `true` if the player was previously signed up.
     pub fn remove_player(&mut self, player_id: &P) -> bool {
-        self.player_names.remove(player_id)
+        let len = self.player_names.len();
+        self.player_names.retain(|iter_player| iter_player != player_id);
+        self.player_names.len() < len
     }
 
     /// Start the game.
     ///
-    /// If fewer roles than players are given, a number of Villagers equal to the difference will be added.
-    pub fn start(self, roles: Vec<Role>) -> Result<State<P>, StartGameError> {
+    /// If fewer roles than players are given, a number of Villagers equal to the difference will be added. The game state keeps the rules for the rest of the game.
+    pub fn start(self, roles: Vec<Role>, rules: Rules) -> Result<State<P>, StartGameError> {
+        self.start_with_backend(roles, rules)
+    }
+
+    /// Start the game, using the given representation for the set of possible universes.
+    ///
+    /// For example, `LazyMultiverse` can be used for games with too many players or roles to store every universe. Otherwise, this is the same as `start`.
+    pub fn start_with_backend<M: MultiverseBackend>(self, roles: Vec<Role>, rules: Rules) -> Result<State<P, M>, StartGameError> {
         let num_players = self.num_players();
-        if num_players < MIN_PLAYERS {
-            return Err(StartGameError::NotEnoughPlayers { required: MIN_PLAYERS, found: num_players });
+        if num_players < rules.min_players {
+            return Err(StartGameError::NotEnoughPlayers { required: rules.min_players, found: num_players });
+        }
+        if num_players > MAX_PLAYERS {
+            return Err(StartGameError::TooManyPlayers { max: MAX_PLAYERS, found: num_players });
         }
         if num_players < roles.len() {
             return Err(StartGameError::RolesCount { required: num_players, found: roles.len() });
         }
-        let Signups { player_names } = self;
-        let mut secret_ids = player_names.into_iter().collect::<Vec<_>>();
-        secret_ids.shuffle(&mut thread_rng());
+        let num_cupids = roles.iter().filter(|&&role| role == Role::Cupid).count();
+        if num_cupids > 1 {
+            return Err(StartGameError::MultipleCupids { found: num_cupids });
+        }
+        let Signups { player_names, seed } = self;
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let mut secret_ids = player_names;
+        secret_ids.shuffle(&mut rng);
         let roles = roles.into_iter()
             .filter(|&role| role != Role::Villager)
             .fold((0, Vec::default()), |(mut num_ww, mut roles), role| {
@@ -167,72 +338,107 @@ impl<P: Eq + Hash> Signups<P> {
                 }
                 (num_ww, roles)
             }).1;
-        let multiverse = Multiverse::new(roles, num_players);
+        let has_masons = roles.contains(&Role::Mason);
+        let mut multiverse = M::new(roles, num_players);
+        // tell each player who the other masons are in case they are a mason, like an investigation at the start of the game
+        let mut mason_partners = vec![None; num_players];
+        if has_masons {
+            for (player_idx, partners) in mason_partners.iter_mut().enumerate() {
+                if let Some(mason_universe) = multiverse.choose(&mut rng, |universe| universe.role(player_idx) == Role::Mason) {
+                    let others = (0..num_players)
+                        .filter(|&iter_idx| iter_idx != player_idx && mason_universe.role(iter_idx) == Role::Mason)
+                        .collect();
+                    *partners = Some(others);
+                    multiverse.apply(Op::Masons(player_idx, others));
+                }
+            }
+        }
         // check for game-ending conditions
         if multiverse.game_over(false) {
-            return Ok(State::Complete(Complete::new(secret_ids, multiverse)));
+            return Ok(State::Complete(Complete::new(secret_ids, multiverse, &mut rng)));
         }
         Ok(State::Night(Night {
-            secret_ids, multiverse,
-            last_heals: vec![None; num_players]
+            secret_ids, multiverse, rng,
+            last_heals: vec![None; num_players],
+            first_night: true,
+            mason_partners,
+            rules
         }))
     }
 }
 
 impl<P: Eq + Hash> Default for Signups<P> {
     fn default() -> Signups<P> {
-        Signups {
-            player_names: HashSet::default()
-        }
+        Signups::with_seed(thread_rng().gen())
     }
 }
 
-impl<P: Eq + Hash> From<Signups<P>> for State<P> {
-    fn from(state: Signups<P>) -> State<P> {
+impl<P: Eq + Hash, M> From<Signups<P>> for State<P, M> {
+    fn from(state: Signups<P>) -> State<P, M> {
         State::Signups(state)
     }
 }
 
+/// Used by `Night::resolve_tar` to ask for night actions, and for the targets of hunters who die during the night.
+///
+/// Night actions are asked from all living players, so being asked does not reveal anything about a player's role. Each method which is given a list of possible targets should return one of them. Any other choice is treated the same as not using the action, except for compulsory actions (Cupid's link, the serial killer's kill, and the werewolves' kill if the rules make it mandatory), where a random choice is made instead.
+pub trait NightChoices<P> {
+    /// Asks a player which two players to link as Cupid. This is only asked on the first night.
+    fn lovers(&self, player: &P, possible_targets: Vec<&P>) -> (P, P);
+    /// Asks a player whom to heal as the healer.
+    fn heal_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P>;
+    /// Asks a player whom to guard as the bodyguard.
+    fn guard_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P>;
+    /// Asks a player whom to investigate as the detective.
+    fn investigation_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P>;
+    /// Asks a player whom to investigate as the seer.
+    fn seer_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P>;
+    /// Asks a player which dead player to contact as the medium. This is only asked if any players are dead.
+    fn medium_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P>;
+    /// Asks a player whom to kill as a werewolf. Which werewolves' choices take effect depends on the kill rule.
+    ///
+    /// If `mandatory` is `true`, this should return one of the possible targets. Otherwise, it may also return `None` to not kill anyone.
+    fn werewolf_kill_target(&self, player: &P, possible_targets: Vec<&P>, mandatory: bool) -> Option<P>;
+    /// Asks a player whom to kill as a serial killer.
+    fn serial_killer_target(&self, player: &P, possible_targets: Vec<&P>) -> P;
+    /// Asks a player whether to save the werewolf victims as the witch.
+    fn witch_save(&self, player: &P) -> bool;
+    /// Asks a player whom to poison as the witch.
+    fn poison_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P>;
+    /// Asks a hunter who has died whom to shoot.
+    fn hunter_target(&self, player: &P, possible_targets: Vec<&P>) -> Option<P>;
+}
+
 /// A running game which is currently in night time, waiting for the players' night actions.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Night<P: Eq + Hash> {
+pub struct Night<P: Eq + Hash, M = Multiverse> {
     secret_ids: Vec<P>,
     last_heals: Vec<Option<usize>>,
-    multiverse: Multiverse
+    multiverse: M,
+    rng: ChaCha20Rng,
+    #[serde(default)]
+    first_night: bool,
+    #[serde(default)]
+    mason_partners: Vec<Option<PlayerSet>>,
+    #[serde(default)]
+    rules: Rules
 }
 
-impl<P: Eq + Hash> Night<P> {
+impl<P: Eq + Hash, M: MultiverseBackend> Night<P, M> {
     /// Returns `true` if no more night actions can be submitted.
     pub fn actions_complete(&self, night_actions: &[NightAction<P>]) -> bool {
-        true && // required to make the if blocks parse as expressions for some reason
-        if self.multiverse.role_alive(Role::Healer) {
-            // all healer actions
-            self.multiverse.alive().into_iter().all(|player_idx|
-                night_actions.into_iter().any(|action| if let NightAction::Heal(ref src, _) = *action {
-                    &self.secret_ids[player_idx] == src
-                } else {
-                    false
-                })
-            )
-        } else { true } &&
-        if self.multiverse.role_alive(Role::Detective) {
-            // all detective investigations
-            self.multiverse.alive().into_iter().all(|player_idx|
-                night_actions.into_iter().any(|action| if let NightAction::Investigate(ref src, _) = *action {
-                    &self.secret_ids[player_idx] == src
-                } else {
-                    false
-                })
-            )
-        } else { true } &&
-        // all werewolf kills
-        self.multiverse.alive().into_iter().all(|player_idx|
-            night_actions.into_iter().any(|action| if let NightAction::Kill(ref src, _) = *action {
-                &self.secret_ids[player_idx] == src
-            } else {
-                false
-            })
-        )
+        let alive = self.multiverse.alive();
+        let all_submitted = |is_kind: fn(&NightAction<P>) -> bool| alive.iter().all(|&player_idx| submitted(night_actions, &self.secret_ids[player_idx], is_kind));
+        (!(self.first_night && self.multiverse.role_alive(Role::Cupid)) || all_submitted(|action| matches!(action, NightAction::Link(..)))) &&
+        (!self.multiverse.role_alive(Role::Healer) || all_submitted(|action| matches!(action, NightAction::Heal(..)))) &&
+        (!self.multiverse.role_alive(Role::Bodyguard) || all_submitted(|action| matches!(action, NightAction::Guard(..)))) &&
+        (!self.multiverse.role_alive(Role::Detective) || all_submitted(|action| matches!(action, NightAction::Investigate(..)))) &&
+        (!self.multiverse.role_alive(Role::Seer) || all_submitted(|action| matches!(action, NightAction::See(..)))) &&
+        // mediums are only asked if anyone is dead
+        (!(self.multiverse.role_alive(Role::Medium) && alive.len() < self.secret_ids.len()) || all_submitted(|action| matches!(action, NightAction::Seance(..)))) &&
+        (!self.multiverse.role_alive(Role::Witch) || (all_submitted(|action| matches!(action, NightAction::Save(..))) && all_submitted(|action| matches!(action, NightAction::Poison(..))))) &&
+        (!self.multiverse.role_alive(Role::SerialKiller) || all_submitted(|action| matches!(action, NightAction::SerialKill(..)))) &&
+        all_submitted(|action| matches!(action, NightAction::Kill(..)))
     }
 
     /// Returns the set of players which are still alive in at least one possible universe.
@@ -242,160 +448,286 @@ impl<P: Eq + Hash> Night<P> {
             .collect()
     }
 
-    /// Advance the game state to the next day using natural action resolution.
+    /// For each player who may be a mason, returns the other masons that player has been told about at the start of the game.
+    ///
+    /// This is only available during the first night, and empty afterwards.
+    pub fn mason_partners(&self) -> Vec<(&P, Vec<&P>)> {
+        self.mason_partners.iter()
+            .enumerate()
+            .filter_map(|(player_idx, partners)| partners.map(|partners| (
+                &self.secret_ids[player_idx],
+                partners.iter().map(|partner_idx| &self.secret_ids[partner_idx]).collect()
+            )))
+            .collect()
+    }
+
+    /// Checks whether the given night action is allowed, for example so it can be rejected when a player submits it.
     ///
-    /// Takes night actions submitted by the players and processes them. Any mandatory night actions not submitted will be randomized.
-    pub fn resolve_nar(mut self, night_actions: &[NightAction<P>]) -> State<P> {
-        // reset kill lists
-        for universe in self.multiverse.iter_mut() {
-            universe.heals = Vec::default();
-            universe.kills = Vec::default();
+    /// Note that `resolve_nar` does not require this check, as it ignores illegal actions.
+    pub fn check_night_action(&self, action: &NightAction<P>) -> Result<(), GameError> {
+        let src_idx = secret_id(&self.secret_ids, action.src())?;
+        let alive = self.multiverse.alive_set();
+        if !alive.contains(src_idx) { return Err(GameError::IllegalAction("dead players cannot perform night actions")) }
+        match *action {
+            NightAction::Heal(_, ref tgt) => {
+                let tgt_idx = secret_id(&self.secret_ids, tgt)?;
+                if !alive.contains(tgt_idx) { return Err(GameError::DeadTarget) }
+                if !self.rules.repeat_heals && self.last_heals[src_idx] == Some(tgt_idx) { return Err(GameError::IllegalAction("cannot heal the same player two nights in a row")) }
+                if !self.rules.self_heals && src_idx == tgt_idx { return Err(GameError::IllegalAction("healers cannot heal themselves")) }
+            }
+            NightAction::Guard(_, ref tgt) => {
+                let tgt_idx = secret_id(&self.secret_ids, tgt)?;
+                if !alive.contains(tgt_idx) { return Err(GameError::DeadTarget) }
+                if tgt_idx == src_idx { return Err(GameError::IllegalAction("bodyguards cannot guard themselves")) }
+            }
+            NightAction::Investigate(_, ref tgt) | NightAction::See(_, ref tgt) => { secret_id(&self.secret_ids, tgt)?; }
+            NightAction::Seance(_, ref tgt) => if alive.contains(secret_id(&self.secret_ids, tgt)?) { return Err(GameError::IllegalAction("mediums can only contact dead players")) },
+            NightAction::Kill(_, Some(ref tgt)) | NightAction::SerialKill(_, ref tgt) | NightAction::Poison(_, Some(ref tgt)) => if !alive.contains(secret_id(&self.secret_ids, tgt)?) { return Err(GameError::DeadTarget) },
+            NightAction::Kill(_, None) => if self.rules.mandatory_werewolf_kill { return Err(GameError::IllegalAction("the werewolves must kill")) },
+            NightAction::Link(_, ref lover1, ref lover2) => {
+                if !self.first_night { return Err(GameError::IllegalAction("lovers can only be chosen on the first night")) }
+                let lover1_idx = secret_id(&self.secret_ids, lover1)?;
+                let lover2_idx = secret_id(&self.secret_ids, lover2)?;
+                if !alive.contains(lover1_idx) || !alive.contains(lover2_idx) { return Err(GameError::DeadTarget) }
+                if lover1_idx == lover2_idx { return Err(GameError::IllegalAction("the lovers must be two different players")) }
+            }
+            NightAction::Save(_, _) | NightAction::Poison(_, None) => {}
         }
+        Ok(())
+    }
+
+    /// Advance the game state to the next day using natural action resolution.
+    ///
+    /// Takes night actions submitted by the players and processes them. Illegal night actions (see `check_night_action`) are ignored. Any mandatory night actions not submitted will be randomized.
+    ///
+    /// If a hunter dies during the night, `choose_hunter_target` is called to ask them whom to shoot. It should return one of the players it is given as possible targets, or `None`. Any other choice is treated the same as `None`.
+    pub fn resolve_nar<T: Fn(&P, Vec<&P>) -> Option<P>>(mut self, night_actions: &[NightAction<P>], choose_hunter_target: T) -> Result<State<P, M>, GameError> {
+        let alive = self.multiverse.alive_set();
         // resolve night actions
         let mut current_heals = vec![None; self.secret_ids.len()];
-        let mut night_action_results = vec![None; self.secret_ids.len()];
-        for action in self.sanitized_night_actions(night_actions) { // healer/detective/werewolf setup does not have any dependencies, so resolve in submitted order
+        let mut night_action_results = vec![Vec::default(); self.secret_ids.len()];
+        let mut late_actions = Vec::default();
+        let mut night_actions = self.sanitized_night_actions(night_actions);
+        // heals and bodyguards must be in place before any kills, so resolve them first
+        night_actions.sort_by_key(|action| !matches!(action, NightAction::Heal(_, _) | NightAction::Guard(_, _)));
+        for action in night_actions { // the sort is stable, so apart from that, actions are resolved in submitted order
             match action {
                 NightAction::Heal(src_idx, tgt_idx) => {
                     current_heals[src_idx] = Some(tgt_idx);
-                    for universe in self.multiverse.iter_mut() {
-                        let can_heal = universe.roles[src_idx] == Role::Healer &&
-                        universe.alive[src_idx] &&
-                        universe.alive[tgt_idx];
-                        if can_heal {
-                            universe.heal(tgt_idx);
-                        }
-                    }
+                    self.multiverse.apply(Op::Heal(src_idx, tgt_idx));
+                }
+                NightAction::Guard(src_idx, tgt_idx) => {
+                    self.multiverse.apply(Op::Guard(src_idx, tgt_idx));
                 }
                 NightAction::Investigate(src_idx, tgt_idx) => {
-                    let investigated_faction = if let Some(investigation_universe) = self.multiverse.iter()
-                        .filter(|universe| universe.roles[src_idx] == Role::Detective) // player must be detective,
-                        .filter(|universe| universe.alive[src_idx]) // and detective must be alive
-                        .rand(&mut thread_rng())
-                    {
-                        investigation_universe.factions[tgt_idx]
+                    let investigated_faction = if let Some(investigation_universe) = self.multiverse.choose(&mut self.rng, |universe|
+                        universe.role(src_idx) == Role::Detective // player must be detective,
+                        && universe.is_alive(src_idx) // and detective must be alive
+                    ) {
+                        investigation_universe.faction(tgt_idx)
                     } else {
                         continue;
                     };
-                    if night_action_results[src_idx].is_some() { unimplemented!("multiple night action results for one player"); }
-                    night_action_results[src_idx] = Some(NightActionResult::Investigation(tgt_idx, investigated_faction));
-                    self.multiverse = self.multiverse.into_iter()
-                        .filter(|universe| !(
-                            universe.roles[src_idx] == Role::Detective &&
-                            universe.alive[src_idx] &&
-                            universe.factions[tgt_idx] != investigated_faction
-                        ))
-                        .collect();
+                    night_action_results[src_idx].push(NightActionResult::Investigation(tgt_idx, investigated_faction));
+                    self.multiverse.apply(Op::Investigate(src_idx, tgt_idx, investigated_faction));
                 }
-                NightAction::Kill(src_idx, tgt_idx) => {
-                    for universe in self.multiverse.iter_mut() {
-                        let can_kill = if let Role::Werewolf(werewolf_rank) = universe.roles[src_idx] {
-                            universe.roles
-                                .iter()
-                                .enumerate()
-                                .all(|(i, role)| if let &Role::Werewolf(cmp_rank) = role {
-                                    cmp_rank >= werewolf_rank || !universe.alive[i]
-                                } else {
-                                    true
-                                })
-                        } else {
-                            false
-                        } &&
-                        universe.alive[src_idx] &&
-                        universe.alive[tgt_idx];
-                        if can_kill {
-                            universe.kill(tgt_idx, true);
-                        }
-                    }
+                NightAction::See(src_idx, tgt_idx) => {
+                    let seen_role = if let Some(vision_universe) = self.multiverse.choose(&mut self.rng, |universe|
+                        universe.role(src_idx) == Role::Seer // player must be seer,
+                        && universe.is_alive(src_idx) // and seer must be alive
+                    ) {
+                        vision_universe.role(tgt_idx)
+                    } else {
+                        continue;
+                    };
+                    night_action_results[src_idx].push(NightActionResult::Vision(tgt_idx, seen_role));
+                    self.multiverse.apply(Op::See(src_idx, tgt_idx, seen_role));
+                }
+                NightAction::Seance(src_idx, tgt_idx) => {
+                    let cause = if let Some(cause) = self.multiverse.choose(&mut self.rng, |universe|
+                        universe.role(src_idx) == Role::Medium // player must be medium,
+                        && universe.is_alive(src_idx) // and medium must be alive
+                    ).and_then(|seance_universe| seance_universe.cause_of_death(tgt_idx)) {
+                        cause
+                    } else {
+                        continue;
+                    };
+                    night_action_results[src_idx].push(NightActionResult::Seance(tgt_idx, cause));
+                    self.multiverse.apply(Op::Seance(src_idx, tgt_idx, cause));
+                }
+                NightAction::Kill(src_idx, tgt_idx) => if let Some(tgt_idx) = tgt_idx {
+                    self.multiverse.apply(Op::Kill(src_idx, tgt_idx));
+                },
+                NightAction::Link(src_idx, lover1_idx, lover2_idx) => {
+                    self.multiverse.apply(Op::Link(src_idx, lover1_idx, lover2_idx));
+                }
+                NightAction::Save(src_idx, save) => if save {
+                    late_actions.push(Op::Save(src_idx));
+                },
+                NightAction::Poison(src_idx, tgt_idx) => if let Some(tgt_idx) = tgt_idx {
+                    late_actions.push(Op::Poison(src_idx, tgt_idx));
+                },
+                NightAction::SerialKill(src_idx, tgt_idx) => {
+                    late_actions.push(Op::SerialKill(src_idx, tgt_idx));
                 }
             }
         }
-        // kill all players on the death list
-        for universe in self.multiverse.iter_mut() {
-            for &player_id in &universe.kills {
-                universe.alive[player_id] = false;
-            }
+        self.multiverse.apply(Op::WerewolfKill(self.rules.kill_rule));
+        // witch saves only affect the werewolf kill, so resolve them after it
+        for op in late_actions {
+            self.multiverse.apply(op);
         }
-        self.multiverse.collapse_roles();
+        // kill all players on the death list
+        self.multiverse.apply(Op::EndNight);
+        self.multiverse.collapse_roles(&mut self.rng, self.rules.reveal)?;
+        hunter_shots(&self.secret_ids, &mut self.multiverse, &mut self.rng, alive, self.rules.reveal, choose_hunter_target)?;
         // check for game-ending conditions
         if self.multiverse.game_over(false) {
-            return State::Complete(Complete::new(self.secret_ids, self.multiverse));
+            return Ok(State::Complete(Complete::new(self.secret_ids, self.multiverse, &mut self.rng)));
         }
-        State::Day(Day {
+        Ok(State::Day(Day {
             secret_ids: self.secret_ids,
             multiverse: self.multiverse,
+            rng: self.rng,
             night_action_results,
-            last_heals: current_heals
-        })
+            last_heals: current_heals,
+            rules: self.rules
+        }))
     }
 
     /// Advance the game state to the next day using temporal action resolution.
     ///
-    /// To do this, all night actions have to be submitted. The functions passed as arguments are used to ask for night actions.
-    pub fn resolve_tar<H, I, W>(mut self, choose_heal_target: H, choose_investigation_target: I, choose_werewolf_kill_target: W) -> State<P> where
-    H: Fn(&P, Vec<&P>) -> Option<P>,
-    I: Fn(&P, Vec<&P>) -> Option<P>,
-    W: Fn(&P, Vec<&P>) -> P {
-        // reset kill lists
-        for universe in self.multiverse.iter_mut() {
-            universe.heals = Vec::default();
-            universe.kills = Vec::default();
+    /// To do this, all night actions have to be submitted. The given `NightChoices` are used to ask for night actions, and for the targets of hunters who die during the night. Illegal choices do not cause an error, see `NightChoices` for how they are handled.
+    pub fn resolve_tar<C: NightChoices<P>>(mut self, choices: &C) -> Result<State<P, M>, GameError> {
+        let alive = self.multiverse.alive_set();
+        // cupid actions
+        if self.first_night && self.multiverse.role_alive(Role::Cupid) {
+            let mut linkable = {
+                let ids = &self.secret_ids;
+                self.multiverse.alive().into_iter()
+                    .map(|iter_id| &ids[iter_id])
+                    .collect::<Vec<_>>()
+            };
+            linkable.shuffle(&mut self.rng);
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
+                if !self.multiverse.alive().contains(&player_id) { continue; }
+                let (lover1, lover2) = choices.lovers(player, linkable.clone());
+                let lovers = match (valid_target(&self.secret_ids, &linkable, Some(lover1)), valid_target(&self.secret_ids, &linkable, Some(lover2))) {
+                    (Some(lover1_id), Some(lover2_id)) if lover1_id != lover2_id => Some((lover1_id, lover2_id)),
+                    // like a missing link with `resolve_nar`, an illegal choice is replaced with a random one
+                    _ => if let [lover1_id, lover2_id] = self.multiverse.alive().choose_multiple(&mut self.rng, 2).copied().collect::<Vec<_>>()[..] { Some((lover1_id, lover2_id)) } else { None },
+                };
+                if let Some((lover1_id, lover2_id)) = lovers {
+                    self.multiverse.apply(Op::Link(player_id, lover1_id, lover2_id));
+                }
+            }
         }
         // healer actions
         let mut current_heals = vec![None; self.secret_ids.len()];
         if self.multiverse.role_alive(Role::Healer) {
-            for (player_id, player) in shuffled_players(&self.secret_ids) {
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
                 if !self.multiverse.alive().contains(&player_id) { continue; }
                 let mut healable = {
                     let ids = &self.secret_ids;
                     self.multiverse.alive().into_iter()
-                        .filter(|&iter_id| self.last_heals[player_id].map_or(true, |heal_id| heal_id != iter_id))
+                        .filter(|&iter_id| self.rules.repeat_heals || self.last_heals[player_id] != Some(iter_id))
+                        .filter(|&iter_id| self.rules.self_heals || iter_id != player_id)
                         .map(|iter_id| &ids[iter_id])
                         .collect::<Vec<_>>()
                 };
-                healable.shuffle(&mut thread_rng());
-                if let Some(target) = choose_heal_target(player, healable) {
-                    let target_id = self.secret_ids.iter().position(|iter_player| &target == iter_player).expect("healed player not in game");
+                healable.shuffle(&mut self.rng);
+                if let Some(target_id) = valid_target(&self.secret_ids, &healable, choices.heal_target(player, healable.clone())) {
                     current_heals[player_id] = Some(target_id);
-                    for universe in self.multiverse.iter_mut() {
-                        let can_heal = universe.roles[player_id] == Role::Healer &&
-                        universe.alive[player_id] &&
-                        universe.alive[target_id];
-                        if can_heal {
-                            universe.heal(target_id);
-                        }
-                    }
+                    self.multiverse.apply(Op::Heal(player_id, target_id));
+                }
+            }
+        }
+        // bodyguard actions
+        if self.multiverse.role_alive(Role::Bodyguard) {
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
+                if !self.multiverse.alive().contains(&player_id) { continue; }
+                let mut guardable = {
+                    let ids = &self.secret_ids;
+                    self.multiverse.alive().into_iter()
+                        .filter(|&iter_id| iter_id != player_id)
+                        .map(|iter_id| &ids[iter_id])
+                        .collect::<Vec<_>>()
+                };
+                guardable.shuffle(&mut self.rng);
+                if let Some(target_id) = valid_target(&self.secret_ids, &guardable, choices.guard_target(player, guardable.clone())) {
+                    self.multiverse.apply(Op::Guard(player_id, target_id));
                 }
             }
         }
         // detective actions
-        let mut night_action_results = vec![None; self.secret_ids.len()];
+        let mut night_action_results = vec![Vec::default(); self.secret_ids.len()];
         if self.multiverse.role_alive(Role::Detective) {
-            let all_players = shuffled_players(&self.secret_ids).into_iter()
+            let all_players = shuffled_players(&self.secret_ids, &mut self.rng).into_iter()
                 .map(|(_, player)| player)
                 .collect::<Vec<_>>();
-            for (player_id, player) in shuffled_players(&self.secret_ids) {
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
                 if !self.multiverse.alive().contains(&player_id) { continue; }
-                if let Some(target) = choose_investigation_target(player, all_players.clone()) {
-                    let target_id = self.secret_ids.iter().position(|iter_player| &target == iter_player).expect("investigated player not in game");
-                    let investigated_faction = if let Some(investigation_universe) = self.multiverse.iter()
-                        .filter(|universe| universe.roles[player_id] == Role::Detective) // player must be detective,
-                        .filter(|universe| universe.alive[player_id]) // and detective must be alive
-                        .rand(&mut thread_rng())
-                    {
-                        investigation_universe.factions[target_id]
+                if let Some(target_id) = valid_target(&self.secret_ids, &all_players, choices.investigation_target(player, all_players.clone())) {
+                    let investigated_faction = if let Some(investigation_universe) = self.multiverse.choose(&mut self.rng, |universe|
+                        universe.role(player_id) == Role::Detective // player must be detective,
+                        && universe.is_alive(player_id) // and detective must be alive
+                    ) {
+                        investigation_universe.faction(target_id)
                     } else {
                         continue;
                     };
-                    if night_action_results[player_id].is_some() { unimplemented!("multiple night action results for one player"); }
-                    night_action_results[player_id] = Some(NightActionResult::Investigation(target_id, investigated_faction));
-                    self.multiverse = self.multiverse.into_iter()
-                        .filter(|universe| !(
-                            universe.roles[player_id] == Role::Detective &&
-                            universe.alive[player_id] &&
-                            universe.factions[target_id] != investigated_faction
-                        ))
-                        .collect();
+                    night_action_results[player_id].push(NightActionResult::Investigation(target_id, investigated_faction));
+                    self.multiverse.apply(Op::Investigate(player_id, target_id, investigated_faction));
+                }
+            }
+        }
+        // seer actions
+        if self.multiverse.role_alive(Role::Seer) {
+            let all_players = shuffled_players(&self.secret_ids, &mut self.rng).into_iter()
+                .map(|(_, player)| player)
+                .collect::<Vec<_>>();
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
+                if !self.multiverse.alive().contains(&player_id) { continue; }
+                if let Some(target_id) = valid_target(&self.secret_ids, &all_players, choices.seer_target(player, all_players.clone())) {
+                    let seen_role = if let Some(vision_universe) = self.multiverse.choose(&mut self.rng, |universe|
+                        universe.role(player_id) == Role::Seer // player must be seer,
+                        && universe.is_alive(player_id) // and seer must be alive
+                    ) {
+                        vision_universe.role(target_id)
+                    } else {
+                        continue;
+                    };
+                    night_action_results[player_id].push(NightActionResult::Vision(target_id, seen_role));
+                    self.multiverse.apply(Op::See(player_id, target_id, seen_role));
+                }
+            }
+        }
+        // medium actions
+        if self.multiverse.role_alive(Role::Medium) {
+            let mut dead = {
+                let alive = self.multiverse.alive_set();
+                self.secret_ids.iter()
+                    .enumerate()
+                    .filter(|&(iter_id, _)| !alive.contains(iter_id))
+                    .map(|(_, player)| player)
+                    .collect::<Vec<_>>()
+            };
+            dead.shuffle(&mut self.rng);
+            if !dead.is_empty() {
+                for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
+                    if !self.multiverse.alive().contains(&player_id) { continue; }
+                    if let Some(target_id) = valid_target(&self.secret_ids, &dead, choices.medium_target(player, dead.clone())) {
+                        let cause = if let Some(cause) = self.multiverse.choose(&mut self.rng, |universe|
+                            universe.role(player_id) == Role::Medium // player must be medium,
+                            && universe.is_alive(player_id) // and medium must be alive
+                        ).and_then(|seance_universe| seance_universe.cause_of_death(target_id)) {
+                            cause
+                        } else {
+                            continue;
+                        };
+                        night_action_results[player_id].push(NightActionResult::Seance(target_id, cause));
+                        self.multiverse.apply(Op::Seance(player_id, target_id, cause));
+                    }
                 }
             }
         }
@@ -407,90 +739,104 @@ impl<P: Eq + Hash> Night<P> {
                     .map(|iter_id| &ids[iter_id])
                     .collect::<Vec<_>>()
             };
-            alive.shuffle(&mut thread_rng());
-            for (player_id, player) in shuffled_players(&self.secret_ids) {
+            alive.shuffle(&mut self.rng);
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
                 if !self.multiverse.alive().contains(&player_id) { continue; }
-                let target = choose_werewolf_kill_target(player, alive.clone());
-                let target_id = self.secret_ids.iter().position(|iter_player| &target == iter_player).expect("killed player not in game");
-                for universe in self.multiverse.iter_mut() {
-                    let can_kill = if let Role::Werewolf(werewolf_rank) = universe.roles[player_id] {
-                        universe.roles
-                            .iter()
-                            .enumerate()
-                            .all(|(i, role)| if let &Role::Werewolf(cmp_rank) = role {
-                                cmp_rank >= werewolf_rank || !universe.alive[i]
-                            } else {
-                                true
-                            })
-                    } else {
-                        false
-                    } &&
-                    universe.alive[player_id] &&
-                    universe.alive[target_id];
-                    if can_kill {
-                        universe.kill(target_id, true);
+                let target = choices.werewolf_kill_target(player, alive.clone(), self.rules.mandatory_werewolf_kill);
+                if let Some(target_id) = valid_target(&self.secret_ids, &alive, target) {
+                    self.multiverse.apply(Op::Kill(player_id, target_id));
+                } else if self.rules.mandatory_werewolf_kill {
+                    // like a missing kill with `resolve_nar`, an illegal or missing choice is replaced with a random one
+                    if let Some(&target_id) = self.multiverse.alive().choose(&mut self.rng) {
+                        self.multiverse.apply(Op::Kill(player_id, target_id));
                     }
                 }
             }
+            self.multiverse.apply(Op::WerewolfKill(self.rules.kill_rule));
         }
-        // kill all players on the death list
-        for universe in self.multiverse.iter_mut() {
-            for &player_id in &universe.kills {
-                universe.alive[player_id] = false;
+        // witch actions
+        if self.multiverse.role_alive(Role::Witch) {
+            let mut alive = {
+                let ids = &self.secret_ids;
+                self.multiverse.alive().into_iter()
+                    .map(|iter_id| &ids[iter_id])
+                    .collect::<Vec<_>>()
+            };
+            alive.shuffle(&mut self.rng);
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
+                if !self.multiverse.alive().contains(&player_id) { continue; }
+                if choices.witch_save(player) {
+                    self.multiverse.apply(Op::Save(player_id));
+                }
+                if let Some(target_id) = valid_target(&self.secret_ids, &alive, choices.poison_target(player, alive.clone())) {
+                    self.multiverse.apply(Op::Poison(player_id, target_id));
+                }
             }
         }
-        self.multiverse.collapse_roles();
+        // serial killer kills
+        if self.multiverse.role_alive(Role::SerialKiller) {
+            let mut alive = {
+                let ids = &self.secret_ids;
+                self.multiverse.alive().into_iter()
+                    .map(|iter_id| &ids[iter_id])
+                    .collect::<Vec<_>>()
+            };
+            alive.shuffle(&mut self.rng);
+            for (player_id, player) in shuffled_players(&self.secret_ids, &mut self.rng) {
+                if !self.multiverse.alive().contains(&player_id) { continue; }
+                let target = choices.serial_killer_target(player, alive.clone());
+                // like a missing kill with `resolve_nar`, an illegal choice is replaced with a random one
+                if let Some(target_id) = valid_target(&self.secret_ids, &alive, Some(target)).or_else(|| self.multiverse.alive().choose(&mut self.rng).copied()) {
+                    self.multiverse.apply(Op::SerialKill(player_id, target_id));
+                }
+            }
+        }
+        // kill all players on the death list
+        self.multiverse.apply(Op::EndNight);
+        self.multiverse.collapse_roles(&mut self.rng, self.rules.reveal)?;
+        hunter_shots(&self.secret_ids, &mut self.multiverse, &mut self.rng, alive, self.rules.reveal, |p, targets| choices.hunter_target(p, targets))?;
         // check for game-ending conditions
         if self.multiverse.game_over(false) {
-            return State::Complete(Complete::new(self.secret_ids, self.multiverse));
+            return Ok(State::Complete(Complete::new(self.secret_ids, self.multiverse, &mut self.rng)));
         }
-        State::Day(Day {
+        Ok(State::Day(Day {
             secret_ids: self.secret_ids,
             multiverse: self.multiverse,
+            rng: self.rng,
             night_action_results,
-            last_heals: current_heals
-        })
+            last_heals: current_heals,
+            rules: self.rules
+        }))
     }
 
     /// Remove illegal actions, add missing compulsory actions.
-    fn sanitized_night_actions(&self, night_actions: &[NightAction<P>]) -> Vec<NightAction<usize>> {
+    fn sanitized_night_actions(&mut self, night_actions: &[NightAction<P>]) -> Vec<NightAction<usize>> {
         let mut result = Vec::default();
-        // remove illegal actions
+        // remove illegal actions, and all but the first action of each kind submitted by each player
         for action in night_actions {
-            match *action {
-                NightAction::Heal(ref src, ref tgt) => {
-                    let src_idx = if let Some(idx) = self.secret_ids.iter().position(|iter_player| src == iter_player) { idx } else { continue; };
-                    let tgt_idx = if let Some(idx) = self.secret_ids.iter().position(|iter_player| tgt == iter_player) { idx } else { continue; };
-                    let alive = self.alive();
-                    if !alive.contains(&src) || !alive.contains(&tgt) { continue; }
-                    if result.iter().any(|action| if let NightAction::Heal(ref iter_src, _) = *action { *iter_src == src_idx } else { false }) { continue; }
-                    if self.last_heals[src_idx] != Some(tgt_idx) {
-                        result.push(NightAction::Heal(src_idx, tgt_idx));
-                    }
-                }
-                NightAction::Investigate(ref src, ref tgt) => {
-                    let src_idx = if let Some(idx) = self.secret_ids.iter().position(|iter_player| src == iter_player) { idx } else { continue; };
-                    let tgt_idx = if let Some(idx) = self.secret_ids.iter().position(|iter_player| tgt == iter_player) { idx } else { continue; };
-                    if !self.alive().contains(&src) { continue; }
-                    if result.iter().any(|action| if let NightAction::Investigate(ref iter_src, _) = *action { *iter_src == src_idx } else { false }) { continue; }
-                    result.push(NightAction::Investigate(src_idx, tgt_idx));
-                }
-                NightAction::Kill(ref src, ref tgt) => {
-                    let src_idx = if let Some(idx) = self.secret_ids.iter().position(|iter_player| src == iter_player) { idx } else { continue; };
-                    let tgt_idx = if let Some(idx) = self.secret_ids.iter().position(|iter_player| tgt == iter_player) { idx } else { continue; };
-                    let alive = self.alive();
-                    if !alive.contains(&src) || !alive.contains(&tgt) { continue; }
-                    if result.iter().any(|action| if let NightAction::Kill(ref iter_src, _) = *action { *iter_src == src_idx } else { false }) { continue; }
-                    result.push(NightAction::Kill(src_idx, tgt_idx));
-                }
-            }
+            if self.check_night_action(action).is_err() { continue; }
+            let Ok(action) = night_action_ids(&self.secret_ids, action) else { continue; };
+            if result.iter().any(|iter_action| mem::discriminant(iter_action) == mem::discriminant(&action) && iter_action.src() == action.src()) { continue; }
+            result.push(action);
         }
         // add missing compulsory actions
-        for secret_id in 0..self.secret_ids.len() {
+        for secret_id in self.multiverse.alive() {
             // werewolf kill
-            if self.multiverse.alive().contains(&secret_id) && !result.iter().any(|action| if let &NightAction::Kill(src_idx, _) = action { src_idx == secret_id } else { false }) {
-                if let Some(random_id) = self.multiverse.alive().into_iter().rand(&mut thread_rng()) {
-                    result.push(NightAction::Kill(secret_id, random_id));
+            if self.rules.mandatory_werewolf_kill && !submitted(&result, &secret_id, |action| matches!(action, NightAction::Kill(..))) {
+                if let Some(random_id) = self.multiverse.alive().into_iter().rand(&mut self.rng) {
+                    result.push(NightAction::Kill(secret_id, Some(random_id)));
+                }
+            }
+            // serial killer kill
+            if self.multiverse.role_alive(Role::SerialKiller) && !submitted(&result, &secret_id, |action| matches!(action, NightAction::SerialKill(..))) {
+                if let Some(random_id) = self.multiverse.alive().into_iter().rand(&mut self.rng) {
+                    result.push(NightAction::SerialKill(secret_id, random_id));
+                }
+            }
+            // cupid link
+            if self.first_night && self.multiverse.role_alive(Role::Cupid) && !submitted(&result, &secret_id, |action| matches!(action, NightAction::Link(..))) {
+                if let [lover1_id, lover2_id] = self.multiverse.alive().choose_multiple(&mut self.rng, 2).copied().collect::<Vec<_>>()[..] {
+                    result.push(NightAction::Link(secret_id, lover1_id, lover2_id));
                 }
             }
         }
@@ -503,22 +849,25 @@ impl<P: Eq + Hash> Night<P> {
     }
 }
 
-impl<P: Eq + Hash> From<Night<P>> for State<P> {
-    fn from(state: Night<P>) -> State<P> {
+impl<P: Eq + Hash, M> From<Night<P, M>> for State<P, M> {
+    fn from(state: Night<P, M>) -> State<P, M> {
         State::Night(state)
     }
 }
 
 /// A running game which is currently in day time, waiting for the result of the lynch vote.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct Day<P: Eq + Hash> {
+pub struct Day<P: Eq + Hash, M = Multiverse> {
     secret_ids: Vec<P>,
-    multiverse: Multiverse,
-    night_action_results: Vec<Option<NightActionResult<usize>>>,
-    last_heals: Vec<Option<usize>>
+    multiverse: M,
+    rng: ChaCha20Rng,
+    night_action_results: Vec<Vec<NightActionResult<usize>>>,
+    last_heals: Vec<Option<usize>>,
+    #[serde(default)]
+    rules: Rules
 }
 
-impl<P: Eq + Hash> Day<P> {
+impl<P: Eq + Hash, M: MultiverseBackend> Day<P, M> {
     /// Returns the set of players which are still alive in at least one possible universe.
     pub fn alive(&self) -> HashSet<&P> {
         self.multiverse.alive().into_iter()
@@ -529,77 +878,115 @@ impl<P: Eq + Hash> Day<P> {
     /// Contains results of the last night's night actions.
     pub fn night_action_results(&self) -> Vec<(&P, NightActionResult<&P>)> {
         let mut list = Vec::default();
-        for (player_idx, result) in self.night_action_results.iter().enumerate() {
-            if let &Some(result) = result {
+        for (player_idx, results) in self.night_action_results.iter().enumerate() {
+            for &result in results {
                 list.push((&self.secret_ids[player_idx], result.index(&self.secret_ids)));
             }
         }
         list
     }
 
-    /// Tests whether `lynch` will panic.
+    /// Tests whether `lynch` will succeed.
     pub fn can_lynch(&self, lynch_target: &P) -> bool {
-        match self.secret_ids.iter().position(|iter_player| lynch_target == iter_player) {
-            Some(lynch_id) => self.multiverse.alive().contains(&lynch_id),
-            None => false
+        self.check_lynch(lynch_target).is_ok()
+    }
+
+    /// Returns the secret player ID of the lynch target, or the reason why they cannot be lynched.
+    fn check_lynch(&self, lynch_target: &P) -> Result<usize, GameError> {
+        let lynch_id = secret_id(&self.secret_ids, lynch_target)?;
+        if self.multiverse.alive_set().contains(lynch_id) {
+            Ok(lynch_id)
+        } else {
+            Err(GameError::DeadTarget)
         }
     }
 
     /// Advance the game state to the next night by lynching a player.
     ///
+    /// If a hunter dies, `choose_hunter_target` is called to ask them whom to shoot. It should return one of the players it is given as possible targets, or `None`. Any other choice is treated the same as `None`.
+    ///
     /// See also `no_lynch`.
-    pub fn lynch(mut self, lynch_target: P) -> State<P> {
-        let lynch_id = self.secret_ids.iter().position(|iter_player| &lynch_target == iter_player).expect("lynched player not in game");
+    pub fn lynch<T: Fn(&P, Vec<&P>) -> Option<P>>(mut self, lynch_target: P, choose_hunter_target: T) -> Result<State<P, M>, GameError> {
+        let lynch_id = self.check_lynch(&lynch_target)?;
+        let alive = self.multiverse.alive_set();
         // eliminate impossible gamestates (where the player to be killed by the vote is already dead), then kill voted player
-        self.multiverse = self.multiverse.into_iter()
-            .filter(|universe| universe.alive[lynch_id])
-            .map(|mut universe| {
-                universe.kill(lynch_id, false);
-                universe
-            })
-            .collect();
-        self.multiverse.collapse_roles();
+        self.multiverse.apply(Op::Lynch(lynch_id));
+        self.multiverse.collapse_roles(&mut self.rng, self.rules.reveal)?;
+        hunter_shots(&self.secret_ids, &mut self.multiverse, &mut self.rng, alive, self.rules.reveal, choose_hunter_target)?;
         // check for game-ending conditions
         if self.multiverse.game_over(false) {
-            return State::Complete(Complete::new(self.secret_ids, self.multiverse));
+            return Ok(State::Complete(Complete::new(self.secret_ids, self.multiverse, &mut self.rng)));
         }
-        State::Night(Night {
+        Ok(State::Night(Night {
             secret_ids: self.secret_ids,
             multiverse: self.multiverse,
-            last_heals: self.last_heals
-        })
+            rng: self.rng,
+            last_heals: self.last_heals,
+            first_night: false,
+            mason_partners: Vec::default(),
+            rules: self.rules
+        }))
+    }
+
+    /// Tests whether `no_lynch` is allowed by the rules.
+    pub fn can_skip_lynch(&self) -> bool {
+        self.rules.no_lynch
     }
 
     /// Advance the game state to the next night without lynching any players.
     ///
-    /// See also `lynch`.
-    pub fn no_lynch(self) -> State<P> {
+    /// Returns an error if the rules require a lynch, see `can_skip_lynch`. See also `lynch`.
+    pub fn no_lynch(mut self) -> Result<State<P, M>, GameError> {
+        if !self.rules.no_lynch { return Err(GameError::IllegalAction("the rules require a lynch")) }
         // check for game-ending conditions
         if self.multiverse.game_over(false) {
-            return State::Complete(Complete::new(self.secret_ids, self.multiverse));
+            return Ok(State::Complete(Complete::new(self.secret_ids, self.multiverse, &mut self.rng)));
         }
-        State::Night(Night {
+        Ok(State::Night(Night {
             secret_ids: self.secret_ids,
             multiverse: self.multiverse,
-            last_heals: self.last_heals
-        })
+            rng: self.rng,
+            last_heals: self.last_heals,
+            first_night: false,
+            mason_partners: Vec::default(),
+            rules: self.rules
+        }))
     }
 
     /// Produces the anonymized probability table shown to players at the start of the day.
     ///
     /// For each player in `secret_id` order, returns that player's probability of being town, of being a werewolf, and of being dead, if that player can still be alive. Otherwise, returns that player's faction.
-    pub fn probability_table(&self) -> Vec<Result<(f64, f64, f64), Faction>> {
+    ///
+    /// Returns an error if there are no possible universes left, which can happen if the rules don't reveal anything about dead players.
+    pub fn probability_table(&self) -> Result<ProbabilityTable, GameError> {
         self.multiverse.probability_table()
     }
 
+    /// Produces a more detailed version of the probability table, for groups playing with full information.
+    ///
+    /// For each player in `secret_id` order, returns that player's probability of having each role they may have, if that player can still be alive. Otherwise, returns that player's role. Werewolves of different ranks are listed separately.
+    pub fn role_probability_table(&self) -> Vec<Result<Vec<(Role, f64)>, Role>> {
+        self.multiverse.role_probability_table()
+    }
+
+    /// Estimates the probability table from a random sample of universes, for games where computing the exact `probability_table` would take too long.
+    ///
+    /// The budget is the number of universes to sample. Each probability is given with a 95% confidence interval. The random choices made for sampling do not affect the rest of the game.
+    pub fn approximate_probability_table(&self, budget: usize) -> Vec<Result<(Estimate, Estimate, Estimate), Faction>> {
+        // use a separate stream so the sample is independent of the game's future random decisions
+        let mut rng = self.rng.clone();
+        rng.set_stream(1);
+        self.multiverse.approximate_probability_table(&mut rng, budget)
+    }
+
     /// Returns the player list, sorted by secret player ID.
     pub fn secret_ids(&self) -> &[P] {
         &self.secret_ids
     }
 }
 
-impl<P: Eq + Hash> From<Day<P>> for State<P> {
-    fn from(state: Day<P>) -> State<P> {
+impl<P: Eq + Hash, M> From<Day<P, M>> for State<P, M> {
+    fn from(state: Day<P, M>) -> State<P, M> {
         State::Day(state)
     }
 }
@@ -612,11 +999,11 @@ pub struct Complete<P: Eq + Hash> {
 }
 
 impl<P: Eq + Hash> Complete<P> {
-    fn new(secret_ids: Vec<P>, multiverse: Multiverse) -> Complete<P> {
-        if let Some(universe) = multiverse.into_iter().rand(&mut thread_rng()) {
+    fn new<M: MultiverseBackend, R: Rng>(secret_ids: Vec<P>, multiverse: M, rng: &mut R) -> Complete<P> {
+        if let Some(universe) = multiverse.choose(rng, |_| true) {
             let winners = secret_ids.into_iter()
                 .enumerate()
-                .filter(|&(player_idx, _)| universe.factions[player_idx].wincon(&universe))
+                .filter(|&(player_idx, _)| universe.wins(player_idx))
                 .map(|(_, name)| name)
                 .collect();
             Complete { winners }
@@ -628,14 +1015,96 @@ impl<P: Eq + Hash> Complete<P> {
     }
 }
 
-impl<P: Eq + Hash> From<Complete<P>> for State<P> {
-    fn from(state: Complete<P>) -> State<P> {
+impl<P: Eq + Hash, M> From<Complete<P>> for State<P, M> {
+    fn from(state: Complete<P>) -> State<P, M> {
         State::Complete(state)
     }
 }
+
+/// Looks up the secret player ID of the given player.
+fn secret_id<P: Eq>(secret_ids: &[P], player: &P) -> Result<usize, GameError> {
+    secret_ids.iter().position(|iter_player| player == iter_player).ok_or(GameError::UnknownPlayer)
+}
+
+/// Replaces each player in a night action with their secret player ID.
+fn night_action_ids<P: Eq>(secret_ids: &[P], action: &NightAction<P>) -> Result<NightAction<usize>, GameError> {
+    let id = |player| secret_id(secret_ids, player);
+    Ok(match *action {
+        NightAction::Heal(ref src, ref tgt) => NightAction::Heal(id(src)?, id(tgt)?),
+        NightAction::Guard(ref src, ref tgt) => NightAction::Guard(id(src)?, id(tgt)?),
+        NightAction::Investigate(ref src, ref tgt) => NightAction::Investigate(id(src)?, id(tgt)?),
+        NightAction::See(ref src, ref tgt) => NightAction::See(id(src)?, id(tgt)?),
+        NightAction::Seance(ref src, ref tgt) => NightAction::Seance(id(src)?, id(tgt)?),
+        NightAction::Kill(ref src, ref tgt) => NightAction::Kill(id(src)?, tgt.as_ref().map(id).transpose()?),
+        NightAction::SerialKill(ref src, ref tgt) => NightAction::SerialKill(id(src)?, id(tgt)?),
+        NightAction::Link(ref src, ref lover1, ref lover2) => NightAction::Link(id(src)?, id(lover1)?, id(lover2)?),
+        NightAction::Save(ref src, save) => NightAction::Save(id(src)?, save),
+        NightAction::Poison(ref src, ref tgt) => NightAction::Poison(id(src)?, tgt.as_ref().map(id).transpose()?),
+    })
+}
+
+/// Returns `true` if `actions` contain an action of the kind checked by `is_kind` which was submitted by `src`.
+fn submitted<P: PartialEq>(actions: &[NightAction<P>], src: &P, is_kind: fn(&NightAction<P>) -> bool) -> bool {
+    actions.iter().any(|action| is_kind(action) && action.src() == src)
+}
+
+/// Returns the secret player ID of the chosen target if it is one of the possible targets. Illegal choices are treated the same as not choosing anyone.
+fn valid_target<P: Eq>(secret_ids: &[P], possible_targets: &[&P], target: Option<P>) -> Option<usize> {
+    let target = target?;
+    if !possible_targets.contains(&&target) { return None }
+    secret_id(secret_ids, &target).ok()
+}
+
+/// Lets each hunter who has died since `alive` was computed shoot a player, repeating as long as this kills more hunters.
+///
+/// Which of the dead players were hunters is decided using a randomly chosen universe, like when roles are revealed, and their roles collapse to hunter. Only those players are asked whom to shoot, and universes in which any of the other dead players are a hunter are eliminated. If the roles of dead players are revealed, this matches the revealed roles.
+fn hunter_shots<P: Eq, M: MultiverseBackend, R: Rng, T: Fn(&P, Vec<&P>) -> Option<P>>(secret_ids: &[P], multiverse: &mut M, rng: &mut R, mut alive: PlayerSet, reveal: RevealRule, choose_hunter_target: T) -> Result<(), GameError> {
+    loop {
+        let new_alive = multiverse.alive_set();
+        let died = PlayerSet(alive.0 & !new_alive.0);
+        alive = new_alive;
+        // only choose a universe if one of the dead players may be a hunter, so games without hunters are not affected
+        let may_be_hunter = multiverse.fold(
+            || false,
+            |may_be_hunter, universe| may_be_hunter || died.iter().any(|player_idx| universe.role(player_idx) == Role::Hunter),
+            |may_be_hunter1, may_be_hunter2| may_be_hunter1 || may_be_hunter2,
+        );
+        if !may_be_hunter { return Ok(()) }
+        let hunter_universe = multiverse.choose(rng, |_| true).ok_or(GameError::Paradox)?;
+        let (hunters, not_hunters) = died.iter().partition::<Vec<_>, _>(|&player_idx| hunter_universe.role(player_idx) == Role::Hunter);
+        for &hunter_idx in &hunters {
+            multiverse.apply(Op::Collapse(hunter_idx, Role::Hunter));
+        }
+        // the other dead players are not asked, so they can't have been hunters
+        for player_idx in not_hunters {
+            multiverse.apply(Op::NotHunter(player_idx));
+        }
+        for hunter_idx in hunters {
+            let mut targets = multiverse.alive_set().iter().map(|player_idx| &secret_ids[player_idx]).collect::<Vec<_>>();
+            if targets.is_empty() { break }
+            targets.shuffle(rng);
+            if let Some(target_id) = valid_target(secret_ids, &targets, choose_hunter_target(&secret_ids[hunter_idx], targets.clone())) {
+                multiverse.apply(Op::Shoot(target_id));
+                multiverse.collapse_roles(rng, reveal)?;
+            }
+        }
+    }
+}
+
+/// Returns what has been publicly revealed about the given player according to the reveal rule, if they are dead in all universes.
+fn reveal<P: Eq, M: MultiverseBackend>(secret_ids: &[P], multiverse: &M, reveal: RevealRule, player: &P) -> Option<Reveal> {
+    let player_idx = secret_id(secret_ids, player).ok()?;
+    if multiverse.alive_set().contains(player_idx) { return None }
+    match reveal {
+        RevealRule::Role => multiverse.role(player_idx).map(Reveal::Role),
+        RevealRule::Faction => multiverse.faction(player_idx).map(Reveal::Faction),
+        RevealRule::Nothing => Some(Reveal::Nothing),
+    }
+}
+
 /// Iterate over all players in a random order.
-fn shuffled_players<P>(secret_ids: &[P]) -> Vec<(usize, &P)> {
+fn shuffled_players<'a, P, R: Rng>(secret_ids: &'a [P], rng: &mut R) -> Vec<(usize, &'a P)> {
     let mut result = secret_ids.iter().enumerate().collect::<Vec<_>>();
-    result.shuffle(&mut thread_rng());
+    result.shuffle(rng);
     result
 }
@@ -2,19 +2,13 @@
 
 use {
     std::{
-        collections::HashMap,
         fmt,
-        mem,
-        slice,
         str::FromStr,
-        vec,
     },
-    rand::thread_rng,
     serde::{
         Deserialize,
         Serialize,
     },
-    crate::util::QwwIteratorExt as _,
 };
 
 /// The faction (also called party) of a player determines their goal. It is usually derived from the role.
@@ -24,25 +18,48 @@ pub enum Faction {
     Werewolves,
     /// The player wants to eliminate all threats to the village.
     Village,
+    /// The player wants to be the last one alive.
+    SerialKiller,
+    /// The player wants to be lynched.
+    Tanner,
+    /// The player has been linked to another player by Cupid, and wants to survive with them. This is not the default faction of any role, lovers can win either with their own faction or as lovers.
+    Lovers,
 }
 
 impl Faction {
+    /// Returns `true` if the village has to eliminate all players of this faction to win.
+    fn threatens_village(&self) -> bool {
+        match *self {
+            Faction::Werewolves | Faction::SerialKiller => true,
+            Faction::Village | Faction::Tanner | Faction::Lovers => false,
+        }
+    }
+
     /// Checks whether a faction's win condition has been met in the given universe.
+    ///
+    /// For factions whose players win individually, like the serial killer or the tanner, this checks whether any player of the faction has won. See `Universe::wins` for whether a specific player has won.
     pub fn wincon(&self, universe: &Universe) -> bool {
         match *self {
-            Faction::Werewolves => !universe.factions.iter()
-                .zip(universe.alive.iter())
-                .any(|(&faction, &alive)| faction == Faction::Village && alive),
+            Faction::Werewolves => {
+                let werewolf_alive = universe.alive.iter()
+                    .any(|player_idx| universe.faction(player_idx) == Faction::Werewolves);
+                let opponent_alive = universe.alive.iter()
+                    .any(|player_idx| matches!(universe.faction(player_idx), Faction::Village | Faction::SerialKiller));
+                werewolf_alive && !opponent_alive
+            }
             Faction::Village => {
-                let villager_alive = universe.factions.iter()
-                    .zip(universe.alive.iter())
-                    .any(|(&faction, &alive)| faction == Faction::Village && alive);
-                // if introducing additional factions considered threats to the village, update this
-                let threat_alive = universe.factions.iter()
-                    .zip(universe.alive.iter())
-                    .any(|(&faction, &alive)| faction == Faction::Werewolves && alive);
+                let villager_alive = universe.alive.iter()
+                    .any(|player_idx| universe.faction(player_idx) == Faction::Village);
+                let threat_alive = universe.alive.iter()
+                    .any(|player_idx| universe.faction(player_idx).threatens_village());
                 villager_alive && !threat_alive
             }
+            Faction::SerialKiller => !universe.alive.is_empty() && universe.alive.iter()
+                .all(|player_idx| universe.faction(player_idx) == Faction::SerialKiller),
+            Faction::Tanner => universe.lynched.iter()
+                .any(|player_idx| universe.faction(player_idx) == Faction::Tanner),
+            Faction::Lovers => !universe.lovers.is_empty() && !universe.alive.is_empty() && universe.alive.iter()
+                .all(|player_idx| universe.lovers.contains(player_idx)),
         }
     }
 }
@@ -52,19 +69,124 @@ impl fmt::Display for Faction {
         match *self {
             Faction::Werewolves => write!(f, "werewolves"),
             Faction::Village => write!(f, "village"),
+            Faction::SerialKiller => write!(f, "serial killer"),
+            Faction::Tanner => write!(f, "tanner"),
+            Faction::Lovers => write!(f, "lovers"),
+        }
+    }
+}
+
+/// Determines which werewolves kill during the night.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KillRule {
+    /// Only the alive werewolf with the lowest rank kills.
+    #[default]
+    Dominant,
+    /// Every alive werewolf kills their own target.
+    Every,
+    /// The alive werewolves vote on a single target. The player with the most votes is killed, with ties broken in favor of the target chosen by the werewolf with the lower rank.
+    Majority,
+}
+
+impl FromStr for KillRule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<KillRule, ()> {
+        match &s.to_lowercase()[..] {
+            "dominant" => Ok(KillRule::Dominant),
+            "every" => Ok(KillRule::Every),
+            "majority" => Ok(KillRule::Majority),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for KillRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            KillRule::Dominant => write!(f, "dominant"),
+            KillRule::Every => write!(f, "every"),
+            KillRule::Majority => write!(f, "majority"),
+        }
+    }
+}
+
+/// Determines what is publicly revealed about players when they die.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RevealRule {
+    /// The role of a dead player is revealed.
+    #[default]
+    Role,
+    /// Only the faction of a dead player is revealed, their role may remain undetermined.
+    Faction,
+    /// Nothing is revealed about dead players, their role and faction may remain undetermined.
+    Nothing,
+}
+
+/// What has been publicly revealed about a dead player, depending on the `RevealRule`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reveal {
+    /// The player's role.
+    Role(Role),
+    /// The player's faction.
+    Faction(Faction),
+    /// Nothing has been revealed.
+    Nothing,
+}
+
+/// How a player died. This is not public information, but it can be learned by a medium.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CauseOfDeath {
+    /// The player was killed by the werewolves, possibly while guarding the werewolf victim.
+    Werewolves,
+    /// The player was killed by a serial killer.
+    SerialKiller,
+    /// The player was poisoned by a witch.
+    Poison,
+    /// The player died together with their lover.
+    Heartbreak,
+    /// The player was lynched.
+    Lynch,
+    /// The player was shot by a dead hunter.
+    Shot,
+}
+
+impl fmt::Display for CauseOfDeath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CauseOfDeath::Werewolves => write!(f, "a werewolf attack"),
+            CauseOfDeath::SerialKiller => write!(f, "a serial killer attack"),
+            CauseOfDeath::Poison => write!(f, "poison"),
+            CauseOfDeath::Heartbreak => write!(f, "heartbreak"),
+            CauseOfDeath::Lynch => write!(f, "lynching"),
+            CauseOfDeath::Shot => write!(f, "a hunter's shot"),
         }
     }
 }
 
 /// A night action submitted by a player.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum NightAction<P> {
     /// A healer action.
     Heal(P, P),
+    /// A bodyguard action.
+    Guard(P, P),
     /// A detective investigation.
     Investigate(P, P),
-    /// A werewolf kill.
-    Kill(P, P),
+    /// A seer investigation.
+    See(P, P),
+    /// A medium contacting a dead player.
+    Seance(P, P),
+    /// A werewolf kill, or `None` to not kill anyone. Not killing anyone is only allowed if `Rules::mandatory_werewolf_kill` is `false`.
+    Kill(P, Option<P>),
+    /// A serial killer kill.
+    SerialKill(P, P),
+    /// Cupid's choice of lovers.
+    Link(P, P, P),
+    /// A witch's decision whether to use their healing potion on the werewolf victim.
+    Save(P, bool),
+    /// A witch's decision whether to use their poison potion, and on whom.
+    Poison(P, Option<P>),
 }
 
 impl<P> NightAction<P> {
@@ -72,17 +194,88 @@ impl<P> NightAction<P> {
     pub fn src(&self) -> &P {
         match *self {
             NightAction::Heal(ref src, _) => src,
+            NightAction::Guard(ref src, _) => src,
             NightAction::Investigate(ref src, _) => src,
+            NightAction::See(ref src, _) => src,
+            NightAction::Seance(ref src, _) => src,
             NightAction::Kill(ref src, _) => src,
+            NightAction::SerialKill(ref src, _) => src,
+            NightAction::Link(ref src, _, _) => src,
+            NightAction::Save(ref src, _) => src,
+            NightAction::Poison(ref src, _) => src,
         }
     }
 }
 
+/// A change to the game state which is applied to each universe separately, using `Universe::apply`.
+///
+/// Players are given as secret player IDs.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// The first player heals the second, if they are an alive healer.
+    Heal(usize, usize),
+    /// The first player guards the second, if they are an alive bodyguard.
+    Guard(usize, usize),
+    /// The first player has learned that the second has the given faction. Universes in which the first player is an alive detective and the second player has a different faction are eliminated.
+    Investigate(usize, usize, Faction),
+    /// The first player has learned that the second has the given role. Universes in which the first player is an alive seer and the second player has a different role are eliminated.
+    See(usize, usize, Role),
+    /// The first player has learned that the second died of the given cause. Universes in which the first player is an alive medium and the second player died differently are eliminated.
+    Seance(usize, usize, CauseOfDeath),
+    /// The first player votes to kill the second during the night, if they are an alive werewolf. The vote takes effect with `WerewolfKill`.
+    Kill(usize, usize),
+    /// All werewolf votes of this night are resolved according to the given rule, killing the chosen players.
+    WerewolfKill(KillRule),
+    /// The first player kills the second during the night, if they are an alive serial killer.
+    SerialKill(usize, usize),
+    /// The first player links the other two, if they are an alive cupid. Linked players die together.
+    Link(usize, usize, usize),
+    /// The given player saves the werewolf victims of this night, if they are an alive witch who has not used their healing potion yet. The potion is only used up if there is a victim to save.
+    ///
+    /// The witch doesn't choose whom to save: if several players are attacked by the werewolves, for example with `KillRule::Every`, all of them are saved, using up only the one potion.
+    ///
+    /// This must be applied after `WerewolfKill`. Players killed in other ways, for example by the serial killer, are not saved, regardless of the order in which the kills are applied.
+    Save(usize),
+    /// The first player kills the second during the night, if they are an alive witch who has not used their poison potion yet.
+    Poison(usize, usize),
+    /// The night has ended, so all players killed during the night die, along with their lovers.
+    EndNight,
+    /// The given player has been lynched. Universes in which they were already dead are eliminated. If they are a tanner, they win.
+    Lynch(usize),
+    /// The given player has been shot by a dead hunter. Like with a lynch, universes in which they were already dead are eliminated.
+    Shoot(usize),
+    /// The given player has been told who the other masons are if they are a mason. Universes in which they are a mason and the other masons are different players are eliminated.
+    Masons(usize, PlayerSet),
+    /// The given player's role has been revealed. Universes in which they have a different role are eliminated.
+    Collapse(usize, Role),
+    /// The given player's faction has been revealed. Universes in which they have a different faction are eliminated.
+    CollapseFaction(usize, Faction),
+    /// The given player has died without being asked to shoot. Universes in which they are a hunter are eliminated.
+    NotHunter(usize),
+}
+
 /// Contains the information sent to a player as the result of a night action.
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum NightActionResult<P> {
     /// An investigation result, for example for a detective.
     Investigation(P, Faction),
+    /// A seer result, containing the exact role of the target.
+    Vision(P, Role),
+    /// A medium result, containing how the target died.
+    Seance(P, CauseOfDeath),
+}
+
+impl<P: Clone> NightActionResult<&P> {
+    /// Maps a `NightActionResult<&P>` to a `NightActionResult<P>` by cloning the players.
+    pub fn cloned(self) -> NightActionResult<P> {
+        use NightActionResult::*;
+
+        match self {
+            Investigation(target, faction) => Investigation(target.clone(), faction),
+            Vision(target, role) => Vision(target.clone(), role),
+            Seance(target, cause) => Seance(target.clone(), cause),
+        }
+    }
 }
 
 impl NightActionResult<usize> {
@@ -91,6 +284,8 @@ impl NightActionResult<usize> {
 
         match self {
             Investigation(idx, faction) => Investigation(&ids[idx], faction),
+            Vision(idx, role) => Vision(&ids[idx], role),
+            Seance(idx, cause) => Seance(&ids[idx], cause),
         }
     }
 }
@@ -104,15 +299,74 @@ pub enum Role {
     Healer,
     /// A regular villager with no special abilities.
     Villager,
-    /// A werewolf. Kills a player each night if no werewolf with a *lower* rank is alive.
+    /// A mason, part of the village. Knows who the other masons are from the start of the game.
+    Mason,
+    /// A bodyguard, part of the village. Guards another player each night. If that player is attacked by the werewolves during the night, the bodyguard dies in their place.
+    Bodyguard,
+    /// A seer, part of the village. Investigates a player each night, learning their exact role.
+    Seer,
+    /// A medium, part of the village. Contacts a dead player each night, learning how they died.
+    Medium,
+    /// A witch, part of the village. Once per game, may save the werewolf victims of the night, which can be more than one player with `KillRule::Every`. Also once per game, may kill a player during the night.
+    Witch,
+    /// A hunter, part of the village. When they die, they may immediately shoot a player, killing them.
+    Hunter,
+    /// Cupid, part of the village. Links two players on the first night, who from then on die together and can also win by being the last survivors. There can be at most one cupid per game.
+    Cupid,
+    /// A serial killer, who is their own faction. Kills a player each night, and wins by being the last one alive.
+    SerialKiller,
+    /// A tanner, who is their own faction. Has no special abilities, and wins by being lynched.
+    Tanner,
+    /// A werewolf. Which werewolves kill each night depends on `Rules::kill_rule`: by default, only the alive werewolf with the lowest rank kills. The rank also breaks ties with `KillRule::Majority`.
     Werewolf(usize),
 }
 
 impl Role {
-    fn default_faction(&self) -> Faction {
+    pub(crate) fn default_faction(&self) -> Faction {
         match *self {
-            Role::Detective | Role::Healer | Role::Villager => Faction::Village,
+            Role::Detective | Role::Healer | Role::Villager | Role::Seer | Role::Witch | Role::Hunter | Role::Cupid | Role::Bodyguard | Role::Mason | Role::Medium => Faction::Village,
             Role::Werewolf(_) => Faction::Werewolves,
+            Role::SerialKiller => Faction::SerialKiller,
+            Role::Tanner => Faction::Tanner,
+        }
+    }
+
+    /// Encodes the role as a single byte, for compact storage in universes.
+    pub(crate) fn pack(self) -> u8 {
+        match self {
+            Role::Detective => 0,
+            Role::Healer => 1,
+            Role::Villager => 2,
+            Role::Seer => 3,
+            Role::Witch => 4,
+            Role::Hunter => 5,
+            Role::Cupid => 6,
+            Role::SerialKiller => 7,
+            Role::Tanner => 8,
+            Role::Bodyguard => 9,
+            Role::Mason => 10,
+            Role::Medium => 11,
+            Role::Werewolf(rank) => u8::try_from(rank + 0x80).expect("too many werewolves"),
+        }
+    }
+
+    /// Decodes a role encoded using `pack`.
+    pub(crate) fn unpack(role: u8) -> Role {
+        match role {
+            0 => Role::Detective,
+            1 => Role::Healer,
+            2 => Role::Villager,
+            3 => Role::Seer,
+            4 => Role::Witch,
+            5 => Role::Hunter,
+            6 => Role::Cupid,
+            7 => Role::SerialKiller,
+            8 => Role::Tanner,
+            9 => Role::Bodyguard,
+            10 => Role::Mason,
+            11 => Role::Medium,
+            0x80..=0xff => Role::Werewolf(usize::from(role - 0x80)),
+            _ => unreachable!("invalid packed role"),
         }
     }
 }
@@ -125,6 +379,15 @@ impl FromStr for Role {
             "detective" => Ok(Role::Detective),
             "healer" => Ok(Role::Healer),
             "villager" => Ok(Role::Villager),
+            "seer" => Ok(Role::Seer),
+            "witch" => Ok(Role::Witch),
+            "hunter" => Ok(Role::Hunter),
+            "cupid" => Ok(Role::Cupid),
+            "serial-killer" | "serialkiller" => Ok(Role::SerialKiller),
+            "tanner" => Ok(Role::Tanner),
+            "bodyguard" => Ok(Role::Bodyguard),
+            "mason" => Ok(Role::Mason),
+            "medium" => Ok(Role::Medium),
             "werewolf" => Ok(Role::Werewolf(0)),
             _ => Err(()),
         }
@@ -137,203 +400,405 @@ impl fmt::Display for Role {
             Role::Detective => write!(f, "detective"),
             Role::Healer => write!(f, "healer"),
             Role::Villager => write!(f, "villager"),
+            Role::Seer => write!(f, "seer"),
+            Role::Witch => write!(f, "witch"),
+            Role::Hunter => write!(f, "hunter"),
+            Role::Cupid => write!(f, "cupid"),
+            Role::SerialKiller => write!(f, "serial killer"),
+            Role::Tanner => write!(f, "tanner"),
+            Role::Bodyguard => write!(f, "bodyguard"),
+            Role::Mason => write!(f, "mason"),
+            Role::Medium => write!(f, "medium"),
             Role::Werewolf(i) => write!(f, "werewolf {i}"),
         }
     }
 }
 
-/// A universe represents one of the possible quantum states in a game of Quantum Werewolf. It contains information such as the distribution of roles, and which players are still alive.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
-pub struct Universe {
-    pub(crate) alive: Vec<bool>,
-    pub(crate) roles: Vec<Role>,
-    pub(crate) factions: Vec<Faction>,
-    pub(crate) heals: Vec<usize>, // this should be a set, but HashSet isn't hashable
-    pub(crate) kills: Vec<usize>, // this should be a set, but HashSet isn't hashable
-}
+/// The maximum number of players in a game, determined by the size of `PlayerSet`.
+pub const MAX_PLAYERS: usize = 64;
 
-impl Universe {
-    /// Checks if the game has ended.
-    fn game_over(&self, night: bool) -> bool {
-        self.alive.iter().all(|alive| !alive) ||
-        (!night && self.alive.iter().filter(|&&alive| alive).count() < 2) ||
-        (!night && self.factions.iter().any(|faction| faction.wincon(self)))
+/// A compact set of secret player IDs, used for per-universe player state such as who is alive.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(transparent)]
+pub struct PlayerSet(pub(crate) u64);
+
+impl PlayerSet {
+    /// The set containing the secret player IDs `0..num_players`.
+    pub fn all(num_players: usize) -> PlayerSet {
+        PlayerSet(if num_players >= MAX_PLAYERS { u64::MAX } else { (1 << num_players) - 1 })
     }
 
-    /// Utility method to properly handle healing a player during the night.
-    pub fn heal(&mut self, player_idx: usize) {
-        self.kills.retain(|&iter_idx| iter_idx != player_idx);
-        self.heals.push(player_idx);
+    /// Returns `true` if the set contains the given player.
+    pub fn contains(&self, player_idx: usize) -> bool {
+        self.0 & (1 << player_idx) != 0
     }
 
-    /// Utility method to properly handle killing a player depending on day/night, healed status, etc.
-    pub fn kill(&mut self, player_idx: usize, night: bool) {
-        if night {
-            if !self.heals.contains(&player_idx) {
-                self.kills.push(player_idx);
-            }
-        } else {
-            self.alive[player_idx] = false;
-        }
+    /// Adds a player to the set.
+    pub fn insert(&mut self, player_idx: usize) {
+        self.0 |= 1 << player_idx;
+    }
+
+    /// Removes a player from the set.
+    pub fn remove(&mut self, player_idx: usize) {
+        self.0 &= !(1 << player_idx);
+    }
+
+    /// Returns `true` if the set contains no players.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the number of players in the set.
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Iterates over the players in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> {
+        let bits = self.0;
+        (0..MAX_PLAYERS).filter(move |&player_idx| bits & (1 << player_idx) != 0)
     }
 }
 
-impl From<Vec<Role>> for Universe {
-    fn from(roles: Vec<Role>) -> Universe {
-        Universe {
-            alive: vec![true; roles.len()],
-            factions: roles.iter().map(Role::default_faction).collect(),
-            roles: roles,
-            heals: Vec::default(),
-            kills: Vec::default(),
+impl FromIterator<usize> for PlayerSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> PlayerSet {
+        let mut set = PlayerSet::default();
+        for player_idx in iter {
+            set.insert(player_idx);
         }
+        set
     }
 }
 
-/// A collection of universes, with several convenience methods.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Multiverse(Vec<Universe>);
+/// A universe represents one of the possible quantum states in a game of Quantum Werewolf. It contains information such as the distribution of roles, and which players are still alive.
+///
+/// Universes which have become indistinguishable are merged, so each universe also has a weight, the number of original universes it represents.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Universe {
+    pub(crate) alive: PlayerSet,
+    pub(crate) roles: Box<[u8]>, // packed using `Role::pack` to save memory
+    pub(crate) heals: PlayerSet,
+    pub(crate) kills: PlayerSet,
+    #[serde(default)]
+    pub(crate) attacks: PlayerSet, // players attacked by the werewolves during this night, kept separate from other kills so the witch can save them
+    #[serde(default)]
+    pub(crate) guards: Vec<(u8, u8)>, // bodyguards and the players they are guarding during this night
+    #[serde(default)]
+    pub(crate) werewolf_votes: Vec<(u8, u8)>, // werewolves and the players they have voted to kill during this night
+    #[serde(default)]
+    pub(crate) saves_used: PlayerSet, // witches who have used their healing potion
+    #[serde(default)]
+    pub(crate) poisons_used: PlayerSet, // witches who have used their poison potion
+    #[serde(default)]
+    pub(crate) lovers: PlayerSet,
+    #[serde(default)]
+    pub(crate) lynched: PlayerSet,
+    pub(crate) deaths: Box<[Option<CauseOfDeath>]>, // set for dead players and for players killed during this night
+    pub(crate) weight: u64,
+}
 
-impl Multiverse {
-    /// Constructs and returns a new multiverse with all possible role distributions.
-    ///
-    /// If fewer roles than players are given, the remaining role slots will be populated with villagers.
-    pub fn new(roles: Vec<Role>, num_players: usize) -> Multiverse {
-        let mut permutations = vec![vec![Role::Villager; num_players]];
-        for role in roles {
-            permutations = permutations.into_iter().flat_map(|perm| {
-                (0..perm.len()).filter_map(|i| {
-                    if perm[i] == Role::Villager {
-                        let mut new_perm = perm.clone();
-                        new_perm[i] = role;
-                        Some(new_perm)
-                    } else {
-                        None
-                    }
-                }).collect::<Vec<_>>()
-            }).collect();
-        }
-        Multiverse(permutations.into_iter().map(Universe::from).collect())
-    }
-
-    /// Returns the set of players which are still alive in at least one possible universe.
-    pub fn alive(&self) -> Vec<usize> {
-        (0..self.num_players()).filter(|&idx| {
-            self.0.iter().any(|universe| universe.alive[idx])
-        }).collect()
-    }
-
-    /// Determines a single role for each dead player and removes all universes where that player doesn't have that role.
-    pub fn collapse_roles(&mut self) {
-        let mut start_size = self.0.len();
-        let mut collapsed_roles = HashMap::<usize, Role>::default();
-        loop {
-            {
-                let collapse_universe = self.iter().rand(&mut thread_rng()).expect("paradox created while collapsing roles");
-                for player_idx in 0..self.num_players() {
-                    if !self.alive().contains(&player_idx) {
-                        collapsed_roles.insert(player_idx, collapse_universe.roles[player_idx]);
-                    }
-                }
-            }
-            let multiverse = mem::replace(&mut self.0, Vec::default());
-            self.0 = multiverse
-                .into_iter()
-                .filter(|universe| collapsed_roles.iter().all(|(&id, &role)| universe.roles[id] == role))
-                .collect();
-            if self.0.len() == start_size {
-                break;
-            } else {
-                start_size = self.0.len();
-            }
+impl Universe {
+    /// Creates a universe at the start of the game from roles packed using `Role::pack`.
+    pub(crate) fn from_packed(roles: Box<[u8]>, weight: u64) -> Universe {
+        Universe {
+            alive: PlayerSet::all(roles.len()),
+            deaths: vec![None; roles.len()].into_boxed_slice(),
+            roles,
+            heals: PlayerSet::default(),
+            kills: PlayerSet::default(),
+            attacks: PlayerSet::default(),
+            guards: Vec::default(),
+            werewolf_votes: Vec::default(),
+            saves_used: PlayerSet::default(),
+            poisons_used: PlayerSet::default(),
+            lovers: PlayerSet::default(),
+            lynched: PlayerSet::default(),
+            weight,
         }
     }
 
-    /// Returns the faction of the given player, if that faction is unambiguous.
-    pub fn faction(&self, player_idx: usize) -> Option<Faction> {
-        let faction = self.0[0].factions[player_idx];
-        if self.0[1..].iter().all(|universe| universe.factions[player_idx] == faction) {
-            Some(faction)
-        } else {
-            None
-        }
+    /// Returns the role of the given player in this universe.
+    pub fn role(&self, player_idx: usize) -> Role {
+        Role::unpack(self.roles[player_idx])
     }
 
-    /// Checks if the game has ended in all universes.
-    pub fn game_over(&self, night: bool) -> bool {
-        self.iter().all(|universe| universe.game_over(night))
+    /// Iterates over the roles of all players in this universe, in secret player ID order.
+    pub fn roles(&self) -> impl Iterator<Item = Role> + '_ {
+        self.roles.iter().map(|&role| Role::unpack(role))
     }
 
-    /// Iterates over all universes in no particular order.
-    pub fn into_iter(self) -> vec::IntoIter<Universe> {
-        self.0.into_iter()
+    /// Returns the faction of the given player in this universe.
+    pub fn faction(&self, player_idx: usize) -> Faction {
+        self.role(player_idx).default_faction()
     }
 
-    /// Iterates over all universes in no particular order.
-    pub fn iter(&self) -> slice::Iter<'_, Universe> {
-        self.0.iter()
+    /// Returns `true` if the given player is alive in this universe.
+    pub fn is_alive(&self, player_idx: usize) -> bool {
+        self.alive.contains(player_idx)
     }
 
-    /// Iterates over all universes in no particular order.
-    pub fn iter_mut(&mut self) -> slice::IterMut<'_, Universe> {
-        self.0.iter_mut()
+    /// Returns how the given player died in this universe, or `None` if they are alive.
+    pub fn cause_of_death(&self, player_idx: usize) -> Option<CauseOfDeath> {
+        self.deaths[player_idx]
     }
 
-    /// The total number of players for which this multiverse was created.
-    pub fn num_players(&self) -> usize {
-        self.0[0].alive.len()
+    /// Returns `true` if the given player has been linked to another player by Cupid in this universe.
+    pub fn is_lover(&self, player_idx: usize) -> bool {
+        self.lovers.contains(player_idx)
     }
 
-    /// Produces the anonymized probability table shown to players at the start of the day.
-    pub fn probability_table(&self) -> Vec<Result<(f64, f64, f64), Faction>> {
-        (0..self.num_players()).into_iter().map(|player_idx| {
-            if self.alive().contains(&player_idx) {
-                let village_universes = self.iter()
-                    .filter(|universe| universe.factions[player_idx] == Faction::Village)
-                    .count();
-                let village_ratio = (village_universes as f64) / (self.0.len() as f64);
-                let werewolves_universes = self.iter()
-                    .filter(|universe| universe.factions[player_idx] == Faction::Werewolves)
-                    .count();
-                let werewolves_ratio = (werewolves_universes as f64) / (self.0.len() as f64);
-                let dead_universes = self.iter()
-                    .filter(|universe| !universe.alive[player_idx])
-                    .count();
-                let dead_ratio = (dead_universes as f64) / (self.0.len() as f64);
-                Ok((village_ratio, werewolves_ratio, dead_ratio))
-            } else {
-                Err(self.faction(player_idx).expect("player is dead but does not have a determined faction"))
+    /// Returns `true` if the given player has won in this universe, either with their faction or as one of the lovers.
+    ///
+    /// A serial killer only wins if they are alive, and a tanner only wins if they have been lynched themselves.
+    pub fn wins(&self, player_idx: usize) -> bool {
+        let faction_wins = match self.faction(player_idx) {
+            Faction::SerialKiller => self.is_alive(player_idx) && Faction::SerialKiller.wincon(self),
+            Faction::Tanner => self.lynched.contains(player_idx),
+            faction => faction.wincon(self),
+        };
+        faction_wins || (self.is_lover(player_idx) && Faction::Lovers.wincon(self))
+    }
+
+    /// The number of original universes this universe represents.
+    pub fn weight(&self) -> u64 {
+        self.weight
+    }
+
+    /// Checks if the game has ended.
+    pub(crate) fn game_over(&self, night: bool) -> bool {
+        self.alive.is_empty() ||
+        (!night && self.alive.len() < 2) ||
+        (!night && (0..self.roles.len()).any(|player_idx| self.wins(player_idx)))
+    }
+
+    /// Utility method to properly handle healing a player during the night.
+    pub fn heal(&mut self, player_idx: usize) {
+        if self.kills.contains(player_idx) {
+            self.kills.remove(player_idx);
+            self.deaths[player_idx] = None;
+        }
+        self.attacks.remove(player_idx);
+        self.heals.insert(player_idx);
+    }
+
+    /// Utility method to handle a bodyguard guarding a player during the night.
+    ///
+    /// If the player is attacked by the werewolves later this night, the bodyguard is killed instead, see `attack`.
+    pub fn guard(&mut self, bodyguard_idx: usize, player_idx: usize) {
+        let bodyguard_idx = u8::try_from(bodyguard_idx).expect("too many players");
+        let player_idx = u8::try_from(player_idx).expect("too many players");
+        self.guards.push((bodyguard_idx, player_idx));
+    }
+
+    /// Utility method to handle the werewolves attacking a player during the night.
+    ///
+    /// If the player is being guarded, their bodyguard is attacked instead. Other night kills are not intercepted by bodyguards.
+    ///
+    /// Attacked players are kept separate from other kills until the end of the night, so that a witch can save them without saving anyone else.
+    pub fn attack(&mut self, player_idx: usize) {
+        if self.heals.contains(player_idx) { return }
+        if let Some(guard_idx) = self.guards.iter().position(|&(_, guarded)| usize::from(guarded) == player_idx) {
+            // each bodyguard can only die once, so remove them from the guards
+            let (bodyguard_idx, _) = self.guards.swap_remove(guard_idx);
+            self.attack(usize::from(bodyguard_idx));
+        } else {
+            self.attacks.insert(player_idx);
+        }
+    }
+
+    /// Utility method to properly handle killing a player depending on day/night, healed status, etc.
+    ///
+    /// During the day, the player's lovers die with them. At night, this happens at the end of the night, so that healing the player still saves both.
+    ///
+    /// If a player is killed multiple times during the night, the first kill is recorded as their cause of death, except that an attack by the werewolves always takes precedence, since the werewolves act first.
+    pub fn kill(&mut self, player_idx: usize, night: bool, cause: CauseOfDeath) {
+        if night {
+            if self.heals.contains(player_idx) { return }
+            if !self.kills.contains(player_idx) {
+                self.kills.insert(player_idx);
+                self.deaths[player_idx] = Some(cause);
+            }
+        } else {
+            self.alive.remove(player_idx);
+            self.deaths[player_idx] = Some(cause);
+            if self.lovers.contains(player_idx) {
+                for lover_idx in self.lovers.iter() {
+                    if self.is_alive(lover_idx) {
+                        self.alive.remove(lover_idx);
+                        self.deaths[lover_idx] = Some(CauseOfDeath::Heartbreak);
+                    }
+                }
             }
-        }).collect()
+        }
+    }
+
+    /// Returns `true` if the given player is an alive witch in this universe.
+    fn is_alive_witch(&self, player_idx: usize) -> bool {
+        self.role(player_idx) == Role::Witch && self.is_alive(player_idx)
     }
 
-    /// Returns the role of the given player, if that role is unambiguous.
-    pub fn role(&self, player_idx: usize) -> Option<Role> {
-        let role = self.0[0].roles[player_idx];
-        if self.0[1..].iter().all(|universe| universe.roles[player_idx] == role) {
-            Some(role)
+    /// Returns the rank of the given player if they are an alive werewolf in this universe.
+    fn alive_werewolf_rank(&self, player_idx: usize) -> Option<usize> {
+        if let Role::Werewolf(rank) = self.role(player_idx) {
+            self.is_alive(player_idx).then_some(rank)
         } else {
             None
         }
     }
 
-    /// Whether or not there is at least one player who may be alive and may have this role.
+    /// Returns the players killed by the werewolves this night according to the given rule.
+    fn werewolf_victims(&self, kill_rule: KillRule) -> Vec<usize> {
+        let mut votes = self.werewolf_votes.iter()
+            .filter_map(|&(src, tgt)| self.alive_werewolf_rank(usize::from(src)).map(|rank| (rank, usize::from(tgt))))
+            .filter(|&(_, tgt)| self.is_alive(tgt))
+            .collect::<Vec<_>>();
+        votes.sort_by_key(|&(rank, _)| rank);
+        match kill_rule {
+            KillRule::Dominant => {
+                let dominant_rank = (0..self.roles.len()).filter_map(|player_idx| self.alive_werewolf_rank(player_idx)).min();
+                votes.into_iter()
+                    .filter(|&(rank, _)| Some(rank) == dominant_rank)
+                    .map(|(_, tgt)| tgt)
+                    .take(1)
+                    .collect()
+            }
+            KillRule::Every => votes.into_iter().map(|(_, tgt)| tgt).collect(),
+            KillRule::Majority => {
+                let num_votes = |tgt| votes.iter().filter(|&&(_, iter_tgt)| iter_tgt == tgt).count();
+                let max_votes = votes.iter().map(|&(_, tgt)| num_votes(tgt)).max();
+                votes.iter()
+                    .map(|&(_, tgt)| tgt)
+                    .find(|&tgt| Some(num_votes(tgt)) == max_votes)
+                    .into_iter()
+                    .collect()
+            }
+        }
+    }
+
+    /// Applies an operation to this universe.
     ///
-    /// Note that this may return true even if this role is dead in all universes, namely if there are multiple players who may have that role.
-    pub fn role_alive(&self, role: Role) -> bool {
-        self.iter().any(|universe| {
-            universe.roles.iter().enumerate().any(|(player_idx, &iter_role)|
-                role == iter_role && (
-                    universe.alive[player_idx] ||
-                    self.role(player_idx).is_none()
-                )
-            )
-        })
+    /// Returns `false` if the operation has made this universe impossible, in which case it should be removed from the multiverse.
+    pub fn apply(&mut self, op: Op) -> bool {
+        match op {
+            Op::Heal(src, tgt) => {
+                if self.role(src) == Role::Healer && self.is_alive(src) && self.is_alive(tgt) {
+                    self.heal(tgt);
+                }
+                true
+            }
+            Op::Guard(src, tgt) => {
+                if self.role(src) == Role::Bodyguard && self.is_alive(src) && self.is_alive(tgt) {
+                    self.guard(src, tgt);
+                }
+                true
+            }
+            Op::Investigate(src, tgt, faction) => !(
+                self.role(src) == Role::Detective &&
+                self.is_alive(src) &&
+                self.faction(tgt) != faction
+            ),
+            Op::See(src, tgt, role) => !(
+                self.role(src) == Role::Seer &&
+                self.is_alive(src) &&
+                self.role(tgt) != role
+            ),
+            Op::Seance(src, tgt, cause) => !(
+                self.role(src) == Role::Medium &&
+                self.is_alive(src) &&
+                self.cause_of_death(tgt) != Some(cause)
+            ),
+            Op::Kill(src, tgt) => {
+                if self.alive_werewolf_rank(src).is_some() && self.is_alive(tgt) {
+                    let src = u8::try_from(src).expect("too many players");
+                    let tgt = u8::try_from(tgt).expect("too many players");
+                    self.werewolf_votes.push((src, tgt));
+                }
+                true
+            }
+            Op::WerewolfKill(kill_rule) => {
+                for tgt in self.werewolf_victims(kill_rule) {
+                    self.attack(tgt);
+                }
+                self.werewolf_votes = Vec::default();
+                true
+            }
+            Op::SerialKill(src, tgt) => {
+                if self.role(src) == Role::SerialKiller && self.is_alive(src) && self.is_alive(tgt) {
+                    self.kill(tgt, true, CauseOfDeath::SerialKiller);
+                }
+                true
+            }
+            Op::Link(src, lover1, lover2) => {
+                if self.role(src) == Role::Cupid && self.is_alive(src) {
+                    self.lovers.insert(lover1);
+                    self.lovers.insert(lover2);
+                }
+                true
+            }
+            Op::Save(src) => {
+                if self.is_alive_witch(src) && !self.saves_used.contains(src) && !self.attacks.is_empty() {
+                    self.attacks = PlayerSet::default();
+                    self.saves_used.insert(src);
+                }
+                true
+            }
+            Op::Poison(src, tgt) => {
+                if self.is_alive_witch(src) && !self.poisons_used.contains(src) && self.is_alive(tgt) {
+                    self.kill(tgt, true, CauseOfDeath::Poison);
+                    self.poisons_used.insert(src);
+                }
+                true
+            }
+            Op::EndNight => {
+                for victim_idx in self.attacks.iter() {
+                    self.kills.insert(victim_idx);
+                    self.deaths[victim_idx] = Some(CauseOfDeath::Werewolves);
+                }
+                if self.kills.0 & self.lovers.0 != 0 {
+                    for lover_idx in self.lovers.iter() {
+                        if self.is_alive(lover_idx) && !self.kills.contains(lover_idx) {
+                            self.kills.insert(lover_idx);
+                            self.deaths[lover_idx] = Some(CauseOfDeath::Heartbreak);
+                        }
+                    }
+                }
+                self.alive.0 &= !self.kills.0;
+                self.heals = PlayerSet::default();
+                self.kills = PlayerSet::default();
+                self.attacks = PlayerSet::default();
+                self.guards = Vec::default();
+                true
+            }
+            Op::Lynch(tgt) => if self.is_alive(tgt) {
+                self.kill(tgt, false, CauseOfDeath::Lynch);
+                self.lynched.insert(tgt);
+                true
+            } else {
+                false // the lynched player must be alive
+            },
+            Op::Shoot(tgt) => if self.is_alive(tgt) {
+                self.kill(tgt, false, CauseOfDeath::Shot);
+                true
+            } else {
+                false // the shot player must be alive
+            },
+            Op::Masons(player_idx, partners) => self.role(player_idx) != Role::Mason || self.roles()
+                .enumerate()
+                .all(|(iter_idx, role)| iter_idx == player_idx || (role == Role::Mason) == partners.contains(iter_idx)),
+            Op::Collapse(player_idx, role) => self.role(player_idx) == role,
+            Op::CollapseFaction(player_idx, faction) => self.faction(player_idx) == faction,
+            Op::NotHunter(player_idx) => self.role(player_idx) != Role::Hunter,
+        }
+    }
+
+    /// Returns `true` if the two universes can no longer be distinguished, meaning they can be merged.
+    pub(crate) fn same_state(&self, other: &Universe) -> bool {
+        self.alive == other.alive && self.roles == other.roles && self.heals == other.heals && self.kills == other.kills && self.attacks == other.attacks && self.guards == other.guards && self.werewolf_votes == other.werewolf_votes && self.saves_used == other.saves_used && self.poisons_used == other.poisons_used && self.lovers == other.lovers && self.lynched == other.lynched && self.deaths == other.deaths
     }
 }
 
-impl FromIterator<Universe> for Multiverse {
-    fn from_iter<I: IntoIterator<Item = Universe>>(iter: I) -> Multiverse {
-        Multiverse(iter.into_iter().collect())
+impl From<Vec<Role>> for Universe {
+    fn from(roles: Vec<Role>) -> Universe {
+        Universe::from_packed(roles.into_iter().map(Role::pack).collect(), 1)
     }
 }
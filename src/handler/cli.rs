@@ -1,23 +1,62 @@
-use std::fmt;
-use std::collections::HashSet;
-
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fmt,
+    fs,
+    hash::Hash,
+    path::PathBuf
+};
+use serde::Serialize;
 use crate::{
     game::{
+        Estimate,
         Faction,
-        Role
+        MultiverseBackend,
+        Reveal,
+        Role,
+        event::Event,
+        state::State
+    },
+    handler::{
+        self,
+        Handler,
+        voting::{
+            Poll,
+            TieRule,
+            VotingSystem
+        }
     },
-    handler::Handler,
     player::Player,
-    util,
+    util
 };
 
 /// A game handler which uses the command line.
-pub struct CliHandler;
+#[derive(Debug, Default)]
+pub struct CliHandler {
+    /// If this is set, the game state is saved to this file after every phase, replacing its previous contents.
+    pub save_file: Option<PathBuf>,
+    /// If this is set, all game events are appended to this file.
+    pub event_log: Option<PathBuf>,
+    /// If this is set, the probability table is estimated from this many sampled universes.
+    pub sample_budget: Option<usize>,
+    /// Whether to also announce the probability of each role for each player, for groups playing with full information.
+    pub role_probabilities: bool,
+    /// If this is set, each living player is asked for their vote and the town lynch target is determined using this voting system. Otherwise, the moderator enters the town lynch target.
+    pub voting: Option<VotingSystem>,
+    /// How ties are resolved in the lynch vote.
+    pub tie_rule: TieRule,
+    /// Whether the rules require a lynch, so players can't vote for no lynch. This should be set from `Rules::no_lynch`, and is also set by `cannot_skip_lynch`.
+    pub lynch_required: bool
+}
 
-impl<P: Player + From<String> + fmt::Display> Handler<P> for CliHandler {
-    fn announce_deaths<I: IntoIterator<Item = (P, Role)>>(&mut self, deaths: I) {
-        for (player, role) in deaths {
-            println!("[ ** ] {} died and was {}", player, role);
+impl<P: Player + Eq + Clone + From<String> + fmt::Display + Serialize> Handler<P> for CliHandler {
+    fn announce_deaths<I: IntoIterator<Item = (P, Reveal)>>(&mut self, deaths: I) {
+        for (player, reveal) in deaths {
+            match reveal {
+                Reveal::Role(role) => { println!("[ ** ] {} died and was {}", player, role); }
+                Reveal::Faction(faction) => { println!("[ ** ] {} died and was in the {} faction", player, faction); }
+                Reveal::Nothing => { println!("[ ** ] {} died", player); }
+            }
         }
     }
 
@@ -34,16 +73,93 @@ impl<P: Player + From<String> + fmt::Display> Handler<P> for CliHandler {
         }
     }
 
+    fn announce_approximate_probability_table<I: IntoIterator<Item = Result<(Estimate, Estimate, Estimate), Faction>>>(&mut self, probability_table: I) {
+        for (player_idx, probabilities) in probability_table.into_iter().enumerate() {
+            match probabilities {
+                Ok((village, werewolves, dead)) => {
+                    println!("[ ** ] {}: {} village, {} werewolf, {} dead", player_idx, village, werewolves, dead);
+                }
+                Err(faction) => {
+                    println!("[ ** ] {}: dead (was {})", player_idx, faction);
+                }
+            }
+        }
+    }
+
+    fn announce_role_probability_table<I: IntoIterator<Item = Result<Vec<(Role, f64)>, Role>>>(&mut self, role_probability_table: I) {
+        if !self.role_probabilities { return }
+        for (player_idx, probabilities) in role_probability_table.into_iter().enumerate() {
+            match probabilities {
+                Ok(probabilities) => {
+                    let probabilities = probabilities.into_iter()
+                        .map(|(role, probability)| format!("{}% {}", (probability * 100.0).round() as u8, role))
+                        .collect::<Vec<_>>();
+                    println!("[ ** ] {}: {}", player_idx, probabilities.join(", "));
+                }
+                Err(role) => {
+                    println!("[ ** ] {}: dead (was {})", player_idx, role);
+                }
+            }
+        }
+    }
+
+    fn sample_budget(&self) -> Option<usize> {
+        self.sample_budget
+    }
+
+    fn checkpoint<M: MultiverseBackend>(&mut self, game_state: &State<P, M>) where P: Eq + Hash {
+        if let Some(ref save_file) = self.save_file {
+            // write to a temporary file first so a crash while saving doesn't destroy the previous save
+            let mut tmp_file = OsString::from(save_file);
+            tmp_file.push(".tmp");
+            if let Err(e) = game_state.to_json()
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(&tmp_file, json).map_err(|e| e.to_string()))
+                .and_then(|()| fs::rename(&tmp_file, save_file).map_err(|e| e.to_string()))
+            {
+                println!("[ !! ] failed to save game: {}", e);
+            }
+        }
+    }
+
+    fn record_event(&mut self, event: &Event<P>) {
+        if let Some(ref event_log) = self.event_log {
+            handler::append_event(event_log, event);
+        }
+    }
+
+    fn announce_votes<I: IntoIterator<Item = (P, Option<P>)>>(&mut self, votes: I) {
+        for (voter, vote) in votes {
+            match vote {
+                Some(target) => { println!("[ ** ] {} votes to lynch {}", voter, target); }
+                None => { println!("[ ** ] {} votes for no lynch", voter); }
+            }
+        }
+    }
+
     fn cannot_lynch(&mut self, _: P) {
         println!("[ !! ] no such player to lynch");
     }
 
-    fn choose_lynch_target(&mut self, _: HashSet<&P>) -> Option<P> {
-        let name = util::input("town lynch target");
-        if name == "no lynch" {
-            None
-        } else {
-            Some(P::from(name))
-        }
+    fn cannot_skip_lynch(&mut self) {
+        self.lynch_required = true;
+        println!("[ !! ] the rules require a lynch");
+    }
+
+    fn choose_lynch_target(&mut self, alive: HashSet<&P>) -> Option<P> {
+        let Some(voting) = self.voting else {
+            let name = util::input("town lynch target");
+            return if name == "no lynch" { None } else { Some(P::from(name)) }
+        };
+        let mut voters = alive.into_iter().cloned().collect::<Vec<_>>();
+        voters.sort_by_key(|player| player.to_string());
+        let mut poll = Poll::new(voting, voters.clone(), voters, !self.lynch_required).tie_rule(self.tie_rule);
+        println!("[ ** ] the town is voting on whom to lynch");
+        let votes = poll.collect_votes();
+        Handler::<P>::record_event(self, &Event::Votes(votes.clone()));
+        self.announce_votes(votes);
+        let tally = poll.tally();
+        println!("[ ** ] vote tally: {}", tally);
+        tally.decision
     }
 }
@@ -1,32 +1,87 @@
 //! Contains the `Handler` trait, which is what the game uses to broadcast public game messages, and some implementations.
 
+pub mod voting;
+
 mod cli;
+mod remote;
 
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    fs::OpenOptions,
+    hash::Hash,
+    io::prelude::*,
+    path::Path
+};
+use serde::Serialize;
 use crate::{
     game::{
+        Estimate,
         Faction,
-        Role
+        MultiverseBackend,
+        Reveal,
+        Role,
+        event::Event,
+        state::State
     },
     player::Player
 };
-pub use self::cli::CliHandler;
+pub use self::{
+    cli::CliHandler,
+    remote::RemoteHandler
+};
 
 /// The game uses this trait to broadcast public game messages.
 pub trait Handler<P: Player> {
-    /// Called when one or more players die. Includes a copy of the player and the flipped role.
-    fn announce_deaths<I: IntoIterator<Item = (P, Role)>>(&mut self, _: I) {}
+    /// Called when one or more players die. Includes a copy of the player and what has been revealed about them, usually their role.
+    fn announce_deaths<I: IntoIterator<Item = (P, Reveal)>>(&mut self, _: I) {}
 
     /// Called at the start of the day to announce the probability table.
     ///
     /// The iterable can be enumerated to generate the secret IDs corresponding to the probabilities.
     fn announce_probability_table<I: IntoIterator<Item = Result<(f64, f64, f64), Faction>>>(&mut self, _: I) {}
 
+    /// Called at the start of the day instead of `announce_probability_table` if `sample_budget` returns a budget.
+    ///
+    /// Each probability is estimated with a 95% confidence interval. By default, the estimated values are announced using `announce_probability_table`.
+    fn announce_approximate_probability_table<I: IntoIterator<Item = Result<(Estimate, Estimate, Estimate), Faction>>>(&mut self, probability_table: I) {
+        self.announce_probability_table(probability_table.into_iter().map(|probabilities| probabilities.map(|(village, werewolves, dead)| (village.value, werewolves.value, dead.value))));
+    }
+
+    /// Called at the start of the day after the probability table has been announced, with the probability of each role for each player.
+    ///
+    /// The iterable can be enumerated to generate the secret IDs corresponding to the probabilities. Dead players are listed with their role if it has been determined. This is not called if `sample_budget` returns a budget.
+    fn announce_role_probability_table<I: IntoIterator<Item = Result<Vec<(Role, f64)>, Role>>>(&mut self, _: I) {}
+
+    /// The number of universes to sample to estimate the probability table, or `None` to compute the exact probability table.
+    ///
+    /// Sampling is useful for games where there are too many possible universes to compute the exact probability table in reasonable time. The budget is also passed to the multiverse using `State::set_sample_budget`, which allows the `LazyMultiverse` to estimate everything else from a sample as well.
+    fn sample_budget(&self) -> Option<usize> { None }
+
+    /// Called after the game has been started and after every phase, with the new game state.
+    ///
+    /// Implementations may use this to save the game, which can then be continued using `game::resume`.
+    fn checkpoint<M: MultiverseBackend>(&mut self, _: &State<P, M>) where P: Eq + Hash {}
+
+    /// Called whenever something happens in the game, including things which are not public, like night actions.
+    ///
+    /// Implementations may use this to write an event log, which can be used to reconstruct the game using `game::event::replay`.
+    fn record_event(&mut self, _: &Event<P>) {}
+
+    /// Called by handlers which collect individual votes using `Player::choose_vote`, once everyone has voted. Includes each voter and the player they voted to lynch, or `None` for a no-lynch vote, in the order in which the votes were cast.
+    ///
+    /// Voters who did not vote are not included.
+    fn announce_votes<I: IntoIterator<Item = (P, Option<P>)>>(&mut self, _: I) {}
+
     /// Called if an invalid player has been chosen as a lynch target.
     ///
     /// A call of this method is followed up by another `choose_lynch_target` call to restart the discussion.
     fn cannot_lynch(&mut self, _: P) {}
 
+    /// Called if no lynch target has been chosen, but the rules require a lynch.
+    ///
+    /// A call of this method is followed up by another `choose_lynch_target` call to restart the discussion.
+    fn cannot_skip_lynch(&mut self) {}
+
     /// Called at the start of the day determine the lynch target.
     ///
     /// Implementations should run the town discussion, implementing any appropriate discussion system, and return the lynched player.
@@ -34,3 +89,16 @@ pub trait Handler<P: Player> {
     /// Returning `None` stands for a no-lynch decision.
     fn choose_lynch_target(&mut self, _: HashSet<&P>) -> Option<P>;
 }
+
+/// Appends an event to a log file in the JSON Lines format, which can be read back and passed to `game::event::replay`.
+fn append_event<P: Serialize>(path: &Path, event: &Event<P>) {
+    if let Err(e) = serde_json::to_string(event)
+        .map_err(|e| e.to_string())
+        .and_then(|json| OpenOptions::new().create(true).append(true).open(path)
+            .and_then(|mut file| writeln!(file, "{}", json))
+            .map_err(|e| e.to_string())
+        )
+    {
+        println!("[ !! ] failed to write event log: {}", e);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf
+};
+use crate::{
+    game::{
+        Estimate,
+        Faction,
+        Reveal,
+        event::Event
+    },
+    handler::{
+        self,
+        Handler,
+        voting::{
+            Choice,
+            Poll,
+            TieRule,
+            VotingSystem
+        }
+    },
+    player::RemotePlayer
+};
+
+/// A game handler for games without a moderator, where each player is connected through their own private channel.
+///
+/// Public messages are sent to all players as well as printed to stdout. The town lynch target is determined by a vote of all living players, who vote in turn using the configured voting system. By default, a player is lynched if more than half of the voters vote for them, otherwise there is no lynch.
+#[derive(Debug, Clone)]
+pub struct RemoteHandler {
+    players: Vec<RemotePlayer>,
+    /// If this is set, all game events are appended to this file.
+    pub event_log: Option<PathBuf>,
+    /// If this is set, the probability table is estimated from this many sampled universes.
+    pub sample_budget: Option<usize>,
+    /// The voting system used to determine the town lynch target.
+    pub voting: VotingSystem,
+    /// How ties are resolved in the lynch vote.
+    pub tie_rule: TieRule,
+    /// Whether the rules require a lynch, so players can't vote for no lynch. This should be set from `Rules::no_lynch`, and is also set by `cannot_skip_lynch`.
+    pub lynch_required: bool
+}
+
+impl RemoteHandler {
+    /// Creates a new handler which broadcasts public messages to the given players.
+    pub fn new(players: Vec<RemotePlayer>) -> RemoteHandler {
+        RemoteHandler {
+            players,
+            event_log: None,
+            sample_budget: None,
+            voting: VotingSystem::default(),
+            tie_rule: TieRule::default(),
+            lynch_required: false
+        }
+    }
+
+    /// Sends a public message to all players.
+    pub fn broadcast(&self, msg: &str) {
+        let msg = format!("[ ** ] {}", msg);
+        println!("{}", msg);
+        for player in &self.players {
+            player.send(&msg);
+        }
+    }
+}
+
+impl Handler<RemotePlayer> for RemoteHandler {
+    fn announce_deaths<I: IntoIterator<Item = (RemotePlayer, Reveal)>>(&mut self, deaths: I) {
+        for (player, reveal) in deaths {
+            match reveal {
+                Reveal::Role(role) => { self.broadcast(&format!("{} died and was {}", player, role)); }
+                Reveal::Faction(faction) => { self.broadcast(&format!("{} died and was in the {} faction", player, faction)); }
+                Reveal::Nothing => { self.broadcast(&format!("{} died", player)); }
+            }
+        }
+    }
+
+    fn announce_probability_table<I: IntoIterator<Item = Result<(f64, f64, f64), Faction>>>(&mut self, probability_table: I) {
+        for (player_idx, probabilities) in probability_table.into_iter().enumerate() {
+            match probabilities {
+                Ok((village_ratio, werewolves_ratio, dead_ratio)) => {
+                    self.broadcast(&format!("{}: {}% village, {}% werewolf, {}% dead", player_idx, (village_ratio * 100.0).round() as u8, (werewolves_ratio * 100.0).round() as u8, (dead_ratio * 100.0).round() as u8));
+                }
+                Err(faction) => {
+                    self.broadcast(&format!("{}: dead (was {})", player_idx, faction));
+                }
+            }
+        }
+    }
+
+    fn announce_approximate_probability_table<I: IntoIterator<Item = Result<(Estimate, Estimate, Estimate), Faction>>>(&mut self, probability_table: I) {
+        for (player_idx, probabilities) in probability_table.into_iter().enumerate() {
+            match probabilities {
+                Ok((village, werewolves, dead)) => {
+                    self.broadcast(&format!("{}: {} village, {} werewolf, {} dead", player_idx, village, werewolves, dead));
+                }
+                Err(faction) => {
+                    self.broadcast(&format!("{}: dead (was {})", player_idx, faction));
+                }
+            }
+        }
+    }
+
+    fn sample_budget(&self) -> Option<usize> {
+        self.sample_budget
+    }
+
+    fn record_event(&mut self, event: &Event<RemotePlayer>) {
+        if let Some(ref event_log) = self.event_log {
+            handler::append_event(event_log, event);
+        }
+    }
+
+    fn announce_votes<I: IntoIterator<Item = (RemotePlayer, Option<RemotePlayer>)>>(&mut self, votes: I) {
+        for (voter, vote) in votes {
+            match vote {
+                Some(target) => { self.broadcast(&format!("{} votes to lynch {}", voter, target)); }
+                None => { self.broadcast(&format!("{} votes for no lynch", voter)); }
+            }
+        }
+    }
+
+    fn cannot_lynch(&mut self, player: RemotePlayer) {
+        self.broadcast(&format!("{} cannot be lynched, voting again", player));
+    }
+
+    fn cannot_skip_lynch(&mut self) {
+        self.lynch_required = true;
+        self.broadcast("the rules require a lynch, voting again");
+    }
+
+    fn choose_lynch_target(&mut self, alive: HashSet<&RemotePlayer>) -> Option<RemotePlayer> {
+        let mut voters = alive.into_iter().cloned().collect::<Vec<_>>();
+        voters.sort_by_key(|player| player.to_string());
+        let mut poll = Poll::new(self.voting, voters.clone(), voters.clone(), !self.lynch_required).tie_rule(self.tie_rule);
+        self.broadcast(&format!("the town is voting on whom to lynch ({} voting)", self.voting));
+        if !self.voting.allows_multiple_choices() {
+            // one option per ballot, so the votes can be collected from the players directly
+            let votes = poll.collect_votes();
+            self.record_event(&Event::Votes(votes.clone()));
+            self.announce_votes(votes);
+            let tally = poll.tally();
+            self.broadcast(&format!("vote tally: {}", tally));
+            return tally.decision
+        }
+        let prompt = if self.voting == VotingSystem::RankedRunoff {
+            "town lynch targets in order of preference, separated by commas [leave blank for no lynch]"
+        } else {
+            "town lynch targets, separated by commas [leave blank for no lynch]"
+        };
+        for voter in &voters {
+            let ballot = loop {
+                let answer = voter.input(prompt).unwrap_or_default();
+                let ballot = if answer.trim().is_empty() {
+                    Some(vec![Choice::NoLynch])
+                } else {
+                    answer.split(',')
+                        .map(|name| match name.trim() {
+                            "no lynch" => Some(Choice::NoLynch),
+                            name => voters.iter().find(|target| target.to_string() == name).cloned().map(Choice::Lynch)
+                        })
+                        .collect()
+                };
+                let ballot = match ballot {
+                    Some(ballot) => ballot,
+                    None => {
+                        voter.send("[ !! ] no such player");
+                        continue;
+                    }
+                };
+                match poll.vote(voter, ballot.clone()) {
+                    Ok(()) => break ballot,
+                    Err(e) => { voter.send(&format!("[ !! ] {}", e)); }
+                }
+            };
+            match &ballot[..] {
+                [Choice::Lynch(target)] => { self.broadcast(&format!("{} votes to lynch {}", voter, target)); }
+                [Choice::NoLynch] => { self.broadcast(&format!("{} votes for no lynch", voter)); }
+                _ => { self.broadcast(&format!("{} votes for {}", voter, ballot.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))); }
+            }
+        }
+        let tally = poll.tally();
+        self.broadcast(&format!("vote tally: {}", tally));
+        tally.decision
+    }
+}
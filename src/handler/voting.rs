@@ -0,0 +1,358 @@
+//! A voting subsystem which handlers can use to determine the town lynch target.
+//!
+//! A `Poll` collects the ballots of the voters, who may change them until the poll closes. Its `Tally` contains the vote counts, which can be announced publicly, and the lynch decision, which is what `Handler::choose_lynch_target` should return. Votes can be collected from the players using `Player::choose_vote`.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    str::FromStr,
+    time::Instant
+};
+use serde::{
+    Deserialize,
+    Serialize
+};
+use crate::player::Player;
+
+/// An option on a ballot.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+pub enum Choice<P> {
+    /// Lynch the given player.
+    Lynch(P),
+    /// Lynch no one.
+    NoLynch
+}
+
+impl<P: fmt::Display> fmt::Display for Choice<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Choice::Lynch(ref player) => player.fmt(f),
+            Choice::NoLynch => write!(f, "no lynch")
+        }
+    }
+}
+
+/// Determines how the lynch decision is made from the ballots.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VotingSystem {
+    /// Each voter chooses one option. The option with the most votes wins.
+    Plurality,
+    /// Each voter chooses one option. An option needs the votes of more than half of the voters to win, otherwise no one is lynched.
+    ///
+    /// The majority is counted against all voters of the poll, so voters who haven't voted or who abstained count as votes against every option.
+    #[default]
+    Majority,
+    /// Each voter may choose any number of options. The option chosen by the most voters wins.
+    Approval,
+    /// Each voter ranks any number of options. Each ballot counts for its highest-ranked option which has not been eliminated. As long as no option has more than half of these votes, the options with the fewest votes are eliminated.
+    RankedRunoff
+}
+
+impl VotingSystem {
+    /// Whether a ballot may contain more than one option.
+    pub fn allows_multiple_choices(&self) -> bool {
+        match *self {
+            VotingSystem::Plurality | VotingSystem::Majority => false,
+            VotingSystem::Approval | VotingSystem::RankedRunoff => true
+        }
+    }
+}
+
+impl FromStr for VotingSystem {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<VotingSystem, ()> {
+        match &s.to_lowercase()[..] {
+            "plurality" => Ok(VotingSystem::Plurality),
+            "majority" => Ok(VotingSystem::Majority),
+            "approval" => Ok(VotingSystem::Approval),
+            "ranked-runoff" | "runoff" => Ok(VotingSystem::RankedRunoff),
+            _ => Err(())
+        }
+    }
+}
+
+impl fmt::Display for VotingSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            VotingSystem::Plurality => write!(f, "plurality"),
+            VotingSystem::Majority => write!(f, "majority"),
+            VotingSystem::Approval => write!(f, "approval"),
+            VotingSystem::RankedRunoff => write!(f, "ranked-runoff")
+        }
+    }
+}
+
+/// Determines the outcome of a vote if several options are tied for the win.
+///
+/// This applies to plurality and approval voting, as well as to ranked runoff voting if all remaining options are tied.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TieRule {
+    /// No one is lynched.
+    #[default]
+    NoLynch,
+    /// The tied option whose earliest vote was cast first wins. Changing a ballot counts as casting it again.
+    FirstVote
+}
+
+impl FromStr for TieRule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<TieRule, ()> {
+        match &s.to_lowercase()[..] {
+            "no-lynch" => Ok(TieRule::NoLynch),
+            "first-vote" => Ok(TieRule::FirstVote),
+            _ => Err(())
+        }
+    }
+}
+
+impl fmt::Display for TieRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TieRule::NoLynch => write!(f, "no-lynch"),
+            TieRule::FirstVote => write!(f, "first-vote")
+        }
+    }
+}
+
+/// An error returned by `Poll::vote`.
+#[derive(Debug, thiserror::Error)]
+pub enum VoteError {
+    /// The deadline of the poll has passed.
+    #[error("voting has closed")]
+    Closed,
+    /// The player is not allowed to vote in this poll.
+    #[error("this player is not allowed to vote")]
+    NotAVoter,
+    /// The ballot contains a player who cannot be lynched.
+    #[error("this player cannot be lynched")]
+    InvalidCandidate,
+    /// The ballot contains the no lynch option, but not lynching is not allowed.
+    #[error("no lynch is not an option")]
+    NoLynchNotAllowed,
+    /// The ballot contains more than one option, but the voting system only allows one.
+    #[error("only one option may be chosen")]
+    TooManyChoices
+}
+
+/// Collects the ballots of the voters for a single lynch decision.
+#[derive(Debug, Clone)]
+pub struct Poll<P> {
+    system: VotingSystem,
+    tie_rule: TieRule,
+    voters: Vec<P>,
+    candidates: Vec<P>,
+    no_lynch: bool,
+    deadline: Option<Instant>,
+    /// The current ballot of each voter who has voted, in the order in which they were last changed.
+    ballots: Vec<(P, Vec<Choice<P>>)>
+}
+
+impl<P: Eq + Clone> Poll<P> {
+    /// Creates a poll in which the given voters decide which of the candidates to lynch. If `no_lynch` is `true`, lynching no one is also an option.
+    ///
+    /// The poll stays open until a deadline is set using `Poll::deadline`, and ties are resolved using the default `TieRule` unless a different one is set using `Poll::tie_rule`.
+    pub fn new(system: VotingSystem, voters: Vec<P>, candidates: Vec<P>, no_lynch: bool) -> Poll<P> {
+        Poll {
+            system,
+            tie_rule: TieRule::default(),
+            voters,
+            candidates,
+            no_lynch,
+            deadline: None,
+            ballots: Vec::default()
+        }
+    }
+
+    /// Sets how ties are resolved.
+    pub fn tie_rule(mut self, tie_rule: TieRule) -> Poll<P> {
+        self.tie_rule = tie_rule;
+        self
+    }
+
+    /// Closes the poll at the given time. Until then, voters may change their ballots.
+    pub fn deadline(mut self, deadline: Instant) -> Poll<P> {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Returns the voting system used by this poll.
+    pub fn system(&self) -> VotingSystem {
+        self.system
+    }
+
+    /// Returns the players who may vote in this poll.
+    pub fn voters(&self) -> &[P] {
+        &self.voters
+    }
+
+    /// Returns the players who may be lynched.
+    pub fn candidates(&self) -> &[P] {
+        &self.candidates
+    }
+
+    /// Returns `true` if lynching no one is an option.
+    pub fn allows_no_lynch(&self) -> bool {
+        self.no_lynch
+    }
+
+    /// Returns `true` if the deadline has not passed yet.
+    pub fn is_open(&self) -> bool {
+        self.deadline.is_none_or(|deadline| Instant::now() < deadline)
+    }
+
+    /// Returns `true` if every voter has cast a ballot.
+    pub fn is_complete(&self) -> bool {
+        self.voters.iter().all(|voter| self.ballot(voter).is_some())
+    }
+
+    /// Returns the current ballot of the given voter, or `None` if they haven't voted.
+    pub fn ballot(&self, voter: &P) -> Option<&[Choice<P>]> {
+        self.ballots.iter()
+            .find(|(ballot_voter, _)| ballot_voter == voter)
+            .map(|(_, ballot)| &ballot[..])
+    }
+
+    /// Casts the given voter's ballot, replacing their previous ballot if they have already voted.
+    ///
+    /// The ballot lists the chosen options, in order of preference for ranked runoff voting. Options listed more than once only count once. An empty ballot is an abstention.
+    pub fn vote(&mut self, voter: &P, ballot: Vec<Choice<P>>) -> Result<(), VoteError> {
+        if !self.is_open() { return Err(VoteError::Closed); }
+        if !self.voters.contains(voter) { return Err(VoteError::NotAVoter); }
+        let mut deduplicated = Vec::with_capacity(ballot.len());
+        for choice in ballot {
+            match choice {
+                Choice::Lynch(ref target) => if !self.candidates.contains(target) { return Err(VoteError::InvalidCandidate); },
+                Choice::NoLynch => if !self.no_lynch { return Err(VoteError::NoLynchNotAllowed); }
+            }
+            if !deduplicated.contains(&choice) {
+                deduplicated.push(choice);
+            }
+        }
+        if deduplicated.len() > 1 && !self.system.allows_multiple_choices() { return Err(VoteError::TooManyChoices); }
+        self.ballots.retain(|(ballot_voter, _)| ballot_voter != voter);
+        self.ballots.push((voter.clone(), deduplicated));
+        Ok(())
+    }
+
+    /// Counts the ballots and determines the lynch decision.
+    pub fn tally(&self) -> Tally<P> {
+        match self.system {
+            VotingSystem::Plurality | VotingSystem::Approval => {
+                let votes = self.count(|_| true);
+                let decision = self.break_tie(&votes);
+                Tally { votes, decision }
+            }
+            VotingSystem::Majority => {
+                let votes = self.count(|_| true);
+                let decision = votes.first()
+                    .filter(|&&(_, num_votes)| num_votes * 2 > self.voters.len())
+                    .and_then(|(choice, _)| decision(choice));
+                Tally { votes, decision }
+            }
+            VotingSystem::RankedRunoff => {
+                let mut eliminated = HashSet::new();
+                loop {
+                    let votes = self.count(|choice_idx| !eliminated.contains(&choice_idx));
+                    let total = votes.iter().map(|&(_, num_votes)| num_votes).sum::<usize>();
+                    let (fewest, most) = match (votes.last(), votes.first()) {
+                        (Some(&(_, fewest)), Some(&(_, most))) => (fewest, most),
+                        _ => return Tally { votes, decision: None }
+                    };
+                    if most * 2 > total || fewest == most {
+                        let decision = self.break_tie(&votes);
+                        return Tally { votes, decision };
+                    }
+                    for (choice, _) in votes.iter().filter(|&&(_, num_votes)| num_votes == fewest) {
+                        eliminated.insert(self.choice_idx(choice));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Counts how many ballots choose each option, sorted by number of votes. Ties are listed in the order of their earliest vote.
+    ///
+    /// For plurality, majority, and ranked runoff voting, each ballot counts only for its first option which is not excluded by `include`. For approval voting, each ballot counts for all of its options.
+    fn count(&self, include: impl Fn(usize) -> bool) -> Vec<(Choice<P>, usize)> {
+        let mut votes = Vec::<(Choice<P>, usize)>::default();
+        for (_, ballot) in &self.ballots {
+            let counted = ballot.iter().filter(|choice| include(self.choice_idx(choice)));
+            let counted = if self.system == VotingSystem::Approval {
+                counted.collect::<Vec<_>>()
+            } else {
+                counted.take(1).collect()
+            };
+            for choice in counted {
+                if let Some((_, num_votes)) = votes.iter_mut().find(|(counted_choice, _)| counted_choice == choice) {
+                    *num_votes += 1;
+                } else {
+                    votes.push((choice.clone(), 1));
+                }
+            }
+        }
+        // stable sort, so ties stay in the order of their earliest vote
+        votes.sort_by(|(_, num_votes1), (_, num_votes2)| num_votes2.cmp(num_votes1));
+        votes
+    }
+
+    /// Returns the decision for the option with the most votes, applying the tie rule if there is more than one.
+    fn break_tie(&self, votes: &[(Choice<P>, usize)]) -> Option<P> {
+        let (winner, most) = votes.first()?;
+        if self.tie_rule == TieRule::NoLynch && votes.get(1).is_some_and(|(_, num_votes)| num_votes == most) { return None; }
+        decision(winner)
+    }
+
+    /// An index identifying an option, used to track eliminated options in ranked runoff voting.
+    fn choice_idx(&self, choice: &Choice<P>) -> usize {
+        match *choice {
+            Choice::Lynch(ref target) => self.candidates.iter().position(|candidate| candidate == target).expect("ballot contains invalid candidate"),
+            Choice::NoLynch => self.candidates.len()
+        }
+    }
+}
+
+impl<P: Player + Eq + Clone> Poll<P> {
+    /// Asks each voter for their vote using `Player::choose_vote`, in the order in which the voters were given, and casts it as a ballot with a single option.
+    ///
+    /// Returns each voter whose vote was valid along with their vote, which can be passed to `Handler::announce_votes`.
+    pub fn collect_votes(&mut self) -> Vec<(P, Option<P>)> {
+        let mut votes = Vec::default();
+        for voter in self.voters.clone() {
+            let vote = voter.choose_vote(self.candidates.iter().collect());
+            let ballot = vec![vote.clone().map_or(Choice::NoLynch, Choice::Lynch)];
+            if self.vote(&voter, ballot).is_ok() {
+                votes.push((voter, vote));
+            }
+        }
+        votes
+    }
+}
+
+fn decision<P: Clone>(choice: &Choice<P>) -> Option<P> {
+    match *choice {
+        Choice::Lynch(ref target) => Some(target.clone()),
+        Choice::NoLynch => None
+    }
+}
+
+/// The result of a poll.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Tally<P> {
+    /// The number of votes for each option which received any, sorted by number of votes. For ranked runoff voting, these are the counts from the final round.
+    pub votes: Vec<(Choice<P>, usize)>,
+    /// The player to lynch, or `None` if no one is lynched.
+    pub decision: Option<P>
+}
+
+impl<P: fmt::Display> fmt::Display for Tally<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.votes.is_empty() { return write!(f, "no votes"); }
+        for (i, (choice, num_votes)) in self.votes.iter().enumerate() {
+            if i > 0 { write!(f, ", ")?; }
+            write!(f, "{}: {} vote{}", choice, num_votes, if *num_votes == 1 { "" } else { "s" })?;
+        }
+        Ok(())
+    }
+}
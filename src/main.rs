@@ -3,26 +3,68 @@
 
 use std::{
     env,
+    fs,
+    iter,
     io::{
         prelude::*,
         stdin,
         stdout
     },
+    path::PathBuf,
     str::FromStr,
     string::ToString
 };
 use quantum_werewolf::{
     game::{
         self,
+        KillRule,
+        LazyMultiverse,
+        Multiverse,
+        MultiverseBackend,
+        NightActionResult,
         Role,
-        state::Signups
+        state::{
+            Rules,
+            Signups
+        }
+    },
+    game::{
+        event::{
+            self,
+            Event
+        },
+        state::State
+    },
+    handler::{
+        CliHandler,
+        Handler,
+        voting::{
+            TieRule,
+            VotingSystem
+        }
     },
-    handler::CliHandler,
     player::CliPlayer
 };
 
+#[derive(Default)]
 struct Args {
-    roles: Option<Vec<Role>>
+    roles: Option<Vec<Role>>,
+    rules_file: Option<PathBuf>,
+    kill_rule: Option<KillRule>,
+    seed: Option<u64>,
+    socket: Option<PathBuf>,
+    serve: bool,
+    http: bool,
+    port: Option<u16>,
+    save: Option<PathBuf>,
+    resume: Option<PathBuf>,
+    log: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    lazy: bool,
+    samples: Option<usize>,
+    role_probabilities: bool,
+    voting: Option<VotingSystem>,
+    tie_rule: Option<TieRule>
 }
 
 impl Args {
@@ -34,34 +76,134 @@ impl Args {
                 .collect()
         );
     }
-}
 
-impl Default for Args {
-    fn default() -> Args {
-        Args {
-            roles: None
+    fn set_kill_rule(&mut self, kill_rule: &str) {
+        self.kill_rule = Some(KillRule::from_str(kill_rule).expect("no such kill rule"));
+    }
+
+    /// Loads the rules from the file given using `--rules`, if any, and applies any rules given as separate options.
+    fn rules(&self) -> Rules {
+        let mut rules = self.rules_file.as_ref().map_or_else(Rules::default, |path| {
+            let json = fs::read_to_string(path).expect("failed to read rules file");
+            serde_json::from_str(&json).expect("failed to parse rules file")
+        });
+        if let Some(kill_rule) = self.kill_rule {
+            rules.kill_rule = kill_rule;
         }
+        rules
+    }
+
+    fn set_seed(&mut self, seed: &str) {
+        self.seed = Some(seed.parse().expect("seed must be a nonnegative integer"));
+    }
+
+    fn set_port(&mut self, port: &str) {
+        self.port = Some(port.parse().expect("port must be an integer between 0 and 65535"));
+    }
+
+    fn set_samples(&mut self, samples: &str) {
+        self.samples = Some(samples.parse().expect("number of samples must be a nonnegative integer"));
+    }
+
+    fn set_voting(&mut self, voting: &str) {
+        self.voting = Some(VotingSystem::from_str(voting).expect("no such voting system"));
+    }
+
+    fn set_tie_rule(&mut self, tie_rule: &str) {
+        self.tie_rule = Some(TieRule::from_str(tie_rule).expect("no such tie rule"));
     }
 }
 
 fn args() -> Args {
     enum ArgsMode {
-        Roles
+        Roles,
+        Rules,
+        KillRule,
+        Seed,
+        Socket,
+        Port,
+        Save,
+        Resume,
+        Log,
+        Replay,
+        Samples,
+        Voting,
+        TieRule
     }
 
     let mut args = Args::default();
     let mut mode = None;
     for arg in env::args().skip(1) {
         match mode {
-            Some(ArgsMode::Roles) => { args.set_roles(&arg); }
+            Some(ArgsMode::Roles) => { args.set_roles(&arg); mode = None; }
+            Some(ArgsMode::Rules) => { args.rules_file = Some(PathBuf::from(arg)); mode = None; }
+            Some(ArgsMode::KillRule) => { args.set_kill_rule(&arg); mode = None; }
+            Some(ArgsMode::Seed) => { args.set_seed(&arg); mode = None; }
+            Some(ArgsMode::Socket) => { args.socket = Some(PathBuf::from(arg)); mode = None; }
+            Some(ArgsMode::Port) => { args.set_port(&arg); mode = None; }
+            Some(ArgsMode::Save) => { args.save = Some(PathBuf::from(arg)); mode = None; }
+            Some(ArgsMode::Resume) => { args.resume = Some(PathBuf::from(arg)); mode = None; }
+            Some(ArgsMode::Log) => { args.log = Some(PathBuf::from(arg)); mode = None; }
+            Some(ArgsMode::Replay) => { args.replay = Some(PathBuf::from(arg)); mode = None; }
+            Some(ArgsMode::Samples) => { args.set_samples(&arg); mode = None; }
+            Some(ArgsMode::Voting) => { args.set_voting(&arg); mode = None; }
+            Some(ArgsMode::TieRule) => { args.set_tie_rule(&arg); mode = None; }
             None => {
                 if arg != "-" && arg.starts_with('-') {
                     // flags and options
                     if arg.starts_with("--") {
                         if arg == "--roles" {
                             mode = Some(ArgsMode::Roles);
-                        } else if arg.starts_with("--roles=") {
-                            args.set_roles(&arg["--roles=".len()..]);
+                        } else if let Some(roles) = arg.strip_prefix("--roles=") {
+                            args.set_roles(roles);
+                        } else if arg == "--rules" {
+                            mode = Some(ArgsMode::Rules);
+                        } else if let Some(path) = arg.strip_prefix("--rules=") {
+                            args.rules_file = Some(PathBuf::from(path));
+                        } else if arg == "--kill-rule" {
+                            mode = Some(ArgsMode::KillRule);
+                        } else if let Some(kill_rule) = arg.strip_prefix("--kill-rule=") {
+                            args.set_kill_rule(kill_rule);
+                        } else if arg == "--seed" {
+                            mode = Some(ArgsMode::Seed);
+                        } else if let Some(seed) = arg.strip_prefix("--seed=") {
+                            args.set_seed(seed);
+                        } else if arg == "--socket" {
+                            mode = Some(ArgsMode::Socket);
+                        } else if let Some(path) = arg.strip_prefix("--socket=") {
+                            args.socket = Some(PathBuf::from(path));
+                        } else if arg == "--port" {
+                            mode = Some(ArgsMode::Port);
+                        } else if let Some(port) = arg.strip_prefix("--port=") {
+                            args.set_port(port);
+                        } else if arg == "--save" {
+                            mode = Some(ArgsMode::Save);
+                        } else if let Some(path) = arg.strip_prefix("--save=") {
+                            args.save = Some(PathBuf::from(path));
+                        } else if arg == "--resume" {
+                            mode = Some(ArgsMode::Resume);
+                        } else if let Some(path) = arg.strip_prefix("--resume=") {
+                            args.resume = Some(PathBuf::from(path));
+                        } else if arg == "--log" {
+                            mode = Some(ArgsMode::Log);
+                        } else if let Some(path) = arg.strip_prefix("--log=") {
+                            args.log = Some(PathBuf::from(path));
+                        } else if arg == "--lazy" {
+                            args.lazy = true;
+                        } else if arg == "--role-probabilities" {
+                            args.role_probabilities = true;
+                        } else if arg == "--samples" {
+                            mode = Some(ArgsMode::Samples);
+                        } else if let Some(samples) = arg.strip_prefix("--samples=") {
+                            args.set_samples(samples);
+                        } else if arg == "--voting" {
+                            mode = Some(ArgsMode::Voting);
+                        } else if let Some(voting) = arg.strip_prefix("--voting=") {
+                            args.set_voting(voting);
+                        } else if arg == "--tie-rule" {
+                            mode = Some(ArgsMode::TieRule);
+                        } else if let Some(tie_rule) = arg.strip_prefix("--tie-rule=") {
+                            args.set_tie_rule(tie_rule);
                         } else {
                             panic!("unrecognized flag: {:?}", arg);
                         }
@@ -71,6 +213,13 @@ fn args() -> Args {
                             panic!("unrecognized flag: -{:?}", short_flag);
                         }
                     }
+                } else if arg == "replay" {
+                    // subcommands
+                    mode = Some(ArgsMode::Replay);
+                } else if arg == "serve" {
+                    args.serve = true;
+                } else if arg == "http" {
+                    args.http = true;
                 } else {
                     // positional args
                     panic!("unexpected positional argument: {:?}", arg);
@@ -85,7 +234,7 @@ fn join<S: ToString, I: IntoIterator<Item=S>>(words: I) -> String {
     let mut words = words.into_iter().map(|word| word.to_string()).collect::<Vec<_>>();
     match words.len() {
         0 => "no one".to_owned(),
-        1 => format!("{}", words.swap_remove(0)),
+        1 => words.swap_remove(0),
         2 => format!("{} and {}", words.swap_remove(0), words.swap_remove(0)),
         _ => {
             let last = words.pop().unwrap();
@@ -94,9 +243,204 @@ fn join<S: ToString, I: IntoIterator<Item=S>>(words: I) -> String {
     }
 }
 
+/// Runs a game without a moderator. Each player connects from their own terminal, and `connections` yields a reader and a writer for each new connection.
+fn main_remote<R, W, I>(args: Args, connections: I, connect_hint: &str)
+where R: Read + Send + 'static, W: Write + Send + 'static, I: Iterator<Item = (R, W)> + Send + 'static {
+    use std::{
+        io::BufReader,
+        sync::{
+            Arc,
+            Mutex
+        },
+        thread
+    };
+    use quantum_werewolf::{
+        handler::RemoteHandler,
+        player::RemotePlayer
+    };
+
+    fn sign_up<R: Read + Send + 'static, W: Write + Send + 'static>(reader: R, mut writer: W, signups: &Mutex<Option<Signups<RemotePlayer>>>) -> std::io::Result<()> {
+        let mut reader = BufReader::new(reader);
+        loop {
+            let name = match RemotePlayer::read_name(&mut reader, &mut writer)? {
+                Some(name) => name,
+                None => return Ok(())
+            };
+            if name.is_empty() { continue; }
+            let mut signups = signups.lock().expect("signups lock poisoned");
+            let game_state = match *signups {
+                Some(ref mut game_state) => game_state,
+                None => {
+                    writeln!(writer, "[ !! ] the game has already started")?;
+                    return Ok(());
+                }
+            };
+            if game_state.players().iter().any(|player| player.to_string() == name) {
+                writeln!(writer, "[ !! ] duplicate player name")?;
+                continue;
+            }
+            println!("[ ** ] {} signed up", name);
+            writeln!(writer, "[ ** ] signed up as {}, waiting for the game to start", name)?;
+            game_state.sign_up(RemotePlayer::new(name, reader, writer));
+            return Ok(());
+        }
+    }
+
+    let rules = args.rules();
+    let signups = Arc::new(Mutex::new(Some(args.seed.map_or_else(Signups::default, Signups::with_seed))));
+    {
+        let signups = Arc::clone(&signups);
+        thread::spawn(move || {
+            for (reader, writer) in connections {
+                let signups = Arc::clone(&signups);
+                thread::spawn(move || sign_up(reader, writer, &signups));
+            }
+        });
+    }
+    println!("[ ?? ] waiting for players, connect using `{}` [press return to start the game]", connect_hint);
+    stdin().read_line(&mut String::new()).expect("failed to read start signal");
+    let game_state = signups.lock().expect("signups lock poisoned").take().expect("game started twice");
+    let mut handler = RemoteHandler::new(game_state.players().to_owned());
+    handler.event_log = args.log;
+    handler.sample_budget = args.samples;
+    handler.voting = args.voting.unwrap_or_default();
+    handler.tie_rule = args.tie_rule.unwrap_or_default();
+    handler.lynch_required = !rules.no_lynch;
+    let roles = args.roles.unwrap_or_else(|| game::default_roles(game_state.num_players()));
+    let winners = if args.lazy {
+        game::run_with_backend::<_, _, LazyMultiverse>(handler.clone(), game_state, roles, rules)
+    } else {
+        game::run_with_roles(handler.clone(), game_state, roles, rules)
+    };
+    handler.broadcast(&format!("The winners are: {}", join(winners.expect("failed to run game"))));
+}
+
+/// Runs a game without a moderator. Each player connects to the given Unix domain socket from their own terminal.
+#[cfg(unix)]
+fn main_socket(args: Args, path: PathBuf) {
+    use std::os::unix::net::UnixListener;
+
+    let listener = UnixListener::bind(&path).expect("failed to create socket");
+    let connections = iter::repeat_with(move || listener.accept())
+        .filter_map(|connection| connection.ok())
+        .filter_map(|(stream, _)| Some((stream.try_clone().ok()?, stream)));
+    main_remote(args, connections, &format!("nc -U {}", path.display()));
+    let _ = fs::remove_file(&path);
+}
+
+/// Runs a game without a moderator. Each player connects to the given TCP port from their own terminal, possibly on another computer.
+fn main_serve(args: Args, port: u16) {
+    use std::net::{
+        Ipv4Addr,
+        TcpListener
+    };
+
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).expect("failed to listen on port");
+    let connections = iter::repeat_with(move || listener.accept())
+        .filter_map(|connection| connection.ok())
+        .filter_map(|(stream, _)| Some((stream.try_clone().ok()?, stream)));
+    main_remote(args, connections, &format!("nc <host> {}", port));
+}
+
+/// Runs an HTTP server which lets clients create and play games using a JSON API, see `quantum_werewolf::server::rest`.
+fn main_http(port: u16) {
+    use std::net::{
+        Ipv4Addr,
+        TcpListener
+    };
+    use quantum_werewolf::server::rest;
+
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).expect("failed to listen on port");
+    println!("[ ** ] serving the game API on port {}", port);
+    rest::serve(listener).expect("failed to accept connection");
+}
+
+/// Reconstructs a game from an event log written using `--log`, and prints every intermediate game state.
+fn main_replay<M: MultiverseBackend>(path: PathBuf) {
+    let log = fs::read_to_string(path).expect("failed to read event log");
+    let events = log.lines()
+        .map(|line| serde_json::from_str::<Event<CliPlayer>>(line).expect("failed to parse event log"));
+    let mut handler = CliHandler::default();
+    let result = event::replay(events, |game_state: &State<CliPlayer, M>| match *game_state {
+        State::Signups(_) => {}
+        State::Night(ref night) => { println!("[ ** ] night falls, alive: {}", join(night.alive())); }
+        State::Day(ref day) => {
+            println!("[ ** ] day breaks, alive: {}", join(day.alive()));
+            for (player, result) in day.night_action_results() {
+                match result {
+                    NightActionResult::Investigation(target, faction) => { println!("[ __ ] @{}: investigation result: {}'s faction is {}", player, target, faction); }
+                    NightActionResult::Vision(target, role) => { println!("[ __ ] @{}: seer result: {}'s role is {}", player, target, role); }
+                    NightActionResult::Seance(target, cause) => { println!("[ __ ] @{}: medium result: {} died of {}", player, target, cause); }
+                }
+            }
+            match day.probability_table() {
+                Ok(probability_table) => Handler::<CliPlayer>::announce_probability_table(&mut handler, probability_table),
+                Err(e) => { println!("[ !! ] {}", e); }
+            }
+        }
+        State::Complete(ref complete) => { println!("[ ** ] The winners are: {}", join(&complete.winners)); }
+    });
+    if let Err(e) = result {
+        println!("[ !! ] {}", e);
+    }
+}
+
 fn main() {
-    let args = args();
-    let mut game_state = Signups::default();
+    let mut args = args();
+    if let Some(path) = args.replay.take() {
+        return if args.lazy { main_replay::<LazyMultiverse>(path) } else { main_replay::<Multiverse>(path) };
+    }
+    if args.http {
+        if args.log.is_some() {
+            panic!("--log is not supported with qww http");
+        }
+        return main_http(args.port.expect("qww http requires --port"));
+    }
+    if let Some(log) = &args.log {
+        if args.resume.is_none() {
+            // start a new event log for a new game
+            fs::write(log, "").expect("failed to create event log");
+        }
+    }
+    if args.serve {
+        if args.socket.is_some() {
+            panic!("--socket cannot be used with qww serve");
+        }
+        if args.save.is_some() || args.resume.is_some() {
+            panic!("saving and resuming games is not supported with qww serve");
+        }
+        let port = args.port.expect("qww serve requires --port");
+        return main_serve(args, port);
+    }
+    if let Some(path) = args.socket.take() {
+        if args.save.is_some() || args.resume.is_some() {
+            panic!("saving and resuming games is not supported with --socket");
+        }
+        #[cfg(unix)] { return main_socket(args, path); }
+        #[cfg(not(unix))] { panic!("--socket is only supported on Unix ({})", path.display()); }
+    }
+    let rules = args.rules();
+    let handler = CliHandler {
+        // when resuming a game, keep saving to the same file by default
+        save_file: args.save.or_else(|| args.resume.clone()),
+        event_log: args.log,
+        sample_budget: args.samples,
+        role_probabilities: args.role_probabilities,
+        voting: args.voting,
+        tie_rule: args.tie_rule.unwrap_or_default(),
+        lynch_required: !rules.no_lynch
+    };
+    if let Some(path) = args.resume {
+        let json = fs::read_to_string(path).expect("failed to read saved game");
+        let winners = if args.lazy {
+            game::resume(handler, State::<CliPlayer, LazyMultiverse>::from_json(&json).expect("failed to parse saved game"))
+        } else {
+            game::resume(handler, State::<CliPlayer>::from_json(&json).expect("failed to parse saved game"))
+        }.expect("failed to run game");
+        println!("[ ** ] The winners are: {}", join(winners));
+        return;
+    }
+    let mut game_state = args.seed.map_or_else(Signups::default, Signups::with_seed);
     loop {
         print!("[ ?? ] player name [leave blank to finish]: ");
         stdout().flush().expect("failed to flush stdout");
@@ -110,10 +454,11 @@ fn main() {
             println!("[ !! ] duplicate player name");
         }
     }
-    let winners = if let Some(roles) = args.roles {
-        game::run_with_roles(CliHandler, game_state, roles)
+    let roles = args.roles.unwrap_or_else(|| game::default_roles(game_state.num_players()));
+    let winners = if args.lazy {
+        game::run_with_backend::<_, _, LazyMultiverse>(handler, game_state, roles, rules)
     } else {
-        game::run(CliHandler, game_state)
-    }.expect("failed to start game");
+        game::run_with_roles(handler, game_state, roles, rules)
+    }.expect("failed to run game");
     println!("[ ** ] The winners are: {}", join(winners));
 }
@@ -6,13 +6,22 @@ use std::{
         stdout
     }
 };
+use serde::{
+    Deserialize,
+    Serialize
+};
 use crate::{
-    game::Faction,
+    game::{
+        CauseOfDeath,
+        Faction,
+        Role
+    },
     player::Player
 };
 
 /// A player who sends game actions via the command line.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(transparent)]
 pub struct CliPlayer {
     name: String
 }
@@ -30,6 +39,28 @@ impl CliPlayer {
     fn print_secret(&self, msg: &str) {
         println!("[ __ ] @{}: {}", self.name, msg);
     }
+
+    fn choose_target(&self, msg: &str, possible_targets: &[&CliPlayer]) -> Option<CliPlayer> {
+        loop {
+            let result = CliPlayer::from(self.input_secret(msg));
+            if result.name.is_empty() {
+                break None;
+            } else if possible_targets.contains(&&result) {
+                break Some(result);
+            }
+            self.print_secret("no such player");
+        }
+    }
+
+    fn choose_required_target(&self, msg: &str, possible_targets: &[&CliPlayer]) -> CliPlayer {
+        loop {
+            let result = CliPlayer::from(self.input_secret(msg));
+            if possible_targets.contains(&&result) {
+                break result;
+            }
+            self.print_secret("no such player");
+        }
+    }
 }
 
 impl From<String> for CliPlayer {
@@ -44,44 +75,93 @@ impl Player for CliPlayer {
         self.print_secret(&format!("your secret player ID is {}", player_id)[..]);
     }
 
-    fn choose_heal_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
-        loop {
-            let result = CliPlayer::from(self.input_secret("player to heal"));
-            if result == CliPlayer::from("".to_owned()) {
-                break None;
-            } else if possible_targets.contains(&&result) {
-                break Some(result);
-            }
-            self.print_secret("no such player");
+    fn recv_mason_partners(&self, partners: Vec<&CliPlayer>) {
+        if partners.is_empty() {
+            self.print_secret("if you are a mason, there are no other masons");
+        } else {
+            let partners = partners.into_iter().map(|partner| partner.to_string()).collect::<Vec<_>>();
+            self.print_secret(&format!("if you are a mason, the other masons are: {}", partners.join(", "))[..]);
         }
     }
 
-    fn choose_investigation_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
-        loop {
-            let result = CliPlayer::from(self.input_secret("player to investigate"));
-            if result == CliPlayer::from("".to_owned()) {
-                break None;
-            } else if possible_targets.contains(&&result) {
-                break Some(result);
+    fn choose_lovers(&self, possible_targets: Vec<&CliPlayer>) -> (CliPlayer, CliPlayer) {
+        let lover1 = self.choose_required_target("first lover", &possible_targets);
+        let lover2 = loop {
+            let result = self.choose_required_target("second lover", &possible_targets);
+            if result != lover1 {
+                break result;
             }
-            self.print_secret("no such player");
-        }
+            self.print_secret("the lovers must be two different players");
+        };
+        (lover1, lover2)
+    }
+
+    fn choose_heal_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("player to heal", &possible_targets)
+    }
+
+    fn choose_guard_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("player to guard", &possible_targets)
+    }
+
+    fn choose_investigation_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("player to investigate", &possible_targets)
     }
 
     fn recv_investigation(&self, target: &CliPlayer, faction: Faction) {
         self.print_secret(&format!("investigation result: {}'s faction is {}", target, faction)[..]);
     }
 
-    fn choose_werewolf_kill_target(&self, possible_targets: Vec<&CliPlayer>) -> CliPlayer {
+    fn choose_seer_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("player to investigate as seer", &possible_targets)
+    }
+
+    fn recv_vision(&self, target: &CliPlayer, role: Role) {
+        self.print_secret(&format!("seer result: {}'s role is {}", target, role)[..]);
+    }
+
+    fn choose_medium_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("dead player to contact as medium", &possible_targets)
+    }
+
+    fn recv_seance(&self, target: &CliPlayer, cause: CauseOfDeath) {
+        self.print_secret(&format!("medium result: {} died of {}", target, cause)[..]);
+    }
+
+    fn choose_werewolf_kill_target(&self, possible_targets: Vec<&CliPlayer>, mandatory: bool) -> Option<CliPlayer> {
+        if mandatory {
+            Some(self.choose_required_target("player to werewolf-kill", &possible_targets))
+        } else {
+            self.choose_target("player to werewolf-kill", &possible_targets)
+        }
+    }
+
+    fn choose_serial_killer_target(&self, possible_targets: Vec<&CliPlayer>) -> CliPlayer {
+        self.choose_required_target("player to kill as serial killer", &possible_targets)
+    }
+
+    fn choose_witch_save(&self) -> bool {
         loop {
-            let result = CliPlayer::from(self.input_secret("player to werewolf-kill"));
-            if possible_targets.contains(&&result) {
-                break result;
+            match &self.input_secret("save the werewolf victim [y/N]")[..] {
+                "y" | "Y" => break true,
+                "" | "n" | "N" => break false,
+                _ => { self.print_secret("please answer y or n"); }
             }
-            self.print_secret("no such player");
         }
     }
 
+    fn choose_poison_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("player to poison", &possible_targets)
+    }
+
+    fn choose_vote(&self, candidates: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("vote to lynch [leave blank for no lynch]", &candidates)
+    }
+
+    fn choose_hunter_target(&self, possible_targets: Vec<&CliPlayer>) -> Option<CliPlayer> {
+        self.choose_target("player to shoot", &possible_targets)
+    }
+
     fn recv_exile(&self, reason: &str) {
         self.print_secret(&format!("you have been exiled for {}", reason)[..]);
     }
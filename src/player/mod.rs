@@ -1,33 +1,95 @@
 //! Contains the `Player` trait, which is what the game uses to talk to players, and some implementations.
 
 mod cli;
+mod remote;
 
 use std::fmt;
-use crate::game::Faction;
-pub use self::cli::CliPlayer;
+use crate::game::{
+    CauseOfDeath,
+    Faction,
+    Role
+};
+pub use self::{
+    cli::CliPlayer,
+    remote::RemotePlayer
+};
 
 /// The game uses this trait to talk to players. Implementing types perform all game actions.
 pub trait Player: fmt::Debug + ::std::marker::Sized {
     /// Notifies the player that they have received a secret player ID.
     fn recv_id(&self, player_id: usize);
 
+    /// Notifies the player at the start of the game who the other masons are, in case they are a mason.
+    fn recv_mason_partners(&self, partners: Vec<&Self>);
+
+    /// Called on the first night when the player should link two players as Cupid. Should return the names of the two lovers.
+    ///
+    /// If either name is not one of the possible targets, or both are the same, two random living players are linked instead.
+    fn choose_lovers(&self, possible_targets: Vec<&Self>) -> (Self, Self);
+
     /// Called when the player should heal a player. Should return the name of the player to heal.
     ///
-    /// Returning the name of a dead player or a name not in the game is treated the same as not healing anyone.
+    /// Returning a name which is not one of the possible targets is treated the same as not healing anyone.
     fn choose_heal_target(&self, possible_targets: Vec<&Self>) -> Option<Self>;
 
+    /// Called when the player should guard another player as the bodyguard. Should return the name of the guarded player.
+    ///
+    /// If the guarded player is attacked by the werewolves during the night, the bodyguard dies instead. Returning a name which is not one of the possible targets is treated the same as not guarding anyone.
+    fn choose_guard_target(&self, possible_targets: Vec<&Self>) -> Option<Self>;
+
     /// Called when the player should investigate another player. Should return the name of the investigated player.
     ///
-    /// Returning the name of a dead player, one's own name, or a name not in the game is treated the same as not investigating anyone.
+    /// Returning a name which is not one of the possible targets is treated the same as not investigating anyone.
     fn choose_investigation_target(&self, possible_targets: Vec<&Self>) -> Option<Self>;
 
     /// Notifies the player of the result of an investigation.
     fn recv_investigation(&self, target: &Self, faction: Faction);
 
-    /// Called when the player should kill another player as the dominant werewolf. Should return the name of the attacked player.
+    /// Called when the player should use their seer ability on another player. Should return the name of the player whose role to learn.
+    ///
+    /// Unlike a detective, who only learns the target's faction, the seer learns the target's exact role, including the rank of a werewolf. Any role distribution in which the target has a different role is ruled out if the player is the seer. Returning a name which is not one of the possible targets is treated the same as not using the ability.
+    fn choose_seer_target(&self, possible_targets: Vec<&Self>) -> Option<Self>;
+
+    /// Notifies the player of the result of a seer investigation.
+    fn recv_vision(&self, target: &Self, role: Role);
+
+    /// Called when the player should use their medium ability on a dead player. Should return the name of the player to contact.
+    ///
+    /// Only called if any players are dead, and the possible targets are exactly the dead players. Returning `None` or a name which is not one of the possible targets means not contacting anyone.
+    fn choose_medium_target(&self, possible_targets: Vec<&Self>) -> Option<Self>;
+
+    /// Notifies the player of the result of a medium seance.
+    fn recv_seance(&self, target: &Self, cause: CauseOfDeath);
+
+    /// Called when the player should kill another player as a werewolf. Should return the name of the attacked player.
+    ///
+    /// Depending on the game's `KillRule`, only the choice of the dominant werewolf takes effect, every werewolf's choice takes effect, or the choices are counted as votes. If `mandatory` is `false`, returning `None` or a name which is not one of the possible targets means not killing anyone. Otherwise, such a choice is replaced with a random living player.
+    fn choose_werewolf_kill_target(&self, possible_targets: Vec<&Self>, mandatory: bool) -> Option<Self>;
+
+    /// Called when the player should kill another player as a serial killer. Should return the name of the attacked player.
+    ///
+    /// Returning a name which is not one of the possible targets is replaced with a random living player.
+    fn choose_serial_killer_target(&self, possible_targets: Vec<&Self>) -> Self;
+
+    /// Called when the player should decide whether to use their healing potion as the witch. Should return `true` to save the werewolf victims of this night. With `KillRule::Every`, this saves every player attacked by the werewolves this night.
+    ///
+    /// The potion is only used up if there is someone to save.
+    fn choose_witch_save(&self) -> bool;
+
+    /// Called when the player should decide whether to use their poison potion as the witch. Should return the name of the player to kill.
+    ///
+    /// Returning a name which is not one of the possible targets is treated the same as not poisoning anyone.
+    fn choose_poison_target(&self, possible_targets: Vec<&Self>) -> Option<Self>;
+
+    /// Called during the day when the player should vote on the town lynch target. Should return the name of the player to lynch, or `None` to vote for no lynch.
+    ///
+    /// Returning a name which is not one of the candidates is treated the same as not voting at all.
+    fn choose_vote(&self, candidates: Vec<&Self>) -> Option<Self>;
+
+    /// Called when the player has died as the hunter and may shoot another player. Should return the name of the player to shoot.
     ///
-    /// An illegal choice will exile the player.
-    fn choose_werewolf_kill_target(&self, possible_targets: Vec<&Self>) -> Self;
+    /// Returning a name which is not one of the possible targets is treated the same as not shooting anyone.
+    fn choose_hunter_target(&self, possible_targets: Vec<&Self>) -> Option<Self>;
 
     /// Called when the player is exiled from the game.
     fn recv_exile(&self, reason: &str);
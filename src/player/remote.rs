@@ -0,0 +1,259 @@
+use std::{
+    fmt,
+    hash::{
+        Hash,
+        Hasher
+    },
+    io::{
+        self,
+        prelude::*
+    },
+    sync::{
+        Arc,
+        Mutex
+    }
+};
+use serde::{
+    Serialize,
+    Serializer
+};
+use crate::{
+    game::{
+        CauseOfDeath,
+        Faction,
+        Role
+    },
+    player::Player
+};
+
+struct Connection {
+    reader: Mutex<Box<dyn BufRead + Send>>,
+    writer: Mutex<Box<dyn Write + Send>>
+}
+
+/// A player who is connected to the game through their own private channel, such as a socket.
+///
+/// The channel uses a line-based text protocol, so it can be used directly from a terminal using a program like `nc`. Messages use the same prefixes as the command line interface.
+///
+/// Players are compared by name only, and clones share the same connection.
+#[derive(Clone)]
+pub struct RemotePlayer {
+    name: String,
+    connection: Arc<Connection>
+}
+
+impl RemotePlayer {
+    /// Creates a new remote player with the given name, communicating over the given channel.
+    pub fn new<R: BufRead + Send + 'static, W: Write + Send + 'static>(name: String, reader: R, writer: W) -> RemotePlayer {
+        RemotePlayer {
+            name,
+            connection: Arc::new(Connection {
+                reader: Mutex::new(Box::new(reader)),
+                writer: Mutex::new(Box::new(writer))
+            })
+        }
+    }
+
+    /// Asks the player for a line of text on a connection which has not yet been associated with a name.
+    ///
+    /// Returns `Ok(None)` if the connection has been closed.
+    pub fn read_name<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<Option<String>> {
+        write!(writer, "[ ?? ] player name: ")?;
+        writer.flush()?;
+        read_line(reader)
+    }
+
+    /// Sends a line of text to this player.
+    ///
+    /// Errors are ignored, since a player who has disconnected should not stop the game for everyone else.
+    pub(crate) fn send(&self, msg: &str) {
+        let mut writer = self.connection.writer.lock().expect("player connection lock poisoned");
+        let _ = writeln!(writer, "{}", msg).and_then(|()| writer.flush());
+    }
+
+    /// Asks this player a question and returns their answer, or `None` if they have disconnected.
+    pub(crate) fn input(&self, msg: &str) -> Option<String> {
+        {
+            let mut writer = self.connection.writer.lock().expect("player connection lock poisoned");
+            write!(writer, "[ ?? ] {}: ", msg).and_then(|()| writer.flush()).ok()?;
+        }
+        let mut reader = self.connection.reader.lock().expect("player connection lock poisoned");
+        read_line(&mut *reader).ok().flatten()
+    }
+
+    fn print_secret(&self, msg: &str) {
+        self.send(&format!("[ __ ] {}", msg));
+    }
+
+    fn choose_target(&self, msg: &str, possible_targets: &[&RemotePlayer]) -> Option<RemotePlayer> {
+        loop {
+            let name = self.input(msg)?;
+            if name.is_empty() {
+                break None;
+            } else if let Some(&target) = possible_targets.iter().find(|target| target.name == name) {
+                break Some(target.clone());
+            }
+            self.print_secret("no such player");
+        }
+    }
+
+    /// The target chosen for a player who doesn't answer. If there are no possible targets, the player is returned, so that the game rejects the choice instead of this panicking.
+    fn default_target(&self, possible_targets: &[&RemotePlayer]) -> RemotePlayer {
+        possible_targets.first().map_or_else(|| self.clone(), |&target| target.clone())
+    }
+
+    fn choose_required_target(&self, msg: &str, possible_targets: &[&RemotePlayer]) -> RemotePlayer {
+        loop {
+            let name = match self.input(msg) {
+                Some(name) => name,
+                // the player has disconnected, so we have to choose for them
+                None => break self.default_target(possible_targets)
+            };
+            if let Some(&target) = possible_targets.iter().find(|target| target.name == name) {
+                break target.clone();
+            }
+            self.print_secret("no such player");
+        }
+    }
+}
+
+impl Player for RemotePlayer {
+    fn recv_id(&self, player_id: usize) {
+        self.print_secret(&format!("your secret player ID is {}", player_id));
+    }
+
+    fn recv_mason_partners(&self, partners: Vec<&RemotePlayer>) {
+        if partners.is_empty() {
+            self.print_secret("if you are a mason, there are no other masons");
+        } else {
+            let partners = partners.into_iter().map(|partner| partner.to_string()).collect::<Vec<_>>();
+            self.print_secret(&format!("if you are a mason, the other masons are: {}", partners.join(", ")));
+        }
+    }
+
+    fn choose_lovers(&self, possible_targets: Vec<&RemotePlayer>) -> (RemotePlayer, RemotePlayer) {
+        // if the player doesn't answer or has disconnected, we have to choose for them
+        let lover1 = self.choose_target("first lover", &possible_targets).unwrap_or_else(|| self.default_target(&possible_targets));
+        let possible_targets = possible_targets.into_iter().filter(|&target| *target != lover1).collect::<Vec<_>>();
+        let lover2 = self.choose_target("second lover", &possible_targets).unwrap_or_else(|| self.default_target(&possible_targets));
+        (lover1, lover2)
+    }
+
+    fn choose_heal_target(&self, possible_targets: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("player to heal", &possible_targets)
+    }
+
+    fn choose_guard_target(&self, possible_targets: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("player to guard", &possible_targets)
+    }
+
+    fn choose_investigation_target(&self, possible_targets: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("player to investigate", &possible_targets)
+    }
+
+    fn recv_investigation(&self, target: &RemotePlayer, faction: Faction) {
+        self.print_secret(&format!("investigation result: {}'s faction is {}", target, faction));
+    }
+
+    fn choose_seer_target(&self, possible_targets: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("player to investigate as seer", &possible_targets)
+    }
+
+    fn recv_vision(&self, target: &RemotePlayer, role: Role) {
+        self.print_secret(&format!("seer result: {}'s role is {}", target, role));
+    }
+
+    fn choose_medium_target(&self, possible_targets: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("dead player to contact as medium", &possible_targets)
+    }
+
+    fn recv_seance(&self, target: &RemotePlayer, cause: CauseOfDeath) {
+        self.print_secret(&format!("medium result: {} died of {}", target, cause));
+    }
+
+    fn choose_werewolf_kill_target(&self, possible_targets: Vec<&RemotePlayer>, mandatory: bool) -> Option<RemotePlayer> {
+        if mandatory {
+            Some(self.choose_required_target("player to werewolf-kill", &possible_targets))
+        } else {
+            self.choose_target("player to werewolf-kill", &possible_targets)
+        }
+    }
+
+    fn choose_serial_killer_target(&self, possible_targets: Vec<&RemotePlayer>) -> RemotePlayer {
+        self.choose_required_target("player to kill as serial killer", &possible_targets)
+    }
+
+    fn choose_witch_save(&self) -> bool {
+        loop {
+            match self.input("save the werewolf victim [y/N]").as_deref() {
+                Some("y" | "Y") => break true,
+                // the player has disconnected, so they don't use their potion
+                None | Some("" | "n" | "N") => break false,
+                Some(_) => { self.print_secret("please answer y or n"); }
+            }
+        }
+    }
+
+    fn choose_poison_target(&self, possible_targets: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("player to poison", &possible_targets)
+    }
+
+    fn choose_vote(&self, candidates: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("town lynch target [leave blank for no lynch]", &candidates)
+    }
+
+    fn choose_hunter_target(&self, possible_targets: Vec<&RemotePlayer>) -> Option<RemotePlayer> {
+        self.choose_target("player to shoot", &possible_targets)
+    }
+
+    fn recv_exile(&self, reason: &str) {
+        self.print_secret(&format!("you have been exiled for {}", reason));
+    }
+}
+
+impl PartialEq for RemotePlayer {
+    fn eq(&self, other: &RemotePlayer) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for RemotePlayer {}
+
+impl Hash for RemotePlayer {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+/// Remote players are serialized as their name, for example in event logs.
+impl Serialize for RemotePlayer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.name.serialize(serializer)
+    }
+}
+
+impl fmt::Debug for RemotePlayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemotePlayer").field("name", &self.name).finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for RemotePlayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.name.fmt(f)
+    }
+}
+
+fn read_line<R: BufRead + ?Sized>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(line))
+}
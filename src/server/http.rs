@@ -0,0 +1,218 @@
+//! A minimal HTTP/1.1 implementation, just enough for the servers in this module. Each connection handles a single request.
+
+use std::io::{
+    self,
+    prelude::*
+};
+use serde::Serialize;
+
+/// Requests with longer header lines or bodies are rejected, so clients can't make the server run out of memory.
+const MAX_LINE_LEN: usize = 8 * 1024;
+const MAX_BODY_LEN: usize = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+
+/// An HTTP request.
+pub(crate) struct Request {
+    pub(crate) method: String,
+    /// The path of the request, without the query string.
+    pub(crate) path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>
+}
+
+impl Request {
+    /// Reads a request from the given connection.
+    ///
+    /// Returns `Ok(None)` if the connection is closed before a request has been sent.
+    pub(crate) fn read<R: BufRead>(reader: &mut R) -> io::Result<Option<Request>> {
+        let Some(request_line) = read_line(reader)? else { return Ok(None) };
+        let mut words = request_line.split(' ');
+        let (Some(method), Some(target), Some(_version), None) = (words.next(), words.next(), words.next(), words.next()) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed request line"))
+        };
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut headers = Vec::default();
+        loop {
+            let line = read_line(reader)?.ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed during headers"))?;
+            if line.is_empty() { break }
+            if headers.len() >= MAX_HEADERS { return Err(io::Error::new(io::ErrorKind::InvalidData, "too many headers")) }
+            let (name, value) = line.split_once(':').ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed header"))?;
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+        }
+        let mut request = Request { method: method.to_owned(), path: path.to_owned(), query: query.to_owned(), headers, body: Vec::default() };
+        if let Some(len) = request.header("content-length") {
+            let len = len.parse::<usize>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "malformed content length"))?;
+            if len > MAX_BODY_LEN { return Err(io::Error::new(io::ErrorKind::InvalidData, "request body too long")) }
+            request.body = vec![0; len];
+            reader.read_exact(&mut request.body)?;
+        }
+        Ok(Some(request))
+    }
+
+    /// Returns the value of the header with the given name, which must be lowercase.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header_name, _)| header_name == name)
+            .map(|(_, value)| &value[..])
+    }
+
+    /// Returns the value of the query parameter with the given name. Percent-encoding is not decoded.
+    pub(crate) fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.split('&')
+            .filter_map(|param| param.split_once('='))
+            .find(|(param_name, _)| *param_name == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the token from an `Authorization: Bearer <token>` header.
+    pub(crate) fn bearer_token(&self) -> Option<&str> {
+        self.header("authorization")?.strip_prefix("Bearer ").map(str::trim)
+    }
+}
+
+/// An HTTP response.
+pub(crate) struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>
+}
+
+impl Response {
+    /// A response with the given body, sent as is.
+    pub(crate) fn new(status: u16, content_type: &'static str, body: impl Into<Vec<u8>>) -> Response {
+        Response { status, content_type, body: body.into() }
+    }
+
+    /// A response containing the given value serialized as JSON.
+    pub(crate) fn json<T: Serialize>(status: u16, value: &T) -> Response {
+        match serde_json::to_vec(value) {
+            Ok(body) => Response::new(status, "application/json", body),
+            Err(e) => Response::error(500, &e.to_string())
+        }
+    }
+
+    /// A JSON response of the form `{"error": "<message>"}`.
+    pub(crate) fn error(status: u16, message: &str) -> Response {
+        Response::new(status, "application/json", serde_json::json!({ "error": message }).to_string())
+    }
+
+    /// Writes the response to the given connection, which is then closed.
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", self.status, reason(self.status), self.content_type, self.body.len())?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error"
+    }
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut line = Vec::default();
+    if reader.by_ref().take(MAX_LINE_LEN as u64).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') { return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long")) }
+    if line.last() == Some(&b'\r') { line.pop(); }
+    String::from_utf8(line).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(request: &[u8]) -> io::Result<Option<Request>> {
+        Request::read(&mut &request[..])
+    }
+
+    fn assert_invalid(request: &[u8], message: &str) {
+        match read(request) {
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                assert_eq!(e.to_string(), message);
+            }
+            Ok(_) => panic!("request should have been rejected with {message:?}")
+        }
+    }
+
+    #[test]
+    fn valid_request() {
+        let request = read(b"POST /games/0/votes?token=abc&x=y HTTP/1.1\r\nAuthorization: Bearer  tok \r\nContent-Length: 4\r\n\r\nbodyextra").expect("failed to read request").expect("no request");
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/games/0/votes");
+        assert_eq!(request.query_param("token"), Some("abc"));
+        assert_eq!(request.query_param("x"), Some("y"));
+        assert_eq!(request.query_param("z"), None);
+        assert_eq!(request.header("content-length"), Some("4"));
+        assert_eq!(request.bearer_token(), Some("tok"));
+        assert_eq!(request.body, b"body");
+    }
+
+    #[test]
+    fn closed_connection() {
+        assert!(read(b"").expect("failed to read request").is_none());
+        assert_eq!(read(b"GET / HTTP/1.1\r\n").err().map(|e| e.kind()), Some(io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn oversized_line() {
+        let mut request = b"GET /".to_vec();
+        request.extend(vec![b'a'; MAX_LINE_LEN]);
+        request.extend_from_slice(b" HTTP/1.1\r\n\r\n");
+        assert_invalid(&request, "line too long");
+        let mut request = b"GET / HTTP/1.1\r\nX-Long: ".to_vec();
+        request.extend(vec![b'a'; MAX_LINE_LEN]);
+        request.extend_from_slice(b"\r\n\r\n");
+        assert_invalid(&request, "line too long");
+    }
+
+    #[test]
+    fn oversized_body() {
+        assert_invalid(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_LEN + 1).as_bytes(), "request body too long");
+        let mut request = format!("POST / HTTP/1.1\r\nContent-Length: {MAX_BODY_LEN}\r\n\r\n").into_bytes();
+        request.extend(vec![b'a'; MAX_BODY_LEN]);
+        assert_eq!(read(&request).expect("failed to read request").expect("no request").body.len(), MAX_BODY_LEN);
+    }
+
+    #[test]
+    fn too_many_headers() {
+        let headers = |n| format!("GET / HTTP/1.1\r\n{}\r\n", "X-Header: a\r\n".repeat(n));
+        assert_eq!(read(headers(MAX_HEADERS).as_bytes()).expect("failed to read request").expect("no request").headers.len(), MAX_HEADERS);
+        assert_invalid(headers(MAX_HEADERS + 1).as_bytes(), "too many headers");
+    }
+
+    #[test]
+    fn bad_content_length() {
+        for len in ["abc", "-1", "", "1 2", "18446744073709551616"] {
+            assert_invalid(format!("POST / HTTP/1.1\r\nContent-Length: {len}\r\n\r\n").as_bytes(), "malformed content length");
+        }
+        // the body is shorter than announced
+        assert_eq!(read(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc").err().map(|e| e.kind()), Some(io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn malformed_request() {
+        assert_invalid(b"GET /\r\n\r\n", "malformed request line");
+        assert_invalid(b"GET / HTTP/1.1\r\nno colon\r\n\r\n", "malformed header");
+    }
+
+    #[test]
+    fn write_response() {
+        let mut written = Vec::default();
+        Response::error(404, "no such game").write(&mut written).expect("failed to write response");
+        assert_eq!(String::from_utf8(written).expect("response is not UTF-8"), "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: 24\r\nConnection: close\r\n\r\n{\"error\":\"no such game\"}");
+    }
+}
@@ -0,0 +1,18 @@
+//! Servers which run games for players connected over a network, without a moderator.
+//!
+//! Unlike `game::run`, which asks players for their choices using the `Player` trait, a `Session` waits for the players to submit their choices on their own, so it can be driven by requests from clients such as the ones handled by the `rest` API, and report changes to them as they happen using `websocket`.
+
+mod http;
+pub mod rest;
+mod session;
+pub mod websocket;
+
+pub use self::session::{
+    Ballot,
+    Phase,
+    PrivateState,
+    PublicState,
+    Session,
+    SessionError,
+    SessionOptions
+};
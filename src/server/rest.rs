@@ -0,0 +1,315 @@
+//! An HTTP/JSON API for running games, where each player takes part using their own client.
+//!
+//! All request and response bodies are JSON. Requests which act on behalf of a player are authenticated using an `Authorization: Bearer <token>` header. Errors are reported as `{"error": "<message>"}` with an appropriate status code. The endpoints are:
+//!
+//! * `POST /games`: creates a game. The body is an optional `SessionOptions` object, for example `{"vote_duration": 300}` to end each lynch vote after five minutes even if not everyone has voted. Returns `{"game": <id>, "admin_token": "<token>"}`.
+//! * `GET /games/<id>`: returns the `PublicState` of the game.
+//! * `POST /games/<id>/players`: signs up for the game with a body of `{"name": "<name>"}`. Returns `{"token": "<token>"}`.
+//! * `GET /games/<id>/me`: returns the `PrivateState` of the authenticated player.
+//! * `POST /games/<id>/start`: starts the game using the admin token. The body may contain `{"roles": ["werewolf", "detective", …]}`, otherwise a basic role distribution is used.
+//! * `POST /games/<id>/night-actions`: submits a night action, such as `{"Heal": "<target>"}`, `{"Link": ["<lover>", "<lover>"]}`, `{"Save": true}`, or `{"Poison": null}`. Werewolves can submit `{"Kill": null}` to not kill anyone if the rules allow it. Uses the same variants as `NightAction`, without the source player.
+//! * `POST /games/<id>/hunter-target`: sets whom to shoot when dying as the hunter, with a body of `{"target": "<name>"}`, or `{"target": null}` to not shoot anyone.
+//! * `POST /games/<id>/votes`: casts or changes a lynch vote, with a body of `{"ballot": ["<name>", …]}`, where `null` stands for no lynch.
+//! * `POST /games/<id>/advance`: ends the current phase early using the admin token.
+//!
+//! In addition, `GET /` serves a browser client, and `/games/<id>/ws` accepts WebSocket connections as described in the `websocket` module.
+//!
+//! At most 256 connections are handled at the same time, and further ones are answered with status 503 until one of them closes.
+
+use std::{
+    io::{
+        self,
+        BufReader
+    },
+    net::{
+        TcpListener,
+        TcpStream
+    },
+    str::FromStr as _,
+    sync::{
+        Arc,
+        Condvar,
+        Mutex,
+        atomic::{
+            AtomicUsize,
+            Ordering::SeqCst
+        }
+    },
+    thread,
+    time::Duration
+};
+use serde::{
+    Deserialize,
+    de::DeserializeOwned
+};
+use crate::{
+    game::{
+        NightAction,
+        Role
+    },
+    handler::voting::Choice,
+    server::{
+        Session,
+        SessionError,
+        SessionOptions,
+        http::{
+            Request,
+            Response
+        },
+        websocket
+    }
+};
+
+const CLIENT: &str = include_str!("client.html");
+
+/// The games running on a server.
+#[derive(Default)]
+pub(crate) struct Games {
+    /// This lock is only held to look up or add sessions. Each session has its own lock, so requests for different games don't wait for each other.
+    sessions: Mutex<Vec<Arc<SharedSession>>>,
+    /// The number of connections currently being handled.
+    connections: AtomicUsize
+}
+
+impl Games {
+    fn get(&self, game: &str) -> Option<Arc<SharedSession>> {
+        game.parse::<usize>().ok().and_then(|game| self.sessions.lock().expect("sessions lock poisoned").get(game).cloned())
+    }
+
+    fn snapshot(&self) -> Vec<Arc<SharedSession>> {
+        self.sessions.lock().expect("sessions lock poisoned").clone()
+    }
+}
+
+/// A session which can be accessed from multiple connections.
+pub(crate) struct SharedSession {
+    pub(crate) session: Mutex<Session>,
+    /// Notified whenever the session may have changed.
+    pub(crate) changed: Condvar
+}
+
+/// Releases a connection slot when the connection has been handled, even if handling it panicked.
+struct ConnectionSlot<'a>(&'a AtomicUsize);
+
+impl Drop for ConnectionSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, SeqCst);
+    }
+}
+
+#[derive(Deserialize)]
+struct SignUpRequest {
+    name: String
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct StartRequest {
+    roles: Option<Vec<String>>
+}
+
+#[derive(Deserialize)]
+pub(crate) enum NightActionRequest {
+    Heal(String),
+    Guard(String),
+    Investigate(String),
+    See(String),
+    Seance(String),
+    Kill(Option<String>),
+    SerialKill(String),
+    Link(String, String),
+    Save(bool),
+    Poison(Option<String>)
+}
+
+impl NightActionRequest {
+    pub(crate) fn into_action(self, src: String) -> NightAction<String> {
+        match self {
+            NightActionRequest::Heal(tgt) => NightAction::Heal(src, tgt),
+            NightActionRequest::Guard(tgt) => NightAction::Guard(src, tgt),
+            NightActionRequest::Investigate(tgt) => NightAction::Investigate(src, tgt),
+            NightActionRequest::See(tgt) => NightAction::See(src, tgt),
+            NightActionRequest::Seance(tgt) => NightAction::Seance(src, tgt),
+            NightActionRequest::Kill(tgt) => NightAction::Kill(src, tgt),
+            NightActionRequest::SerialKill(tgt) => NightAction::SerialKill(src, tgt),
+            NightActionRequest::Link(lover1, lover2) => NightAction::Link(src, lover1, lover2),
+            NightActionRequest::Save(save) => NightAction::Save(src, save),
+            NightActionRequest::Poison(tgt) => NightAction::Poison(src, tgt)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct HunterTargetRequest {
+    target: Option<String>
+}
+
+#[derive(Deserialize)]
+struct VoteRequest {
+    ballot: Vec<Option<String>>
+}
+
+/// How often the deadlines of lynch votes are checked.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// The maximum number of connections handled at the same time, including WebSocket connections. Further connections are turned away with a 503 response.
+const MAX_CONNECTIONS: usize = 256;
+/// How long a client may take to send a request, so idle connections don't keep their slot forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Accepts connections on the given listener and answers API requests until the listener fails.
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    let games = Arc::<Games>::default();
+    {
+        let games = Arc::clone(&games);
+        thread::spawn(move || loop {
+            thread::sleep(TICK_INTERVAL);
+            for shared in games.snapshot() {
+                if shared.session.lock().expect("session lock poisoned").tick() {
+                    shared.changed.notify_all();
+                }
+            }
+        });
+    }
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        if games.connections.fetch_add(1, SeqCst) >= MAX_CONNECTIONS {
+            games.connections.fetch_sub(1, SeqCst);
+            let _ = Response::error(503, "too many connections").write(&mut stream);
+            continue
+        }
+        let games = Arc::clone(&games);
+        thread::spawn(move || {
+            let _slot = ConnectionSlot(&games.connections);
+            handle_connection(stream, &games)
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, games: &Games) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let response = match Request::read(&mut reader) {
+        Ok(Some(request)) => {
+            if let Some((shared, key)) = websocket_upgrade(&request, games) {
+                let token = request.query_param("token").filter(|token| !token.is_empty()).map(str::to_owned);
+                if let Some(ref token) = token {
+                    if let Err(e) = shared.session.lock().expect("session lock poisoned").player(token) {
+                        return session_error(&e).write(&mut writer)
+                    }
+                }
+                // WebSocket clients may stay silent for as long as they like
+                reader.get_ref().set_read_timeout(None)?;
+                return websocket::serve(reader, writer, key, &shared, token)
+            }
+            handle(&request, games)
+        }
+        Ok(None) => return Ok(()),
+        Err(e) => Response::error(400, &e.to_string())
+    };
+    response.write(&mut writer)
+}
+
+/// If the request is a WebSocket handshake for an existing game, returns the game's session and the `Sec-WebSocket-Key`.
+fn websocket_upgrade<'a>(request: &'a Request, games: &Games) -> Option<(Arc<SharedSession>, &'a str)> {
+    let segments = request.path.trim_matches('/').split('/').collect::<Vec<_>>();
+    let ("GET", ["games", game, "ws"]) = (&request.method[..], &segments[..]) else { return None };
+    if !request.header("upgrade")?.eq_ignore_ascii_case("websocket") { return None }
+    Some((games.get(game)?, request.header("sec-websocket-key")?))
+}
+
+/// Answers a single API request.
+fn handle(request: &Request, games: &Games) -> Response {
+    let segments = request.path.trim_matches('/').split('/').collect::<Vec<_>>();
+    match (&request.method[..], &segments[..]) {
+        ("GET", [""]) => Response::new(200, "text/html; charset=utf-8", CLIENT),
+        ("POST", ["games"]) => {
+            let options = match parse_body::<Option<SessionOptions>>(request) {
+                Ok(options) => options.unwrap_or_default(),
+                Err(response) => return response
+            };
+            let session = Session::new(options);
+            let admin_token = session.admin_token().to_owned();
+            let mut sessions = games.sessions.lock().expect("sessions lock poisoned");
+            sessions.push(Arc::new(SharedSession { session: Mutex::new(session), changed: Condvar::default() }));
+            Response::json(201, &serde_json::json!({ "game": sessions.len() - 1, "admin_token": admin_token }))
+        }
+        (method, ["games", game, rest @ ..]) => {
+            let Some(shared) = games.get(game) else { return Response::error(404, "no such game") };
+            let response = handle_game(request, method, rest, &mut shared.session.lock().expect("session lock poisoned"));
+            shared.changed.notify_all();
+            response
+        }
+        ("GET" | "POST", _) => Response::error(404, "not found"),
+        _ => Response::error(405, "method not allowed")
+    }
+}
+
+/// Answers an API request for the given game. `rest` is the path after `/games/<id>`.
+fn handle_game(request: &Request, method: &str, rest: &[&str], session: &mut Session) -> Response {
+    let token = request.bearer_token().unwrap_or_default();
+    let result = match (method, rest) {
+        ("GET", []) => return Response::json(200, &*session.public_state()),
+        ("GET", ["me"]) => return match session.private_state(token) {
+            Ok(private_state) => Response::json(200, &private_state),
+            Err(e) => session_error(&e)
+        },
+        ("POST", ["players"]) => match parse_body::<SignUpRequest>(request) {
+            Ok(SignUpRequest { name }) => return match session.sign_up(name) {
+                Ok(token) => Response::json(201, &serde_json::json!({ "token": token })),
+                Err(e) => session_error(&e)
+            },
+            Err(response) => return response
+        },
+        ("POST", ["start"]) => match parse_body::<Option<StartRequest>>(request) {
+            Ok(start) => match start.unwrap_or_default().roles.map(|roles| roles.iter().map(|role| Role::from_str(role)).collect::<Result<Vec<_>, ()>>()).transpose() {
+                Ok(roles) => session.start(token, roles),
+                Err(()) => return Response::error(400, "no such role")
+            },
+            Err(response) => return response
+        },
+        ("POST", ["night-actions"]) => match parse_body::<NightActionRequest>(request) {
+            Ok(action) => match session.player(token) {
+                Ok(player) => {
+                    let action = action.into_action(player.to_owned());
+                    session.submit_night_action(token, action)
+                }
+                Err(e) => Err(e)
+            },
+            Err(response) => return response
+        },
+        ("POST", ["hunter-target"]) => match parse_body::<HunterTargetRequest>(request) {
+            Ok(HunterTargetRequest { target }) => session.set_hunter_target(token, target),
+            Err(response) => return response
+        },
+        ("POST", ["votes"]) => match parse_body::<VoteRequest>(request) {
+            Ok(VoteRequest { ballot }) => session.vote(token, ballot.into_iter().map(|choice| choice.map_or(Choice::NoLynch, Choice::Lynch)).collect()),
+            Err(response) => return response
+        },
+        ("POST", ["advance"]) => session.advance(token),
+        ("GET" | "POST", _) => return Response::error(404, "not found"),
+        _ => return Response::error(405, "method not allowed")
+    };
+    match result {
+        Ok(()) => Response::json(200, &*session.public_state()),
+        Err(e) => session_error(&e)
+    }
+}
+
+/// Parses the request body as JSON. An empty body is treated like `null`.
+fn parse_body<T: DeserializeOwned>(request: &Request) -> Result<T, Response> {
+    let body = if request.body.is_empty() { &b"null"[..] } else { &request.body[..] };
+    serde_json::from_slice(body).map_err(|e| Response::error(400, &e.to_string()))
+}
+
+fn session_error(e: &SessionError) -> Response {
+    let status = match e {
+        SessionError::InvalidToken => 401,
+        SessionError::NotAdmin => 403,
+        SessionError::WrongPhase | SessionError::DuplicateName | SessionError::Start(_) => 409,
+        SessionError::EmptyName | SessionError::Game(_) | SessionError::Vote(_) => 400,
+        SessionError::Failed(_) => 500
+    };
+    Response::error(status, &e.to_string())
+}
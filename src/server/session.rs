@@ -0,0 +1,486 @@
+use std::{
+    collections::{
+        HashMap,
+        HashSet
+    },
+    mem,
+    sync::{
+        Arc,
+        OnceLock
+    },
+    time::{
+        Duration,
+        Instant
+    }
+};
+use rand::Rng as _;
+use serde::{
+    Deserialize,
+    Serialize
+};
+use crate::{
+    game::{
+        self,
+        NightAction,
+        NightActionResult,
+        ProbabilityTable,
+        Reveal,
+        Role,
+        state::{
+            Complete,
+            GameError,
+            Rules,
+            Signups,
+            StartGameError,
+            State
+        }
+    },
+    handler::voting::{
+        Choice,
+        Poll,
+        Tally,
+        TieRule,
+        VoteError,
+        VotingSystem
+    }
+};
+
+/// The settings for a new `Session`. When deserializing, missing fields are set to their default values.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct SessionOptions {
+    /// The seed for the game's random number generator, or `None` to use a random seed.
+    pub seed: Option<u64>,
+    /// The rules of the game.
+    pub rules: Rules,
+    /// The voting system used to determine the town lynch target.
+    pub voting: VotingSystem,
+    /// How ties are resolved in the lynch vote.
+    pub tie_rule: TieRule,
+    /// If given, each lynch vote is also tallied once it has been open for this many seconds, even if not every living player has voted.
+    pub vote_duration: Option<u64>
+}
+
+/// The possible errors returned by `Session` methods.
+#[derive(Debug, thiserror::Error)]
+pub enum SessionError {
+    /// The given token does not belong to any player in this session.
+    #[error("invalid token")]
+    InvalidToken,
+    /// The action can only be performed by the player who created the session.
+    #[error("only the creator of the game can do this")]
+    NotAdmin,
+    /// The action is not possible in the current phase of the game.
+    #[error("this is not possible in the current phase of the game")]
+    WrongPhase,
+    /// A player with the same name has already signed up.
+    #[error("duplicate player name")]
+    DuplicateName,
+    /// The player name is empty.
+    #[error("player names must not be empty")]
+    EmptyName,
+    /// The game could not be started.
+    #[error(transparent)]
+    Start(#[from] StartGameError),
+    /// The game rejected the action.
+    #[error(transparent)]
+    Game(#[from] GameError),
+    /// The game could not be continued, so the session has moved to `Phase::Failed`.
+    #[error("the game could not be continued: {0}")]
+    Failed(GameError),
+    /// The vote was invalid.
+    #[error(transparent)]
+    Vote(#[from] VoteError)
+}
+
+/// The current phase of a game, as shown in its `PublicState`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Players can sign up.
+    Signups,
+    /// Players submit their night actions.
+    Night,
+    /// The town votes on whom to lynch.
+    Day,
+    /// The game is over.
+    Complete,
+    /// The game has been aborted because it could not be continued, see `PublicState::error`.
+    Failed
+}
+
+/// A player's current ballot in the lynch vote, as shown in the `PublicState`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Ballot {
+    /// The player who cast this ballot.
+    pub voter: String,
+    /// The chosen options, in order of preference for ranked runoff voting.
+    pub choices: Vec<Choice<String>>
+}
+
+/// Everything about a game which all players may know.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PublicState {
+    /// The current phase of the game.
+    pub phase: Phase,
+    /// All players who have signed up, sorted by name.
+    pub players: Vec<String>,
+    /// The players who are alive in at least one universe, sorted by name. Empty before the game has started, and not updated during the final phase of the game, like `deaths`.
+    pub alive: Vec<String>,
+    /// The players who have died, in order of death, along with what has been revealed about them.
+    ///
+    /// Deaths during the phase which ends the game are not included, since a completed game doesn't keep track of them.
+    pub deaths: Vec<(String, Reveal)>,
+    /// The probability table announced at the start of the most recent day, indexed by secret player ID.
+    pub probability_table: Option<ProbabilityTable>,
+    /// During the day, the ballots cast so far.
+    pub ballots: Vec<Ballot>,
+    /// The results of all lynch votes so far, including ones which had to be repeated because the rules require a lynch.
+    pub tallies: Vec<Tally<String>>,
+    /// The winners, sorted by name, once the game is over. `None` if the game has failed.
+    pub winners: Option<Vec<String>>,
+    /// Why the game could not be continued, if it has failed.
+    pub error: Option<String>
+}
+
+/// Everything about a game which only the given player may know.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrivateState {
+    /// The player's name.
+    pub name: String,
+    /// The player's secret player ID, once the game has started.
+    pub secret_id: Option<usize>,
+    /// The other masons, if this player may be a mason.
+    pub mason_partners: Option<Vec<String>>,
+    /// The results of all of this player's night actions so far.
+    pub night_action_results: Vec<NightActionResult<String>>,
+    /// The night actions this player has submitted during the current night.
+    pub night_actions: Vec<NightAction<String>>,
+    /// The player this player will shoot if they die as the hunter.
+    pub hunter_target: Option<String>
+}
+
+/// A single game which is advanced by requests from the players, rather than by a `Handler` asking them for their choices.
+///
+/// Players are identified by their names, and authenticate using secret tokens handed out when they sign up. The player who creates the session receives an additional admin token, which is required to start the game and to end a phase early.
+///
+/// Night actions are resolved using natural action resolution once everyone has submitted theirs. Since players are not asked anything, hunters choose whom to shoot in advance, and the lynch target is determined using a `Poll` which is tallied once every living player has voted, or once its deadline has passed if `SessionOptions::vote_duration` is set. Deadlines are only checked when `Session::tick` is called.
+#[derive(Debug)]
+pub struct Session {
+    state: State<String>,
+    options: SessionOptions,
+    admin_token: String,
+    tokens: HashMap<String, String>,
+    players: Vec<String>,
+    secret_ids: Vec<String>,
+    alive: HashSet<String>,
+    deaths: Vec<(String, Reveal)>,
+    probability_table: Option<ProbabilityTable>,
+    mason_partners: HashMap<String, Vec<String>>,
+    night_actions: Vec<NightAction<String>>,
+    night_action_results: HashMap<String, Vec<NightActionResult<String>>>,
+    hunter_targets: HashMap<String, String>,
+    poll: Option<Poll<String>>,
+    tallies: Vec<Tally<String>>,
+    failure: Option<String>,
+    version: u64,
+    /// The public state for the current version, computed when first requested.
+    public_state: OnceLock<Arc<PublicState>>
+}
+
+impl Session {
+    /// Creates a new session in the signups phase. The admin token can be retrieved using `admin_token`.
+    pub fn new(options: SessionOptions) -> Session {
+        Session {
+            state: State::Signups(options.seed.map_or_else(Signups::default, Signups::with_seed)),
+            options,
+            admin_token: new_token(),
+            tokens: HashMap::default(),
+            players: Vec::default(),
+            secret_ids: Vec::default(),
+            alive: HashSet::default(),
+            deaths: Vec::default(),
+            probability_table: None,
+            mason_partners: HashMap::default(),
+            night_actions: Vec::default(),
+            night_action_results: HashMap::default(),
+            hunter_targets: HashMap::default(),
+            poll: None,
+            tallies: Vec::default(),
+            failure: None,
+            version: 0,
+            public_state: OnceLock::default()
+        }
+    }
+
+    /// Returns the token required to start the game and to end a phase early.
+    pub fn admin_token(&self) -> &str {
+        &self.admin_token
+    }
+
+    /// Signs up a player with the given name and returns their token.
+    pub fn sign_up(&mut self, name: String) -> Result<String, SessionError> {
+        let State::Signups(ref mut signups) = self.state else { return Err(SessionError::WrongPhase) };
+        if name.is_empty() { return Err(SessionError::EmptyName) }
+        if !signups.sign_up(name.clone()) { return Err(SessionError::DuplicateName) }
+        let token = new_token();
+        self.tokens.insert(token.clone(), name.clone());
+        self.players.push(name);
+        self.players.sort();
+        self.changed();
+        Ok(token)
+    }
+
+    /// Returns the name of the player with the given token.
+    pub fn player(&self, token: &str) -> Result<&str, SessionError> {
+        self.tokens.get(token).map(String::as_str).ok_or(SessionError::InvalidToken)
+    }
+
+    /// Starts the game with the given roles, or a basic role distribution if `None` is given. Requires the admin token.
+    pub fn start(&mut self, admin_token: &str, roles: Option<Vec<Role>>) -> Result<(), SessionError> {
+        self.check_admin(admin_token)?;
+        let State::Signups(ref signups) = self.state else { return Err(SessionError::WrongPhase) };
+        let roles = roles.unwrap_or_else(|| game::default_roles(signups.num_players()));
+        // keep the signups if the game can't be started, so the admin can fix the problem
+        let game_state = signups.clone().start(roles, self.options.rules)?;
+        self.secret_ids = game_state.secret_ids().map_or_else(Vec::default, <[_]>::to_vec);
+        if let State::Night(ref night) = game_state {
+            self.mason_partners = night.mason_partners().into_iter()
+                .map(|(player, partners)| (player.clone(), partners.into_iter().cloned().collect()))
+                .collect();
+        }
+        self.alive = game_state.alive().into_iter().flatten().cloned().collect();
+        self.enter(game_state).map_err(|e| self.fail(e))
+    }
+
+    /// Submits a night action for the player with the given token, replacing any action of the same kind they have submitted during this night.
+    ///
+    /// The source of the action must be the player themselves. Once every living player has submitted all night actions, the night is resolved.
+    pub fn submit_night_action(&mut self, token: &str, action: NightAction<String>) -> Result<(), SessionError> {
+        if action.src() != self.player(token)? { return Err(SessionError::InvalidToken) }
+        let State::Night(ref night) = self.state else { return Err(SessionError::WrongPhase) };
+        night.check_night_action(&action)?;
+        self.night_actions.retain(|submitted| submitted.src() != action.src() || mem::discriminant(submitted) != mem::discriminant(&action));
+        self.night_actions.push(action);
+        let complete = night.actions_complete(&self.night_actions);
+        self.changed();
+        if complete {
+            self.resolve_night()?;
+        }
+        Ok(())
+    }
+
+    /// Sets whom the player with the given token shoots if they die as the hunter, or `None` to not shoot anyone.
+    ///
+    /// If the chosen player is already dead at that point, the hunter doesn't shoot.
+    pub fn set_hunter_target(&mut self, token: &str, target: Option<String>) -> Result<(), SessionError> {
+        let player = self.player(token)?.to_owned();
+        match target {
+            Some(target) => { self.hunter_targets.insert(player, target); }
+            None => { self.hunter_targets.remove(&player); }
+        }
+        self.changed();
+        Ok(())
+    }
+
+    /// Casts or changes the lynch vote of the player with the given token. Once every living player has voted, the votes are tallied.
+    ///
+    /// Returns `VoteError::Closed` if the deadline of the vote has passed but `tick` hasn't been called since.
+    pub fn vote(&mut self, token: &str, ballot: Vec<Choice<String>>) -> Result<(), SessionError> {
+        let player = self.player(token)?.to_owned();
+        let poll = self.poll.as_mut().ok_or(SessionError::WrongPhase)?;
+        poll.vote(&player, ballot)?;
+        let complete = poll.is_complete();
+        self.changed();
+        if complete {
+            self.decide_lynch()?;
+        }
+        Ok(())
+    }
+
+    /// Ends the current phase without waiting for the remaining players. Requires the admin token.
+    ///
+    /// At night, any mandatory night actions which have not been submitted are randomized. During the day, the votes cast so far are tallied.
+    pub fn advance(&mut self, admin_token: &str) -> Result<(), SessionError> {
+        self.check_admin(admin_token)?;
+        match self.state {
+            State::Signups(_) | State::Complete(_) => Err(SessionError::WrongPhase),
+            State::Night(_) => self.resolve_night(),
+            State::Day(_) => self.decide_lynch()
+        }
+    }
+
+    /// Tallies the lynch vote if its deadline has passed. Returns `true` if this changed the state of the session.
+    ///
+    /// Servers should call this regularly if `SessionOptions::vote_duration` is set. If the game can't be continued, the session moves to `Phase::Failed` as with any other action.
+    pub fn tick(&mut self) -> bool {
+        if self.poll.as_ref().is_none_or(Poll::is_open) { return false }
+        // the only possible error is a failure, which is already recorded in the public state
+        let _ = self.decide_lynch();
+        true
+    }
+
+    /// Returns the current phase of the game.
+    pub fn phase(&self) -> Phase {
+        if self.failure.is_some() { return Phase::Failed }
+        match self.state {
+            State::Signups(_) => Phase::Signups,
+            State::Night(_) => Phase::Night,
+            State::Day(_) => Phase::Day,
+            State::Complete(_) => Phase::Complete
+        }
+    }
+
+    /// Returns a number which changes whenever the public state, or the private state of any player, may have changed.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Returns everything about the game which all players may know.
+    ///
+    /// This is only computed once per `version`, so it can be cheaply sent to any number of clients.
+    pub fn public_state(&self) -> Arc<PublicState> {
+        Arc::clone(self.public_state.get_or_init(|| Arc::new(self.compute_public_state())))
+    }
+
+    fn compute_public_state(&self) -> PublicState {
+        let mut alive = self.alive.iter().cloned().collect::<Vec<_>>();
+        alive.sort();
+        PublicState {
+            phase: self.phase(),
+            players: self.players.clone(),
+            alive,
+            deaths: self.deaths.clone(),
+            probability_table: self.probability_table.clone(),
+            ballots: self.poll.as_ref().map_or_else(Vec::default, |poll| poll.voters().iter()
+                .filter_map(|voter| poll.ballot(voter).map(|choices| Ballot { voter: voter.clone(), choices: choices.to_owned() }))
+                .collect()
+            ),
+            tallies: self.tallies.clone(),
+            winners: if let (State::Complete(ref complete), None) = (&self.state, &self.failure) {
+                let mut winners = complete.winners.iter().cloned().collect::<Vec<_>>();
+                winners.sort();
+                Some(winners)
+            } else {
+                None
+            },
+            error: self.failure.clone()
+        }
+    }
+
+    /// Returns everything about the game which only the player with the given token may know.
+    pub fn private_state(&self, token: &str) -> Result<PrivateState, SessionError> {
+        let name = self.player(token)?.to_owned();
+        Ok(PrivateState {
+            secret_id: self.secret_ids.iter().position(|player| *player == name),
+            mason_partners: self.mason_partners.get(&name).cloned(),
+            night_action_results: self.night_action_results.get(&name).cloned().unwrap_or_default(),
+            night_actions: self.night_actions.iter().filter(|action| *action.src() == name).cloned().collect(),
+            hunter_target: self.hunter_targets.get(&name).cloned(),
+            name
+        })
+    }
+
+    fn check_admin(&self, token: &str) -> Result<(), SessionError> {
+        if token == self.admin_token { Ok(()) } else { Err(SessionError::NotAdmin) }
+    }
+
+    /// Must be called after every change to the session.
+    fn changed(&mut self) {
+        self.version += 1;
+        self.public_state = OnceLock::default();
+    }
+
+    /// Ends the game because it can't be continued after the given error, and returns the error to report.
+    ///
+    /// Game state transitions consume the previous state, so it can't be restored.
+    fn fail(&mut self, error: GameError) -> SessionError {
+        self.state = State::Complete(Complete { winners: HashSet::default() });
+        self.poll = None;
+        self.failure = Some(error.to_string());
+        self.changed();
+        SessionError::Failed(error)
+    }
+
+    fn resolve_night(&mut self) -> Result<(), SessionError> {
+        let State::Night(night) = mem::take(&mut self.state) else { unreachable!("resolving night during another phase") };
+        let hunter_targets = &self.hunter_targets;
+        let game_state = match night.resolve_nar(&self.night_actions, |hunter, targets| hunter_target(hunter_targets, hunter, targets)) {
+            Ok(game_state) => game_state,
+            Err(e) => return Err(self.fail(e))
+        };
+        self.night_actions.clear();
+        self.enter(game_state).map_err(|e| self.fail(e))
+    }
+
+    fn decide_lynch(&mut self) -> Result<(), SessionError> {
+        let tally = self.poll.as_ref().ok_or(SessionError::WrongPhase)?.tally();
+        let decision = tally.decision.clone();
+        self.tallies.push(tally);
+        self.changed();
+        let State::Day(day) = mem::take(&mut self.state) else { unreachable!("poll outside of the day") };
+        let game_state = match decision {
+            Some(target) if day.can_lynch(&target) => {
+                let hunter_targets = &self.hunter_targets;
+                day.lynch(target, |hunter, targets| hunter_target(hunter_targets, hunter, targets))
+            }
+            None if day.can_skip_lynch() => day.no_lynch(),
+            _ => {
+                // vote again
+                self.state = State::Day(day);
+                self.enter_day();
+                return Ok(());
+            }
+        };
+        match game_state {
+            Ok(game_state) => self.enter(game_state).map_err(|e| self.fail(e)),
+            Err(e) => Err(self.fail(e))
+        }
+    }
+
+    /// Switches to the given game state and updates the information derived from it.
+    fn enter(&mut self, game_state: State<String>) -> Result<(), GameError> {
+        self.state = game_state;
+        self.changed();
+        if let Some(new_alive) = self.state.alive() {
+            let new_alive = new_alive.into_iter().cloned().collect::<HashSet<_>>();
+            let mut died = (&self.alive - &new_alive).into_iter().collect::<Vec<_>>();
+            died.sort();
+            for player in died {
+                if let Some(reveal) = self.state.reveal(&player) {
+                    self.deaths.push((player, reveal));
+                }
+            }
+            self.alive = new_alive;
+        }
+        self.poll = None;
+        if let State::Day(ref day) = self.state {
+            for (player, result) in day.night_action_results() {
+                self.night_action_results.entry(player.clone()).or_default().push(result.cloned());
+            }
+            self.probability_table = Some(day.probability_table()?);
+            self.enter_day();
+        }
+        Ok(())
+    }
+
+    /// Opens a new lynch vote.
+    fn enter_day(&mut self) {
+        let State::Day(ref day) = self.state else { return };
+        let mut voters = day.alive().into_iter().cloned().collect::<Vec<_>>();
+        voters.sort();
+        let mut poll = Poll::new(self.options.voting, voters.clone(), voters, day.can_skip_lynch()).tie_rule(self.options.tie_rule);
+        if let Some(vote_duration) = self.options.vote_duration {
+            poll = poll.deadline(Instant::now() + Duration::from_secs(vote_duration));
+        }
+        self.poll = Some(poll);
+    }
+}
+
+fn hunter_target(hunter_targets: &HashMap<String, String>, hunter: &String, targets: Vec<&String>) -> Option<String> {
+    hunter_targets.get(hunter).filter(|target| targets.contains(target)).cloned()
+}
+
+fn new_token() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
@@ -0,0 +1,410 @@
+//! Pushes game updates to clients over WebSocket, and accepts their actions.
+//!
+//! Clients connect to `/games/<id>/ws?token=<token>` on the `rest` server. Leaving out the token connects as a spectator, who only receives public information. Whenever anything in the game changes, the server sends a text message of the form `{"public": <PublicState>, "private": <PrivateState or null>}`, starting with the current state right after connecting.
+//!
+//! Players can send the following text messages to act in the game. If an action is rejected, the server replies with `{"error": "<message>"}`.
+//!
+//! * `{"NightAction": <night action>}`, where the night action has the same format as for `POST /games/<id>/night-actions`.
+//! * `{"HunterTarget": "<name>"}`, or `{"HunterTarget": null}` to not shoot anyone.
+//! * `{"Vote": ["<name>", …]}`, where `null` stands for no lynch.
+
+use std::{
+    io::{
+        self,
+        BufReader,
+        prelude::*
+    },
+    net::{
+        Shutdown,
+        TcpStream
+    },
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{
+            AtomicBool,
+            Ordering::SeqCst
+        }
+    },
+    thread,
+    time::Duration
+};
+use serde::Deserialize;
+use crate::{
+    handler::voting::Choice,
+    server::{
+        PrivateState,
+        PublicState,
+        Session,
+        SessionError,
+        rest::{
+            NightActionRequest,
+            SharedSession
+        }
+    }
+};
+
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_MESSAGE_LEN: u64 = 64 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+#[derive(Deserialize)]
+enum ClientMessage {
+    NightAction(NightActionRequest),
+    HunterTarget(Option<String>),
+    Vote(Vec<Option<String>>)
+}
+
+/// Completes the WebSocket handshake for a request with the given `Sec-WebSocket-Key`, then pushes updates for the given session until the client disconnects.
+pub(crate) fn serve(mut reader: BufReader<TcpStream>, mut writer: TcpStream, key: &str, shared: &SharedSession, token: Option<String>) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", accept_key(key))?;
+    writer.flush()?;
+    let writer = Mutex::new(writer);
+    let closed = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            if let Err(e) = receive(&mut reader, &writer, shared, token.as_deref()) {
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    // status code 1002: protocol error
+                    let _ = send(&writer, OPCODE_CLOSE, &1002u16.to_be_bytes());
+                }
+            }
+            closed.store(true, SeqCst);
+            shared.changed.notify_all();
+        });
+        let result = push(&writer, shared, token.as_deref(), &closed);
+        // make sure the receiving thread stops
+        let _ = writer.lock().expect("WebSocket writer lock poisoned").shutdown(Shutdown::Both);
+        result
+    })
+}
+
+/// Sends the game state to the client whenever it changes.
+///
+/// The public state is shared by all clients of a session, and messages are serialized after releasing the session lock.
+fn push(writer: &Mutex<TcpStream>, shared: &SharedSession, token: Option<&str>, closed: &AtomicBool) -> io::Result<()> {
+    let mut last_version = None;
+    loop {
+        let (public, private) = {
+            let mut session = shared.session.lock().expect("session lock poisoned");
+            loop {
+                if closed.load(SeqCst) { return Ok(()) }
+                if last_version != Some(session.version()) {
+                    last_version = Some(session.version());
+                    break snapshot(&session, token)
+                }
+                // wake up regularly to notice when the client has disconnected without a close frame
+                session = shared.changed.wait_timeout(session, Duration::from_secs(30)).expect("session lock poisoned").0;
+            }
+        };
+        let message = serde_json::json!({ "public": &*public, "private": private }).to_string();
+        send(writer, OPCODE_TEXT, message.as_bytes())?;
+    }
+}
+
+fn snapshot(session: &Session, token: Option<&str>) -> (Arc<PublicState>, Option<PrivateState>) {
+    (session.public_state(), token.and_then(|token| session.private_state(token).ok()))
+}
+
+/// Handles messages from the client until it closes the connection.
+fn receive(reader: &mut BufReader<TcpStream>, writer: &Mutex<TcpStream>, shared: &SharedSession, token: Option<&str>) -> io::Result<()> {
+    loop {
+        let (opcode, payload) = read_message(reader)?;
+        match opcode {
+            OPCODE_TEXT => {
+                let result = match serde_json::from_slice::<ClientMessage>(&payload) {
+                    Ok(message) => {
+                        let result = handle(&mut shared.session.lock().expect("session lock poisoned"), token.unwrap_or_default(), message).map_err(|e| e.to_string());
+                        shared.changed.notify_all();
+                        result
+                    }
+                    Err(e) => Err(e.to_string())
+                };
+                if let Err(e) = result {
+                    send(writer, OPCODE_TEXT, serde_json::json!({ "error": e }).to_string().as_bytes())?;
+                }
+            }
+            OPCODE_CLOSE => {
+                send(writer, OPCODE_CLOSE, &[])?;
+                return Ok(());
+            }
+            OPCODE_PING => { send(writer, OPCODE_PONG, &payload)?; }
+            _ => {} // binary messages and pongs are ignored
+        }
+    }
+}
+
+fn handle(session: &mut Session, token: &str, message: ClientMessage) -> Result<(), SessionError> {
+    match message {
+        ClientMessage::NightAction(action) => {
+            let action = action.into_action(session.player(token)?.to_owned());
+            session.submit_night_action(token, action)
+        }
+        ClientMessage::HunterTarget(target) => session.set_hunter_target(token, target),
+        ClientMessage::Vote(ballot) => session.vote(token, ballot.into_iter().map(|choice| choice.map_or(Choice::NoLynch, Choice::Lynch)).collect())
+    }
+}
+
+/// Reads a complete message, joining fragmented frames. Control frames are returned immediately, since they can't be fragmented.
+fn read_message<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut message_opcode = None;
+    let mut message = Vec::default();
+    loop {
+        let (fin, opcode, payload) = read_frame(reader)?;
+        if opcode >= OPCODE_CLOSE { return Ok((opcode, payload)) }
+        if opcode != OPCODE_CONTINUATION { message_opcode = Some(opcode); }
+        message.extend(payload);
+        if message.len() as u64 > MAX_MESSAGE_LEN { return Err(io::Error::new(io::ErrorKind::InvalidData, "message too long")) }
+        if fin {
+            return message_opcode.map(|opcode| (opcode, message)).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "continuation frame without a message"))
+        }
+    }
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    // RFC 6455 §5.1: the server must close the connection if a client frame is not masked
+    if header[1] & 0x80 == 0 { return Err(io::Error::new(io::ErrorKind::InvalidData, "unmasked client frame")) }
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len).into()
+        }
+        127 => {
+            let mut len = [0; 8];
+            reader.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len.into()
+    };
+    if len > MAX_MESSAGE_LEN { return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long")) }
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+
+fn send<W: Write>(writer: &Mutex<W>, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    let mut writer = writer.lock().expect("WebSocket writer lock poisoned");
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// The value of the `Sec-WebSocket-Accept` header for the given `Sec-WebSocket-Key`.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key.trim(), GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h = [0x67452301u32, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([chunk[4 * i], chunk[4 * i + 1], chunk[4 * i + 2], chunk[4 * i + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6)
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+    let mut digest = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[4 * i..4 * i + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or_default(), chunk.get(2).copied().unwrap_or_default()];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize].into());
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a frame the way a client would, using the given length form: 0 for the shortest one, otherwise 126 or 127.
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8], len_form: u8) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+        match len_form {
+            0 => frame.push(0x80 | u8::try_from(payload.len()).expect("payload too long for the short length form")),
+            126 => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&u16::try_from(payload.len()).expect("payload too long for the 16-bit length form").to_be_bytes());
+            }
+            _ => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+        frame
+    }
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn sent(opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let writer = Mutex::new(Vec::default());
+        send(&writer, opcode, payload).expect("failed to send frame");
+        writer.into_inner().expect("writer lock poisoned")
+    }
+
+    #[test]
+    fn handshake() {
+        // RFC 6455 §1.3
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn sha1_vectors() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // longer than 55 bytes, so the padding needs a second block
+        assert_eq!(hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(hex(&sha1(&[b'a'; 1000])), "291e9a6c66994949b57ba5e650361e98fc36b1ba");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn unmasked_frame() {
+        let mut frame = client_frame(true, OPCODE_TEXT, b"hello", 0);
+        frame[1] &= 0x7f;
+        frame.drain(2..6);
+        let e = read_message(&mut &frame[..]).expect_err("unmasked frame was accepted");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn masked_frame() {
+        assert_eq!(read_message(&mut &client_frame(true, OPCODE_TEXT, b"hello", 0)[..]).expect("failed to read message"), (OPCODE_TEXT, b"hello".to_vec()));
+    }
+
+    #[test]
+    fn fragmented_message() {
+        let mut frames = client_frame(false, OPCODE_TEXT, b"hel", 0);
+        frames.extend(client_frame(false, OPCODE_CONTINUATION, b"", 0));
+        frames.extend(client_frame(true, OPCODE_CONTINUATION, b"lo", 0));
+        frames.extend(client_frame(true, OPCODE_TEXT, b"world", 0));
+        let mut reader = &frames[..];
+        assert_eq!(read_message(&mut reader).expect("failed to read message"), (OPCODE_TEXT, b"hello".to_vec()));
+        assert_eq!(read_message(&mut reader).expect("failed to read message"), (OPCODE_TEXT, b"world".to_vec()));
+        assert!(reader.is_empty());
+        let e = read_message(&mut &client_frame(true, OPCODE_CONTINUATION, b"lo", 0)[..]).expect_err("continuation without a message was accepted");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn length_forms() {
+        let payload = vec![b'a'; 200];
+        assert_eq!(read_message(&mut &client_frame(true, OPCODE_TEXT, &payload, 126)[..]).expect("failed to read message").1, payload);
+        let payload = vec![b'a'; MAX_MESSAGE_LEN as usize];
+        assert_eq!(read_message(&mut &client_frame(true, OPCODE_TEXT, &payload, 127)[..]).expect("failed to read message").1, payload);
+        let payload = vec![b'a'; MAX_MESSAGE_LEN as usize + 1];
+        let e = read_message(&mut &client_frame(true, OPCODE_TEXT, &payload, 127)[..]).expect_err("oversized frame was accepted");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        // each frame is short enough, but the message isn't
+        let mut frames = client_frame(false, OPCODE_TEXT, &vec![b'a'; MAX_MESSAGE_LEN as usize], 127);
+        frames.extend(client_frame(true, OPCODE_CONTINUATION, b"a", 0));
+        let e = read_message(&mut &frames[..]).expect_err("oversized message was accepted");
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn close_frame() {
+        let frame = client_frame(true, OPCODE_CLOSE, &1000u16.to_be_bytes(), 0);
+        assert_eq!(read_message(&mut &frame[..]).expect("failed to read message"), (OPCODE_CLOSE, 1000u16.to_be_bytes().to_vec()));
+        // control frames are returned as soon as they arrive
+        let mut frames = client_frame(false, OPCODE_TEXT, b"hel", 0);
+        frames.extend(client_frame(true, OPCODE_CLOSE, b"", 0));
+        assert_eq!(read_message(&mut &frames[..]).expect("failed to read message"), (OPCODE_CLOSE, Vec::default()));
+        assert_eq!(sent(OPCODE_CLOSE, &[]), [0x88, 0]);
+        assert_eq!(sent(OPCODE_CLOSE, &1002u16.to_be_bytes()), [0x88, 2, 0x03, 0xea]);
+    }
+
+    #[test]
+    fn sent_length_forms() {
+        assert_eq!(sent(OPCODE_TEXT, b"hi"), [0x81, 2, b'h', b'i']);
+        let frame = sent(OPCODE_TEXT, &[b'a'; 125]);
+        assert_eq!(frame[..2], [0x81, 125]);
+        assert_eq!(frame.len(), 2 + 125);
+        let frame = sent(OPCODE_TEXT, &[b'a'; 126]);
+        assert_eq!(frame[..4], [0x81, 126, 0, 126]);
+        assert_eq!(frame.len(), 4 + 126);
+        let frame = sent(OPCODE_TEXT, &[b'a'; 0x10000]);
+        assert_eq!(frame[..10], [0x81, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(frame.len(), 10 + 0x10000);
+    }
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use quantum_werewolf::game::{
+    self,
+    KillRule,
+    LazyMultiverse,
+    Multiverse,
+    MultiverseBackend,
+    Op,
+    ProbabilityTable,
+    RevealRule,
+    Role,
+    event::Event,
+    state::{
+        Rules,
+        Signups
+    }
+};
+use crate::common::{
+    Bot,
+    Log,
+    NAMES,
+    Recorder
+};
+
+fn assert_tables_eq(tables1: &[ProbabilityTable], tables2: &[ProbabilityTable]) {
+    assert_eq!(tables1.len(), tables2.len());
+    for (table1, table2) in tables1.iter().zip(tables2) {
+        assert_eq!(table1.len(), table2.len());
+        for (entry1, entry2) in table1.iter().zip(table2) {
+            match (entry1, entry2) {
+                (Ok((village1, werewolves1, dead1)), Ok((village2, werewolves2, dead2))) => {
+                    // the backends may add up the weights in a different order
+                    for (p1, p2) in [(village1, village2), (werewolves1, werewolves2), (dead1, dead2)] {
+                        assert!((p1 - p2).abs() < 1e-9, "{table1:?} != {table2:?}");
+                    }
+                }
+                (Err(faction1), Err(faction2)) => assert_eq!(faction1, faction2),
+                _ => panic!("{table1:?} != {table2:?}")
+            }
+        }
+    }
+}
+
+#[test]
+fn lazy_matches_eager() {
+    let rules = [
+        Rules::default(),
+        Rules { kill_rule: KillRule::Every, ..Rules::default() },
+        Rules { reveal: RevealRule::Faction, ..Rules::default() }
+    ];
+    // the lazy multiverse is slow in debug builds, so each set of rules is only tested with one seed
+    for (seed, rules) in rules.into_iter().enumerate() {
+        let seed = seed as u64;
+        let (winners, log) = common::play::<Multiverse>(seed, rules);
+        let (lazy_winners, lazy_log) = common::play::<LazyMultiverse>(seed, rules);
+        assert_eq!(winners.expect("game failed"), lazy_winners.expect("game failed"), "seed {seed}, {rules:?}");
+        assert_eq!(log.events, lazy_log.events, "seed {seed}, {rules:?}");
+        assert!(!log.probability_tables.is_empty());
+        assert_tables_eq(&log.probability_tables, &lazy_log.probability_tables);
+    }
+}
+
+#[test]
+fn lazy_sample_estimates_probability_table() {
+    let ops = [Op::Kill(0, 3), Op::Kill(1, 3), Op::WerewolfKill(KillRule::Dominant), Op::EndNight, Op::Lynch(5), Op::Collapse(5, Role::Detective)];
+    let mut multiverse = Multiverse::new(common::roles(), NAMES.len());
+    let mut lazy = LazyMultiverse::new(common::roles(), NAMES.len());
+    // fewer samples than role distributions, so the lazy multiverse has to sample
+    lazy.set_sample_budget(1000, 0);
+    for op in ops {
+        multiverse.apply(op);
+        lazy.apply(op);
+    }
+    let table = multiverse.probability_table().expect("no universes");
+    let estimate = lazy.probability_table().expect("no universes");
+    assert_eq!(table.len(), estimate.len());
+    for (entry, estimated_entry) in table.iter().zip(&estimate) {
+        match (entry, estimated_entry) {
+            (Ok((village, werewolves, dead)), Ok((estimated_village, estimated_werewolves, estimated_dead))) => {
+                for (p, estimated_p) in [(village, estimated_village), (werewolves, estimated_werewolves), (dead, estimated_dead)] {
+                    assert!((p - estimated_p).abs() < 0.1, "{table:?} != {estimate:?}");
+                }
+            }
+            (Err(faction), Err(estimated_faction)) => assert_eq!(faction, estimated_faction),
+            _ => panic!("{table:?} != {estimate:?}")
+        }
+    }
+    assert_eq!(lazy.role(5), Some(Role::Detective));
+}
+
+#[test]
+fn lazy_sample_plays_games() {
+    for seed in 0..3 {
+        let mut signups = Signups::with_seed(seed);
+        for name in NAMES {
+            signups.sign_up(Bot(name.to_owned()));
+        }
+        let mut game_state = signups.start_with_backend::<LazyMultiverse>(common::roles(), Rules::default()).expect("failed to start game");
+        game_state.set_sample_budget(100);
+        let mut log = Log::default();
+        game::resume(Recorder(&mut log), game_state).expect("game failed");
+        assert!(matches!(log.events.last(), Some(Event::GameOver(_))), "seed {seed}");
+        assert!(!log.probability_tables.is_empty(), "seed {seed}");
+        // the sample budget is saved with the game
+        let (_, json) = log.checkpoints.first().expect("no checkpoints");
+        assert!(json.contains("\"sampling\":[100,"), "seed {seed}");
+    }
+}
@@ -0,0 +1,127 @@
+//! A deterministic player and handler shared by the integration tests.
+
+#![allow(dead_code)] // not every test uses every helper
+
+use std::collections::HashSet;
+use serde::{
+    Deserialize,
+    Serialize
+};
+use quantum_werewolf::{
+    Handler,
+    Player,
+    game::{
+        self,
+        CauseOfDeath,
+        Faction,
+        MultiverseBackend,
+        ProbabilityTable,
+        Role,
+        event::Event,
+        state::{
+            GameError,
+            Rules,
+            Signups,
+            State
+        }
+    }
+};
+
+/// The player names used by `play`.
+pub const NAMES: [&str; 7] = ["alice", "bob", "carol", "dave", "erin", "frank", "grace"];
+
+/// The roles used by `play`, which include a few special roles to make the game less trivial. The remaining players are villagers.
+pub fn roles() -> Vec<Role> {
+    vec![Role::Werewolf(0), Role::Werewolf(1), Role::Detective, Role::Healer, Role::Hunter, Role::Witch]
+}
+
+/// A player who always makes the same choices in the same situation, regardless of the order in which the possible targets are given.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Bot(pub String);
+
+impl Bot {
+    fn first<'a>(&self, possible_targets: Vec<&'a Bot>) -> Option<&'a Bot> {
+        possible_targets.into_iter().filter(|&target| target != self).min()
+    }
+
+    fn last<'a>(&self, possible_targets: Vec<&'a Bot>) -> Option<&'a Bot> {
+        possible_targets.into_iter().filter(|&target| target != self).max()
+    }
+}
+
+impl Player for Bot {
+    fn recv_id(&self, _: usize) {}
+    fn recv_mason_partners(&self, _: Vec<&Bot>) {}
+
+    fn choose_lovers(&self, mut possible_targets: Vec<&Bot>) -> (Bot, Bot) {
+        possible_targets.sort();
+        (possible_targets[0].clone(), possible_targets[1].clone())
+    }
+
+    fn choose_heal_target(&self, possible_targets: Vec<&Bot>) -> Option<Bot> { self.last(possible_targets).cloned() }
+    fn choose_guard_target(&self, possible_targets: Vec<&Bot>) -> Option<Bot> { self.last(possible_targets).cloned() }
+    fn choose_investigation_target(&self, possible_targets: Vec<&Bot>) -> Option<Bot> { self.first(possible_targets).cloned() }
+    fn recv_investigation(&self, _: &Bot, _: Faction) {}
+    fn choose_seer_target(&self, possible_targets: Vec<&Bot>) -> Option<Bot> { self.first(possible_targets).cloned() }
+    fn recv_vision(&self, _: &Bot, _: Role) {}
+    fn choose_medium_target(&self, possible_targets: Vec<&Bot>) -> Option<Bot> { self.first(possible_targets).cloned() }
+    fn recv_seance(&self, _: &Bot, _: CauseOfDeath) {}
+    fn choose_werewolf_kill_target(&self, possible_targets: Vec<&Bot>, _: bool) -> Option<Bot> { self.first(possible_targets).cloned() }
+
+    fn choose_serial_killer_target(&self, possible_targets: Vec<&Bot>) -> Bot {
+        self.last(possible_targets).expect("no serial killer target").clone()
+    }
+
+    fn choose_witch_save(&self) -> bool { true }
+    fn choose_poison_target(&self, possible_targets: Vec<&Bot>) -> Option<Bot> { self.last(possible_targets).cloned() }
+    fn choose_vote(&self, candidates: Vec<&Bot>) -> Option<Bot> { self.first(candidates).cloned() }
+    fn choose_hunter_target(&self, possible_targets: Vec<&Bot>) -> Option<Bot> { self.first(possible_targets).cloned() }
+    fn recv_exile(&self, reason: &str) { panic!("{self:?} was exiled: {reason}") }
+}
+
+/// Everything a `Recorder` has seen during a game.
+#[derive(Debug, Default)]
+pub struct Log {
+    pub events: Vec<Event<Bot>>,
+    pub probability_tables: Vec<ProbabilityTable>,
+    /// Every checkpoint as JSON, along with the number of events recorded before it.
+    pub checkpoints: Vec<(usize, String)>,
+    /// The votes announced using `Handler::announce_votes`.
+    pub announced_votes: Vec<Vec<(Bot, Option<Bot>)>>
+}
+
+/// A handler which records the game in a `Log` and always lynches the alive player whose name comes last.
+pub struct Recorder<'a>(pub &'a mut Log);
+
+impl Handler<Bot> for Recorder<'_> {
+    fn announce_probability_table<I: IntoIterator<Item = Result<(f64, f64, f64), Faction>>>(&mut self, probability_table: I) {
+        self.0.probability_tables.push(probability_table.into_iter().collect());
+    }
+
+    fn checkpoint<M: MultiverseBackend>(&mut self, game_state: &State<Bot, M>) {
+        self.0.checkpoints.push((self.0.events.len(), game_state.to_json().expect("failed to save game")));
+    }
+
+    fn record_event(&mut self, event: &Event<Bot>) {
+        self.0.events.push(event.clone());
+    }
+
+    fn announce_votes<I: IntoIterator<Item = (Bot, Option<Bot>)>>(&mut self, votes: I) {
+        self.0.announced_votes.push(votes.into_iter().collect());
+    }
+
+    fn choose_lynch_target(&mut self, alive: HashSet<&Bot>) -> Option<Bot> {
+        alive.into_iter().max().cloned()
+    }
+}
+
+/// Plays a complete game with the players from `NAMES` and the roles from `roles()`, and returns the winners along with everything the handler has seen.
+pub fn play<M: MultiverseBackend>(seed: u64, rules: Rules) -> (Result<HashSet<Bot>, GameError>, Log) {
+    let mut signups = Signups::with_seed(seed);
+    for name in NAMES {
+        signups.sign_up(Bot(name.to_owned()));
+    }
+    let mut log = Log::default();
+    let winners = game::run_with_backend::<_, _, M>(Recorder(&mut log), signups, roles(), rules);
+    (winners, log)
+}
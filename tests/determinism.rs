@@ -0,0 +1,51 @@
+mod common;
+
+use quantum_werewolf::game::{
+    self,
+    Multiverse,
+    event::Event,
+    state::{
+        Rules,
+        State
+    }
+};
+use crate::common::{
+    Bot,
+    Log,
+    Recorder
+};
+
+#[test]
+fn same_seed_same_game() {
+    for seed in 0..5 {
+        let (winners1, log1) = common::play::<Multiverse>(seed, Rules::default());
+        let (winners2, log2) = common::play::<Multiverse>(seed, Rules::default());
+        assert_eq!(winners1.expect("game failed"), winners2.expect("game failed"), "seed {seed}");
+        assert_eq!(log1.events, log2.events, "seed {seed}");
+        assert!(matches!(log1.events.last(), Some(Event::GameOver(_))), "seed {seed}");
+    }
+}
+
+#[test]
+fn seed_affects_game() {
+    let logs = (0..5).map(|seed| common::play::<Multiverse>(seed, Rules::default()).1.events).collect::<Vec<_>>();
+    assert!(logs.iter().any(|events| *events != logs[0]), "all seeds produced the same game");
+}
+
+#[test]
+fn resume_saved_game() {
+    for seed in 0..2 {
+        let (winners, log) = common::play::<Multiverse>(seed, Rules::default());
+        let winners = winners.expect("game failed");
+        for (num_events, json) in &log.checkpoints {
+            let game_state = State::<Bot, Multiverse>::from_json(json).expect("failed to load game");
+            if !matches!(game_state, State::Night(_) | State::Day(_)) { continue }
+            let mut resumed_log = Log::default();
+            let resumed_winners = game::resume(Recorder(&mut resumed_log), game_state).expect("resumed game failed");
+            assert_eq!(resumed_winners, winners, "seed {seed}");
+            // deaths during the phase before the checkpoint are announced by the original game only
+            let expected = log.events[*num_events..].iter().skip_while(|event| matches!(event, Event::Death(..))).cloned().collect::<Vec<_>>();
+            assert_eq!(resumed_log.events, expected, "seed {seed}");
+        }
+    }
+}
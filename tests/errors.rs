@@ -0,0 +1,138 @@
+mod common;
+
+use quantum_werewolf::game::{
+    self,
+    NightAction,
+    Role,
+    state::{
+        GameError,
+        NightChoices,
+        Rules,
+        Signups,
+        StartGameError,
+        State
+    }
+};
+use crate::common::{
+    Bot,
+    Log,
+    Recorder
+};
+
+const NAMES: [&str; 6] = ["alice", "bob", "carol", "dave", "erin", "frank"];
+
+fn start(roles: Vec<Role>, rules: Rules) -> State<&'static str> {
+    let mut signups = Signups::with_seed(0);
+    for name in NAMES {
+        signups.sign_up(name);
+    }
+    signups.start(roles, rules).expect("failed to start game")
+}
+
+/// Plays the first night without any night actions, so the werewolf kill is randomized.
+fn first_day(roles: Vec<Role>, rules: Rules) -> State<&'static str> {
+    let State::Night(night) = start(roles, rules) else { panic!("game did not start with a night") };
+    night.resolve_nar(&[], |_, _| None).expect("failed to resolve night")
+}
+
+/// Night choices which only ever name a player who is not in the game.
+struct Nobody;
+
+impl NightChoices<&'static str> for Nobody {
+    fn lovers(&self, _: &&'static str, _: Vec<&&'static str>) -> (&'static str, &'static str) { ("nobody", "nobody") }
+    fn heal_target(&self, _: &&'static str, _: Vec<&&'static str>) -> Option<&'static str> { Some("nobody") }
+    fn guard_target(&self, _: &&'static str, _: Vec<&&'static str>) -> Option<&'static str> { Some("nobody") }
+    fn investigation_target(&self, _: &&'static str, _: Vec<&&'static str>) -> Option<&'static str> { Some("nobody") }
+    fn seer_target(&self, _: &&'static str, _: Vec<&&'static str>) -> Option<&'static str> { Some("nobody") }
+    fn medium_target(&self, _: &&'static str, _: Vec<&&'static str>) -> Option<&'static str> { Some("nobody") }
+    fn werewolf_kill_target(&self, _: &&'static str, _: Vec<&&'static str>, _: bool) -> Option<&'static str> { Some("nobody") }
+    fn serial_killer_target(&self, _: &&'static str, _: Vec<&&'static str>) -> &'static str { "nobody" }
+    fn witch_save(&self, _: &&'static str) -> bool { false }
+    fn poison_target(&self, _: &&'static str, _: Vec<&&'static str>) -> Option<&'static str> { Some("nobody") }
+    fn hunter_target(&self, _: &&'static str, _: Vec<&&'static str>) -> Option<&'static str> { Some("nobody") }
+}
+
+#[test]
+fn start_error() {
+    let mut signups = Signups::with_seed(0);
+    signups.sign_up(Bot("alice".to_owned()));
+    let mut log = Log::default();
+    let result = game::run_with_roles(Recorder(&mut log), signups, vec![Role::Werewolf(0)], Rules::default());
+    assert!(matches!(result, Err(GameError::Start(StartGameError::NotEnoughPlayers { required: 3, found: 1 }))));
+}
+
+#[test]
+fn unknown_player() {
+    let State::Night(night) = start(vec![Role::Werewolf(0), Role::Healer], Rules::default()) else { panic!("game did not start with a night") };
+    assert!(matches!(night.check_night_action(&NightAction::Heal("nobody", "alice")), Err(GameError::UnknownPlayer)));
+    assert!(matches!(night.check_night_action(&NightAction::Heal("alice", "nobody")), Err(GameError::UnknownPlayer)));
+    let State::Day(day) = first_day(vec![Role::Werewolf(0)], Rules::default()) else { panic!("game did not continue with a day") };
+    assert!(!day.can_lynch(&"nobody"));
+    assert!(matches!(day.lynch("nobody", |_, _| None), Err(GameError::UnknownPlayer)));
+}
+
+#[test]
+fn dead_target() {
+    // the player lynched on the first day is dead in all universes on the second day
+    for lynched in NAMES {
+        let State::Day(day) = first_day(vec![Role::Werewolf(0)], Rules::default()) else { panic!("game did not continue with a day") };
+        if !day.can_lynch(&lynched) { continue }
+        let State::Night(night) = day.lynch(lynched, |_, _| None).expect("failed to lynch") else { continue };
+        let src = **night.alive().iter().next().expect("no alive players");
+        assert!(matches!(night.check_night_action(&NightAction::Kill(src, Some(lynched))), Err(GameError::DeadTarget)));
+        let State::Day(day) = night.resolve_nar(&[], |_, _| None).expect("failed to resolve night") else { continue };
+        assert!(!day.can_lynch(&lynched));
+        assert!(matches!(day.lynch(lynched, |_, _| None), Err(GameError::DeadTarget)));
+        return
+    }
+    panic!("the game never reached a second day")
+}
+
+#[test]
+fn illegal_action() {
+    let State::Day(day) = first_day(vec![Role::Werewolf(0)], Rules { no_lynch: false, ..Rules::default() }) else { panic!("game did not continue with a day") };
+    assert!(!day.can_skip_lynch());
+    assert!(matches!(day.no_lynch(), Err(GameError::IllegalAction(_))));
+    let State::Night(night) = start(vec![Role::Werewolf(0), Role::Healer], Rules { self_heals: false, ..Rules::default() }) else { panic!("game did not start with a night") };
+    assert!(matches!(night.check_night_action(&NightAction::Heal("alice", "alice")), Err(GameError::IllegalAction(_))));
+}
+
+#[test]
+fn paradox() {
+    let mut signups = Signups::with_seed(0);
+    for name in NAMES {
+        signups.sign_up(Bot(name.to_owned()));
+    }
+    let State::Night(night) = signups.start(vec![Role::Werewolf(0)], Rules::default()).expect("failed to start game") else { panic!("game did not start with a night") };
+    let day = night.resolve_nar(&[], |_, _| None).expect("failed to resolve night");
+    assert!(matches!(day, State::Day(_)));
+    // a corrupted save file without any possible universes
+    let mut json = serde_json::from_str::<serde_json::Value>(&day.to_json().expect("failed to save game")).expect("failed to parse save");
+    json["Day"]["multiverse"] = serde_json::Value::Array(Vec::default());
+    let State::Day(day) = State::<Bot>::from_json(&json.to_string()).expect("failed to load game") else { panic!("game did not load as a day") };
+    assert!(matches!(day.probability_table(), Err(GameError::Paradox)));
+}
+
+#[test]
+fn illegal_choices_are_no_action() {
+    // none of these night actions are compulsory, so nothing happens
+    let rules = Rules { mandatory_werewolf_kill: false, ..Rules::default() };
+    let State::Night(night) = start(vec![Role::Werewolf(0), Role::Healer, Role::Bodyguard, Role::Detective, Role::Seer, Role::Witch], rules) else { panic!("game did not start with a night") };
+    let State::Day(day) = night.resolve_tar(&Nobody).expect("illegal choices caused an error") else { panic!("game did not continue with a day") };
+    let table = day.probability_table().expect("no universes");
+    assert!(table.iter().all(|entry| matches!(entry, Ok((_, _, dead)) if *dead == 0.0)), "{table:?}");
+    assert!(day.night_action_results().is_empty());
+}
+
+#[test]
+fn illegal_compulsory_choices_are_randomized() {
+    let State::Night(night) = start(vec![Role::Werewolf(0), Role::SerialKiller], Rules::default()) else { panic!("game did not start with a night") };
+    match night.resolve_tar(&Nobody).expect("illegal choices caused an error") {
+        State::Day(day) => {
+            let table = day.probability_table().expect("no universes");
+            assert!(table.iter().any(|entry| !matches!(entry, Ok((_, _, dead)) if *dead == 0.0)), "nobody was killed: {table:?}");
+        }
+        State::Complete(_) => {} // only possible if someone was killed
+        _ => panic!("game did not continue with a day")
+    }
+}
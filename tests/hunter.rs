@@ -0,0 +1,102 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet
+};
+use quantum_werewolf::game::{
+    RevealRule,
+    Role,
+    state::{
+        Rules,
+        Signups,
+        State
+    }
+};
+
+const NAMES: [&str; 6] = ["alice", "bob", "carol", "dave", "erin", "frank"];
+
+/// Checks that exactly the players who died as the hunter during a phase were asked whom to shoot, and that their targets died.
+///
+/// Returns the number of hunters who were asked.
+fn check_phase(before: &HashSet<&str>, after: &State<&str>, asked: &[(&str, &str)]) -> usize {
+    let Some(alive) = after.alive() else { return asked.len() }; // the game is over, so roles are no longer known
+    for &(hunter, target) in asked {
+        assert_eq!(after.role(&hunter), Some(Role::Hunter), "{hunter} was asked to shoot without being the hunter");
+        assert!(!alive.contains(&hunter), "{hunter} was asked to shoot while alive");
+        assert!(!alive.contains(&target), "the target of {hunter} survived");
+    }
+    for &player in before {
+        if !alive.contains(&player) && after.role(&player) == Some(Role::Hunter) {
+            assert!(asked.iter().any(|&(hunter, _)| hunter == player), "{player} died as the hunter without being asked to shoot");
+        }
+    }
+    asked.len()
+}
+
+/// Plays the first night and lynches a player, and returns the number of hunters who were asked to shoot.
+fn play(seed: u64, reveal: RevealRule) -> usize {
+    let mut signups = Signups::with_seed(seed);
+    for name in NAMES {
+        signups.sign_up(name);
+    }
+    let rules = Rules { reveal, ..Rules::default() };
+    let asked = RefCell::new(Vec::default());
+    let choose_hunter_target = |&hunter: &&'static str, targets: Vec<&&'static str>| -> Option<&'static str> {
+        let target = targets.into_iter().max().copied().expect("no hunter targets");
+        asked.borrow_mut().push((hunter, target));
+        Some(target)
+    };
+    let State::Night(night) = signups.start(vec![Role::Werewolf(0), Role::Hunter], rules).expect("failed to start game") else { panic!("game did not start with a night") };
+    let before = night.alive().into_iter().copied().collect();
+    // the werewolf kill is randomized
+    let game_state = night.resolve_nar(&[], choose_hunter_target).expect("failed to resolve night");
+    let mut num_asked = check_phase(&before, &game_state, &asked.take());
+    let State::Day(day) = game_state else { return num_asked };
+    let before = day.alive().into_iter().copied().collect();
+    let lynched = day.alive().into_iter().min().copied().expect("no alive players");
+    let game_state = day.lynch(lynched, choose_hunter_target).expect("failed to lynch");
+    num_asked += check_phase(&before, &game_state, &asked.take());
+    num_asked
+}
+
+#[test]
+fn only_hunters_shoot() {
+    for reveal in [RevealRule::Role, RevealRule::Nothing] {
+        let num_asked = (0..30).map(|seed| play(seed, reveal)).sum::<usize>();
+        assert!(num_asked > 0, "no hunter was ever asked to shoot with {reveal:?}");
+    }
+}
+
+#[test]
+fn unasked_dead_players_are_not_hunters() {
+    let mut num_checked = 0;
+    for seed in 0..30 {
+        let mut signups = Signups::with_seed(seed);
+        for name in NAMES {
+            signups.sign_up(name);
+        }
+        let rules = Rules { reveal: RevealRule::Nothing, ..Rules::default() };
+        let asked = RefCell::new(Vec::default());
+        let choose_hunter_target = |&hunter: &&'static str, targets: Vec<&&'static str>| -> Option<&'static str> {
+            asked.borrow_mut().push(hunter);
+            targets.into_iter().max().copied()
+        };
+        let State::Night(night) = signups.start(vec![Role::Werewolf(0), Role::Hunter], rules).expect("failed to start game") else { panic!("game did not start with a night") };
+        // nobody is dead in all universes until the first lynch
+        let State::Day(day) = night.resolve_nar(&[], choose_hunter_target).expect("failed to resolve night") else { continue };
+        let lynched = day.alive().into_iter().min().copied().expect("no alive players");
+        let State::Night(night) = day.lynch(lynched, choose_hunter_target).expect("failed to lynch") else { continue };
+        let State::Day(day) = night.resolve_nar(&[], choose_hunter_target).expect("failed to resolve night") else { continue };
+        let asked = asked.take();
+        let alive = day.alive();
+        // the roles of dead players are not revealed, but no remaining universe may have a dead hunter who didn't get to shoot
+        for (player, entry) in day.secret_ids().iter().zip(day.role_probability_table()) {
+            if alive.contains(player) || asked.contains(player) { continue }
+            num_checked += 1;
+            match entry {
+                Ok(probabilities) => assert!(probabilities.iter().all(|&(role, _)| role != Role::Hunter), "{player} may have been a hunter without shooting: {probabilities:?}"),
+                Err(role) => assert_ne!(role, Role::Hunter, "{player} was a hunter without shooting"),
+            }
+        }
+    }
+    assert!(num_checked > 0, "no player died without being asked to shoot");
+}
@@ -0,0 +1,57 @@
+use quantum_werewolf::game::{
+    Multiverse,
+    Role,
+    state::{
+        Rules,
+        Signups,
+        State
+    }
+};
+
+const NAMES: [&str; 6] = ["alice", "bob", "carol", "dave", "erin", "frank"];
+
+#[test]
+fn masons_learn_each_other() {
+    let mut num_messages = 0;
+    let mut num_silent = 0;
+    for seed in 0..20 {
+        let mut signups = Signups::with_seed(seed);
+        for name in NAMES {
+            signups.sign_up(name.to_owned());
+        }
+        let game_state = signups.start(vec![Role::Werewolf(0), Role::Mason, Role::Mason, Role::Mason], Rules::default()).expect("failed to start game");
+        let State::Night(ref night) = game_state else { panic!("game did not start with a night") };
+        let secret_ids = night.secret_ids();
+        let mason_partners = night.mason_partners();
+        // the multiverse is only accessible through a save file
+        let json = serde_json::from_str::<serde_json::Value>(&game_state.to_json().expect("failed to save game")).expect("failed to parse save");
+        let multiverse = serde_json::from_value::<Multiverse>(json["Night"]["multiverse"].clone()).expect("failed to parse multiverse");
+        for (player_idx, player) in secret_ids.iter().enumerate() {
+            let partners = mason_partners.iter().find(|&&(iter_player, _)| iter_player == player).map(|(_, partners)| partners);
+            match partners {
+                // in every remaining universe in which the player is a mason, the other masons are exactly the ones they have been told about
+                Some(partners) => {
+                    num_messages += 1;
+                    for universe in multiverse.iter().filter(|universe| universe.role(player_idx) == Role::Mason) {
+                        let mut others = secret_ids.iter().enumerate()
+                            .filter(|&(iter_idx, _)| iter_idx != player_idx && universe.role(iter_idx) == Role::Mason)
+                            .map(|(_, iter_player)| iter_player)
+                            .collect::<Vec<_>>();
+                        others.sort();
+                        let mut partners = partners.clone();
+                        partners.sort();
+                        assert_eq!(partners, others, "seed {seed}: {player} was told about the wrong masons");
+                    }
+                }
+                // a player who isn't told anything can't be a mason
+                None => {
+                    num_silent += 1;
+                    assert!(multiverse.iter().all(|universe| universe.role(player_idx) != Role::Mason), "seed {seed}: {player} may be a mason but wasn't told about the others");
+                }
+            }
+        }
+        assert!(multiverse.iter().any(|universe| universe.roles().filter(|&role| role == Role::Mason).count() == 3), "seed {seed}: no universes left");
+    }
+    assert!(num_messages > 0);
+    assert!(num_silent > 0, "every player may have been a mason");
+}
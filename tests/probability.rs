@@ -0,0 +1,122 @@
+use rand::SeedableRng as _;
+use rand_chacha::ChaCha20Rng;
+use quantum_werewolf::game::{
+    Estimate,
+    KillRule,
+    LazyMultiverse,
+    Multiverse,
+    MultiverseBackend,
+    Op,
+    Role,
+    Universe
+};
+
+fn weights(multiverse: &Multiverse) -> Vec<u64> {
+    let mut weights = multiverse.iter().map(Universe::weight).collect::<Vec<_>>();
+    weights.sort_unstable();
+    weights
+}
+
+#[test]
+fn merged_universes_keep_weight() {
+    // the two masons are interchangeable, so each distinct universe stands for 2 permutations
+    let multiverse = Multiverse::new(vec![Role::Mason, Role::Mason], 4);
+    assert_eq!(weights(&multiverse), [2; 6]);
+    assert_eq!(multiverse.total_weight(), 12);
+}
+
+#[test]
+fn dedup_keeps_probability_table() {
+    let mut multiverse = [
+        vec![Role::Werewolf(0), Role::Villager, Role::Villager],
+        vec![Role::Villager, Role::Werewolf(0), Role::Villager],
+        vec![Role::Werewolf(0), Role::Villager, Role::Villager],
+        vec![Role::Werewolf(0), Role::Villager, Role::Villager],
+        vec![Role::Villager, Role::Villager, Role::Werewolf(0)],
+    ].into_iter().map(Universe::from).collect::<Multiverse>();
+    let table = multiverse.probability_table().expect("no universes");
+    let role_table = multiverse.role_probability_table();
+    multiverse.dedup();
+    assert_eq!(weights(&multiverse), [1, 1, 3]);
+    assert_eq!(multiverse.total_weight(), 5);
+    assert_eq!(multiverse.probability_table().expect("no universes"), table);
+    assert_eq!(multiverse.role_probability_table(), role_table);
+}
+
+/// Checks that each player's role probabilities add up to 1 and match the expected ones, in any order.
+fn assert_role_table<M: MultiverseBackend>(multiverse: &M, expected: &[Result<Vec<(Role, f64)>, Role>]) {
+    let table = multiverse.role_probability_table();
+    assert_eq!(table.len(), expected.len());
+    for (entry, expected_entry) in table.iter().zip(expected) {
+        match (entry, expected_entry) {
+            (Ok(probabilities), Ok(expected_probabilities)) => {
+                assert!((probabilities.iter().map(|&(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9, "{table:?}");
+                assert_eq!(probabilities.len(), expected_probabilities.len(), "{table:?}");
+                for &(role, expected_p) in expected_probabilities {
+                    let &(_, p) = probabilities.iter().find(|&&(iter_role, _)| iter_role == role).unwrap_or_else(|| panic!("{role:?} missing from {table:?}"));
+                    assert!((p - expected_p).abs() < 1e-9, "{table:?}");
+                }
+            }
+            (Err(role), Err(expected_role)) => assert_eq!(role, expected_role),
+            _ => panic!("{table:?} != {expected:?}")
+        }
+    }
+}
+
+fn role_table_matches_hand_computed<M: MultiverseBackend>() {
+    // of the 12 permutations, each player is the werewolf in 3 and a mason in 6
+    let multiverse = M::new(vec![Role::Werewolf(0), Role::Mason, Role::Mason], 4);
+    let entry = Ok(vec![(Role::Werewolf(0), 0.25), (Role::Mason, 0.5), (Role::Villager, 0.25)]);
+    assert_role_table(&multiverse, &[entry.clone(), entry.clone(), entry.clone(), entry]);
+    // the lynched villager's role is known, the others are equally likely to have each of the remaining roles
+    let mut multiverse = M::new(vec![Role::Werewolf(0), Role::Detective], 3);
+    multiverse.apply(Op::Lynch(2));
+    multiverse.apply(Op::Collapse(2, Role::Villager));
+    let entry = Ok(vec![(Role::Werewolf(0), 0.5), (Role::Detective, 0.5)]);
+    assert_role_table(&multiverse, &[entry.clone(), entry, Err(Role::Villager)]);
+}
+
+#[test]
+fn role_table_matches_hand_computed_eager() {
+    role_table_matches_hand_computed::<Multiverse>();
+}
+
+#[test]
+fn role_table_matches_hand_computed_lazy() {
+    role_table_matches_hand_computed::<LazyMultiverse>();
+}
+
+#[test]
+fn estimate_bounds() {
+    // reference values for the 95% Wilson score interval
+    for (successes, sample_size, low, high) in [(5, 10, 0.2366, 0.7634), (0, 10, 0.0, 0.2775), (10, 10, 0.7225, 1.0), (50, 100, 0.4038, 0.5962)] {
+        let estimate = Estimate::new(successes, sample_size);
+        assert_eq!(estimate.value, successes as f64 / sample_size as f64);
+        assert!((estimate.low - low).abs() < 1e-4, "{successes}/{sample_size}: {estimate:?}");
+        assert!((estimate.high - high).abs() < 1e-4, "{successes}/{sample_size}: {estimate:?}");
+    }
+    // without any samples, nothing is known
+    assert_eq!(Estimate::new(0, 0), Estimate { value: 0.5, low: 0.0, high: 1.0 });
+}
+
+#[test]
+fn approximate_table_brackets_exact() {
+    let mut multiverse = Multiverse::new(vec![Role::Werewolf(0), Role::Werewolf(1), Role::Detective, Role::Healer], 7);
+    for op in [Op::Kill(0, 3), Op::Kill(1, 3), Op::WerewolfKill(KillRule::Dominant), Op::EndNight, Op::Lynch(5)] {
+        multiverse.apply(op);
+    }
+    let table = multiverse.probability_table().expect("no universes");
+    let estimate = multiverse.approximate_probability_table(&mut ChaCha20Rng::seed_from_u64(0), 1000);
+    assert_eq!(table.len(), estimate.len());
+    for (entry, estimated_entry) in table.into_iter().zip(estimate) {
+        match (entry, estimated_entry) {
+            (Ok((village, werewolves, dead)), Ok((estimated_village, estimated_werewolves, estimated_dead))) => {
+                for (p, estimate) in [(village, estimated_village), (werewolves, estimated_werewolves), (dead, estimated_dead)] {
+                    assert!(estimate.low <= p && p <= estimate.high, "{p} is outside of {estimate:?}");
+                }
+            }
+            (Err(faction), Err(estimated_faction)) => assert_eq!(faction, estimated_faction),
+            (entry, estimated_entry) => panic!("{entry:?} != {estimated_entry:?}")
+        }
+    }
+}
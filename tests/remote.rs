@@ -0,0 +1,99 @@
+use std::{
+    io::{
+        BufReader,
+        prelude::*
+    },
+    net::{
+        Shutdown,
+        TcpListener,
+        TcpStream
+    }
+};
+use quantum_werewolf::{
+    Player,
+    handler::voting::{
+        Choice,
+        Poll,
+        VotingSystem
+    },
+    player::RemotePlayer
+};
+
+/// Connects a remote player over loopback TCP, and returns the player along with the other end of the connection.
+fn connect(name: &str) -> (RemotePlayer, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind listener");
+    let client = TcpStream::connect(listener.local_addr().expect("failed to get listener address")).expect("failed to connect");
+    let (server, _) = listener.accept().expect("failed to accept connection");
+    let reader = BufReader::new(server.try_clone().expect("failed to clone stream"));
+    (RemotePlayer::new(name.to_owned(), reader, server), client)
+}
+
+/// Connects a remote player who has already sent the given lines and then closed their end of the connection.
+fn connect_with_answers(name: &str, answers: &[&str]) -> (RemotePlayer, TcpStream) {
+    let (player, mut client) = connect(name);
+    for answer in answers {
+        writeln!(client, "{answer}").expect("failed to send answer");
+    }
+    client.shutdown(Shutdown::Write).expect("failed to close connection");
+    (player, client)
+}
+
+/// Drops the player so the connection is closed, and returns everything they have been sent.
+fn output(player: RemotePlayer, mut client: TcpStream) -> String {
+    drop(player);
+    let mut output = String::default();
+    client.read_to_string(&mut output).expect("failed to read output");
+    output
+}
+
+fn players(names: &[&str]) -> Vec<RemotePlayer> {
+    names.iter().map(|&name| connect_with_answers(name, &[]).0).collect()
+}
+
+#[test]
+fn choose_target() {
+    let targets = players(&["bob", "carol"]);
+    let (player, client) = connect_with_answers("alice", &["carol"]);
+    assert_eq!(player.choose_heal_target(targets.iter().collect()).map(|target| target.to_string()), Some("carol".to_owned()));
+    let output = output(player, client);
+    assert_eq!(output, "[ ?? ] player to heal: ");
+}
+
+#[test]
+fn unknown_name_prompts_again() {
+    let targets = players(&["bob", "carol"]);
+    let (player, client) = connect_with_answers("alice", &["dave", "bob"]);
+    assert_eq!(player.choose_poison_target(targets.iter().collect()).map(|target| target.to_string()), Some("bob".to_owned()));
+    let output = output(player, client);
+    assert_eq!(output, "[ ?? ] player to poison: [ __ ] no such player\n[ ?? ] player to poison: ");
+}
+
+#[test]
+fn disconnect_uses_default_target() {
+    let targets = players(&["bob", "carol"]);
+    let (player, _client) = connect_with_answers("alice", &[]);
+    // optional actions are skipped, compulsory ones go to the first possible target
+    assert!(player.choose_heal_target(targets.iter().collect()).is_none());
+    assert_eq!(player.choose_serial_killer_target(targets.iter().collect()).to_string(), "bob");
+    assert_eq!(player.choose_werewolf_kill_target(targets.iter().collect(), true).map(|target| target.to_string()), Some("bob".to_owned()));
+    let (lover1, lover2) = player.choose_lovers(targets.iter().collect());
+    assert_eq!((lover1.to_string(), lover2.to_string()), ("bob".to_owned(), "carol".to_owned()));
+    assert!(!player.choose_witch_save());
+}
+
+#[test]
+fn disconnected_voter_votes_no_lynch() {
+    let (alice, alice_client) = connect_with_answers("alice", &["carol"]);
+    let (bob, _bob_client) = connect_with_answers("bob", &[]);
+    let carol = players(&["carol"]).remove(0);
+    let voters = vec![alice.clone(), bob.clone(), carol.clone()];
+    let mut poll = Poll::new(VotingSystem::Plurality, voters.clone(), voters, true);
+    let votes = poll.collect_votes();
+    let votes = votes.into_iter().map(|(voter, vote)| (voter.to_string(), vote.map(|vote| vote.to_string()))).collect::<Vec<_>>();
+    assert_eq!(votes, [("alice".to_owned(), Some("carol".to_owned())), ("bob".to_owned(), None), ("carol".to_owned(), None)]);
+    assert_eq!(poll.ballot(&bob), Some(&[Choice::NoLynch][..]));
+    assert_eq!(poll.tally().decision, None);
+    drop(poll);
+    drop((bob, carol));
+    assert!(output(alice, alice_client).starts_with("[ ?? ] town lynch target [leave blank for no lynch]: "));
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::cell::RefCell;
+use quantum_werewolf::game::{
+    LazyMultiverse,
+    Multiverse,
+    MultiverseBackend,
+    NightAction,
+    event::{
+        self,
+        Event,
+        Resolution
+    },
+    state::{
+        Rules,
+        Signups,
+        State
+    }
+};
+use crate::common::Bot;
+
+/// The players alive after each phase, sorted by name.
+fn alive<M: MultiverseBackend>(game_state: &State<Bot, M>) -> Option<Vec<Bot>> {
+    game_state.alive().map(|alive| {
+        let mut alive = alive.into_iter().cloned().collect::<Vec<_>>();
+        alive.sort();
+        alive
+    })
+}
+
+fn check_replay<M: MultiverseBackend>(events: &[Event<Bot>], expected_alive: &[Option<Vec<Bot>>]) {
+    let mut replayed_alive = Vec::default();
+    let game_state = event::replay::<_, M, _, _>(events.iter().cloned(), |game_state| replayed_alive.push(alive(game_state))).expect("replay failed");
+    let State::Complete(complete) = game_state else { panic!("replayed game is not over") };
+    let Some(Event::GameOver(winners)) = events.last() else { panic!("event log does not end with the game over") };
+    assert_eq!(complete.winners, winners.iter().cloned().collect());
+    assert_eq!(replayed_alive, expected_alive);
+}
+
+#[test]
+fn temporal() {
+    for seed in 0..3 {
+        let (winners, log) = common::play::<Multiverse>(seed, Rules::default());
+        winners.expect("game failed");
+        // the alive players after each phase, reconstructed from the deaths in the log, which are recorded after the phase which caused them
+        let mut expected_alive = vec![Some(common::NAMES.iter().map(|&name| Bot(name.to_owned())).collect::<Vec<_>>())];
+        for event in &log.events {
+            match event {
+                Event::Death(player, _) => expected_alive.last_mut().and_then(Option::as_mut).expect("no phases").retain(|iter_player| iter_player != player),
+                Event::Dawn | Event::Lynch(_) => expected_alive.push(expected_alive.last().cloned().expect("no phases")),
+                _ => {}
+            }
+        }
+        // the game is over after the last phase, so no one is alive anymore
+        *expected_alive.last_mut().expect("no phases") = None;
+        check_replay::<Multiverse>(&log.events, &expected_alive);
+        check_replay::<LazyMultiverse>(&log.events, &expected_alive);
+    }
+}
+
+#[test]
+fn natural() {
+    for seed in 0..3 {
+        let mut events = Vec::default();
+        let mut signups = Signups::with_seed(seed);
+        for name in common::NAMES {
+            signups.sign_up(Bot(name.to_owned()));
+            events.push(Event::SignUp(Bot(name.to_owned())));
+        }
+        events.push(Event::Start { seed, roles: common::roles(), rules: Rules::default(), resolution: Resolution::Natural });
+        let mut game_state = signups.start(common::roles(), Rules::default()).expect("failed to start game");
+        events.push(Event::SecretIds(game_state.secret_ids().expect("no secret IDs").to_owned()));
+        let mut expected_alive = vec![alive(&game_state)];
+        let hunter_shots = RefCell::new(Vec::default());
+        let hunter_target = |hunter: &Bot, targets: Vec<&Bot>| {
+            let target = targets.into_iter().filter(|&target| target != hunter).min().cloned();
+            hunter_shots.borrow_mut().push(Event::HunterShot(hunter.clone(), target.clone()));
+            target
+        };
+        game_state = loop {
+            game_state = match game_state {
+                State::Signups(_) => unreachable!(),
+                State::Night(night) => {
+                    let mut alive = night.alive().into_iter().cloned().collect::<Vec<_>>();
+                    alive.sort();
+                    let mut night_actions = Vec::default();
+                    for player in &alive {
+                        let others = alive.iter().filter(|&other| other != player).collect::<Vec<_>>();
+                        night_actions.push(NightAction::Heal(player.clone(), others[others.len() - 1].clone()));
+                        night_actions.push(NightAction::Investigate(player.clone(), others[0].clone()));
+                        night_actions.push(NightAction::Kill(player.clone(), Some(others[0].clone())));
+                        night_actions.push(NightAction::Save(player.clone(), true));
+                        night_actions.push(NightAction::Poison(player.clone(), Some(others[others.len() - 1].clone())));
+                    }
+                    events.extend(night_actions.iter().cloned().map(Event::NightAction));
+                    let game_state = night.resolve_nar(&night_actions, hunter_target).expect("failed to resolve night");
+                    events.append(&mut hunter_shots.borrow_mut());
+                    events.push(Event::Dawn);
+                    game_state
+                }
+                State::Day(day) => {
+                    let target = day.alive().into_iter().max().cloned().expect("no alive players");
+                    let game_state = day.lynch(target.clone(), hunter_target).expect("failed to lynch");
+                    events.append(&mut hunter_shots.borrow_mut());
+                    events.push(Event::Lynch(Some(target)));
+                    game_state
+                }
+                State::Complete(complete) => {
+                    events.push(Event::GameOver(complete.winners.iter().cloned().collect()));
+                    break State::Complete(complete)
+                }
+            };
+            expected_alive.push(alive(&game_state));
+        };
+        check_replay::<Multiverse>(&events, &expected_alive);
+        check_replay::<LazyMultiverse>(&events, &expected_alive);
+    }
+}
@@ -0,0 +1,87 @@
+use quantum_werewolf::game::{
+    NightAction,
+    Role,
+    state::{
+        Day,
+        GameError,
+        Night,
+        Rules,
+        Signups,
+        StartGameError,
+        State
+    }
+};
+
+const NAMES: [&str; 6] = ["alice", "bob", "carol", "dave", "erin", "frank"];
+
+fn first_night(rules: Rules) -> Night<&'static str> {
+    let mut signups = Signups::with_seed(0);
+    for name in NAMES {
+        signups.sign_up(name);
+    }
+    let State::Night(night) = signups.start(vec![Role::Werewolf(0), Role::Healer], rules).expect("failed to start game") else { panic!("game did not start with a night") };
+    night
+}
+
+fn first_day(rules: Rules) -> Day<&'static str> {
+    let State::Day(day) = first_night(rules).resolve_nar(&[], |_, _| None).expect("failed to resolve night") else { panic!("game did not continue with a day") };
+    day
+}
+
+/// Plays until the second night, with alice healing bob during the first night.
+fn second_night(rules: Rules) -> Night<&'static str> {
+    let night = first_night(rules);
+    let State::Day(day) = night.resolve_nar(&[NightAction::Heal("alice", "bob")], |_, _| None).expect("failed to resolve night") else { panic!("game did not continue with a day") };
+    let State::Night(night) = day.no_lynch().expect("failed to skip the lynch") else { panic!("game did not continue with a night") };
+    night
+}
+
+#[test]
+fn repeat_heals() {
+    let night = second_night(Rules { repeat_heals: false, ..Rules::default() });
+    assert!(matches!(night.check_night_action(&NightAction::Heal("alice", "bob")), Err(GameError::IllegalAction(_))));
+    assert!(night.check_night_action(&NightAction::Heal("alice", "carol")).is_ok());
+    let night = second_night(Rules { repeat_heals: true, ..Rules::default() });
+    assert!(night.check_night_action(&NightAction::Heal("alice", "bob")).is_ok());
+}
+
+#[test]
+fn self_heals() {
+    let night = first_night(Rules { self_heals: false, ..Rules::default() });
+    assert!(matches!(night.check_night_action(&NightAction::Heal("alice", "alice")), Err(GameError::IllegalAction(_))));
+    assert!(night.check_night_action(&NightAction::Heal("alice", "bob")).is_ok());
+    let night = first_night(Rules { self_heals: true, ..Rules::default() });
+    assert!(night.check_night_action(&NightAction::Heal("alice", "alice")).is_ok());
+}
+
+#[test]
+fn no_lynch() {
+    let day = first_day(Rules { no_lynch: false, ..Rules::default() });
+    assert!(!day.can_skip_lynch());
+    assert!(matches!(day.no_lynch(), Err(GameError::IllegalAction(_))));
+    let day = first_day(Rules { no_lynch: true, ..Rules::default() });
+    assert!(day.can_skip_lynch());
+    assert!(matches!(day.no_lynch(), Ok(State::Night(_))));
+}
+
+#[test]
+fn mandatory_werewolf_kill() {
+    let night = first_night(Rules { mandatory_werewolf_kill: true, ..Rules::default() });
+    assert!(matches!(night.check_night_action(&NightAction::Kill("alice", None)), Err(GameError::IllegalAction(_))));
+    assert!(night.check_night_action(&NightAction::Kill("alice", Some("bob"))).is_ok());
+    let night = first_night(Rules { mandatory_werewolf_kill: false, ..Rules::default() });
+    assert!(night.check_night_action(&NightAction::Kill("alice", None)).is_ok());
+}
+
+#[test]
+fn min_players() {
+    let start = |min_players| {
+        let mut signups = Signups::with_seed(0);
+        for name in &NAMES[..4] {
+            signups.sign_up(*name);
+        }
+        signups.start(vec![Role::Werewolf(0)], Rules { min_players, ..Rules::default() })
+    };
+    assert!(matches!(start(5), Err(StartGameError::NotEnoughPlayers { required: 5, found: 4 })));
+    assert!(matches!(start(4), Ok(State::Night(_))));
+}
@@ -0,0 +1,124 @@
+use quantum_werewolf::{
+    game::{
+        NightAction,
+        Role
+    },
+    handler::voting::Choice::{
+        Lynch,
+        NoLynch
+    },
+    server::{
+        Phase,
+        Session,
+        SessionError,
+        SessionOptions
+    }
+};
+
+const PLAYERS: [&str; 5] = ["alice", "bob", "carol", "dave", "erin"];
+
+/// Creates a session and signs up all players. Returns the session, the admin token, and the players' tokens.
+fn signed_up() -> (Session, String, [String; 5]) {
+    let mut session = Session::new(SessionOptions { seed: Some(0), ..SessionOptions::default() });
+    let admin_token = session.admin_token().to_owned();
+    let tokens = PLAYERS.map(|name| session.sign_up(name.to_owned()).expect("failed to sign up"));
+    (session, admin_token, tokens)
+}
+
+#[test]
+fn join() {
+    let (mut session, admin_token, tokens) = signed_up();
+    assert_eq!(session.phase(), Phase::Signups);
+    assert_eq!(session.public_state().players, PLAYERS);
+    assert!(matches!(session.sign_up("alice".to_owned()), Err(SessionError::DuplicateName)));
+    assert!(matches!(session.sign_up(String::default()), Err(SessionError::EmptyName)));
+    for (name, token) in PLAYERS.iter().zip(&tokens) {
+        assert_eq!(session.player(token).expect("invalid token"), *name);
+        let private_state = session.private_state(token).expect("invalid token");
+        assert_eq!(private_state.name, *name);
+        assert_eq!(private_state.secret_id, None);
+    }
+    assert!(matches!(session.player(&admin_token), Err(SessionError::InvalidToken)));
+    assert!(matches!(session.player("not a token"), Err(SessionError::InvalidToken)));
+    // only the admin can start the game, and failing to start keeps the signups
+    assert!(matches!(session.start(&tokens[0], None), Err(SessionError::NotAdmin)));
+    assert!(matches!(session.start(&admin_token, Some(vec![Role::Werewolf(0); 6])), Err(SessionError::Start(_))));
+    assert_eq!(session.phase(), Phase::Signups);
+    session.start(&admin_token, None).expect("failed to start game");
+    assert_eq!(session.phase(), Phase::Night);
+    assert_eq!(session.public_state().alive, PLAYERS);
+    let mut secret_ids = tokens.iter().map(|token| session.private_state(token).expect("invalid token").secret_id.expect("no secret ID")).collect::<Vec<_>>();
+    secret_ids.sort();
+    assert_eq!(secret_ids, [0, 1, 2, 3, 4]);
+    assert!(matches!(session.sign_up("frank".to_owned()), Err(SessionError::WrongPhase)));
+    assert!(matches!(session.start(&admin_token, None), Err(SessionError::WrongPhase)));
+}
+
+#[test]
+fn act() {
+    let (mut session, admin_token, tokens) = signed_up();
+    session.start(&admin_token, Some(vec![Role::Werewolf(0)])).expect("failed to start game");
+    assert!(matches!(session.vote(&tokens[0], vec![NoLynch]), Err(SessionError::WrongPhase)));
+    // players can only act for themselves
+    assert!(matches!(session.submit_night_action(&tokens[0], NightAction::Kill("bob".to_owned(), Some("carol".to_owned()))), Err(SessionError::InvalidToken)));
+    // the rules require a werewolf kill
+    assert!(matches!(session.submit_night_action(&tokens[0], NightAction::Kill("alice".to_owned(), None)), Err(SessionError::Game(_))));
+    // a later action of the same kind replaces the earlier one
+    let version = session.version();
+    session.submit_night_action(&tokens[0], NightAction::Kill("alice".to_owned(), Some("bob".to_owned()))).expect("failed to submit night action");
+    session.submit_night_action(&tokens[0], NightAction::Kill("alice".to_owned(), Some("carol".to_owned()))).expect("failed to submit night action");
+    assert!(session.version() > version);
+    assert_eq!(session.private_state(&tokens[0]).expect("invalid token").night_actions, [NightAction::Kill("alice".to_owned(), Some("carol".to_owned()))]);
+    // the night is resolved once everyone has submitted their actions
+    for (name, token) in PLAYERS.iter().zip(&tokens).skip(1) {
+        assert_eq!(session.phase(), Phase::Night);
+        session.submit_night_action(token, NightAction::Kill((*name).to_owned(), Some("alice".to_owned()))).expect("failed to submit night action");
+    }
+    assert_eq!(session.phase(), Phase::Day);
+    let public_state = session.public_state();
+    assert!(public_state.probability_table.is_some());
+    assert!(public_state.ballots.is_empty());
+    assert!(session.private_state(&tokens[0]).expect("invalid token").night_actions.is_empty());
+    assert!(matches!(session.submit_night_action(&tokens[0], NightAction::Kill("alice".to_owned(), Some("bob".to_owned()))), Err(SessionError::WrongPhase)));
+}
+
+#[test]
+fn vote() {
+    let (mut session, admin_token, tokens) = signed_up();
+    session.start(&admin_token, Some(vec![Role::Werewolf(0)])).expect("failed to start game");
+    assert!(matches!(session.advance(&tokens[0]), Err(SessionError::NotAdmin)));
+    // randomizes the werewolf kills
+    session.advance(&admin_token).expect("failed to resolve night");
+    assert_eq!(session.phase(), Phase::Day);
+    let alive = session.public_state().alive.clone();
+    let voters = PLAYERS.iter().zip(&tokens).filter(|(name, _)| alive.iter().any(|alive| alive == *name)).collect::<Vec<_>>();
+    let target = alive[0].clone();
+    let dead_token = PLAYERS.iter().zip(&tokens).find(|(name, _)| !alive.iter().any(|alive| alive == *name)).map(|(_, token)| token);
+    if let Some(dead_token) = dead_token {
+        assert!(matches!(session.vote(dead_token, vec![NoLynch]), Err(SessionError::Vote(_))));
+    }
+    // a vote can be changed until everyone has voted
+    let (_, first_token) = voters[0];
+    session.vote(first_token, vec![NoLynch]).expect("failed to vote");
+    session.vote(first_token, vec![Lynch(target.clone())]).expect("failed to vote");
+    let ballots = &session.public_state().ballots;
+    assert_eq!(ballots.len(), 1);
+    assert_eq!(ballots[0].voter, *voters[0].0);
+    assert_eq!(ballots[0].choices, [Lynch(target.clone())]);
+    for &(_, token) in &voters[1..] {
+        assert_eq!(session.phase(), Phase::Day);
+        session.vote(token, vec![Lynch(target.clone())]).expect("failed to vote");
+    }
+    let public_state = session.public_state();
+    assert_eq!(public_state.tallies.len(), 1);
+    assert_eq!(public_state.tallies[0].decision, Some(target.clone()));
+    assert!(public_state.ballots.is_empty());
+    match public_state.phase {
+        Phase::Night => {
+            assert!(!public_state.alive.contains(&target));
+            assert!(public_state.deaths.iter().any(|(player, _)| *player == target));
+        }
+        Phase::Complete => assert!(public_state.winners.is_some()),
+        phase => panic!("unexpected phase after lynch: {phase:?}")
+    }
+}
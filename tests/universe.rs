@@ -0,0 +1,342 @@
+use quantum_werewolf::game::{
+    CauseOfDeath,
+    Faction,
+    KillRule,
+    Multiverse,
+    MultiverseBackend as _,
+    Op,
+    Role,
+    Universe
+};
+
+/// Applies the given operations, checking that none of them eliminates the universe.
+fn apply(universe: &mut Universe, ops: impl IntoIterator<Item = Op>) {
+    for op in ops {
+        assert!(universe.apply(op), "{op:?} eliminated the universe");
+    }
+}
+
+/// The operations for the werewolf in the given position killing the given player.
+fn werewolf_kill(werewolf_idx: usize, target_idx: usize) -> [Op; 2] {
+    [Op::Kill(werewolf_idx, target_idx), Op::WerewolfKill(KillRule::Dominant)]
+}
+
+#[test]
+fn save() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Witch, Role::Villager, Role::Villager]);
+    apply(&mut universe, werewolf_kill(0, 2));
+    apply(&mut universe, [Op::Save(1), Op::EndNight]);
+    assert!(universe.is_alive(2));
+    // the potion has been used up
+    apply(&mut universe, werewolf_kill(0, 3));
+    apply(&mut universe, [Op::Save(1), Op::EndNight]);
+    assert!(!universe.is_alive(3));
+    assert_eq!(universe.cause_of_death(3), Some(CauseOfDeath::Werewolves));
+}
+
+#[test]
+fn save_without_victim() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Witch, Role::Villager]);
+    // there is no one to save, so the potion is not used up
+    apply(&mut universe, [Op::Save(1), Op::EndNight]);
+    apply(&mut universe, werewolf_kill(0, 2));
+    apply(&mut universe, [Op::Save(1), Op::EndNight]);
+    assert!(universe.is_alive(2));
+}
+
+#[test]
+fn save_only_werewolf_victims() {
+    // the order of the poison and the werewolf attack must not matter
+    for poison_first in [false, true] {
+        let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Witch, Role::Villager, Role::Villager]);
+        if poison_first { apply(&mut universe, [Op::Poison(1, 2)]) }
+        apply(&mut universe, werewolf_kill(0, 3));
+        if !poison_first { apply(&mut universe, [Op::Poison(1, 2)]) }
+        apply(&mut universe, [Op::Save(1), Op::EndNight]);
+        assert!(!universe.is_alive(2));
+        assert_eq!(universe.cause_of_death(2), Some(CauseOfDeath::Poison));
+        assert!(universe.is_alive(3));
+    }
+}
+
+#[test]
+fn dead_witch_cannot_act() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Witch, Role::Villager, Role::Villager]);
+    apply(&mut universe, werewolf_kill(0, 1));
+    apply(&mut universe, [Op::EndNight]);
+    apply(&mut universe, werewolf_kill(0, 2));
+    apply(&mut universe, [Op::Save(1), Op::Poison(1, 3), Op::EndNight]);
+    assert!(!universe.is_alive(2));
+    assert!(universe.is_alive(3));
+}
+
+#[test]
+fn poison() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Witch, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Poison(1, 0), Op::EndNight]);
+    assert!(!universe.is_alive(0));
+    assert_eq!(universe.cause_of_death(0), Some(CauseOfDeath::Poison));
+    // the potion has been used up
+    apply(&mut universe, [Op::Poison(1, 2), Op::EndNight]);
+    assert!(universe.is_alive(2));
+}
+
+#[test]
+fn poison_only_witch() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Witch, Role::Villager]);
+    apply(&mut universe, [Op::Poison(2, 0), Op::EndNight]);
+    assert!(universe.is_alive(0));
+}
+
+#[test]
+fn heal_prevents_poison() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Witch, Role::Healer, Role::Villager]);
+    apply(&mut universe, [Op::Heal(2, 3), Op::Poison(1, 3), Op::EndNight]);
+    assert!(universe.is_alive(3));
+}
+
+#[test]
+fn shoot() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Hunter, Role::Villager]);
+    apply(&mut universe, [Op::Lynch(1), Op::Shoot(0)]);
+    assert_eq!(universe.cause_of_death(1), Some(CauseOfDeath::Lynch));
+    assert_eq!(universe.cause_of_death(0), Some(CauseOfDeath::Shot));
+    // the shot player must have been alive
+    assert!(!universe.apply(Op::Shoot(0)));
+}
+
+#[test]
+fn collapse() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Hunter, Role::Villager]);
+    apply(&mut universe, [Op::Collapse(1, Role::Hunter), Op::CollapseFaction(0, Faction::Werewolves), Op::CollapseFaction(2, Faction::Village)]);
+    assert!(!universe.clone().apply(Op::Collapse(2, Role::Hunter)));
+    assert!(!universe.apply(Op::CollapseFaction(1, Faction::Werewolves)));
+}
+
+#[test]
+fn link_night() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Cupid, Role::Villager, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Link(1, 2, 3)]);
+    assert!(universe.is_lover(2) && universe.is_lover(3));
+    apply(&mut universe, werewolf_kill(0, 2));
+    apply(&mut universe, [Op::EndNight]);
+    assert_eq!(universe.cause_of_death(2), Some(CauseOfDeath::Werewolves));
+    assert_eq!(universe.cause_of_death(3), Some(CauseOfDeath::Heartbreak));
+    assert!(universe.is_alive(4));
+}
+
+#[test]
+fn link_day() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Cupid, Role::Villager, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Link(1, 2, 3), Op::EndNight, Op::Lynch(3)]);
+    assert_eq!(universe.cause_of_death(3), Some(CauseOfDeath::Lynch));
+    assert_eq!(universe.cause_of_death(2), Some(CauseOfDeath::Heartbreak));
+}
+
+#[test]
+fn heal_saves_both_lovers() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Cupid, Role::Healer, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Link(1, 3, 4), Op::Heal(2, 3)]);
+    apply(&mut universe, werewolf_kill(0, 3));
+    apply(&mut universe, [Op::EndNight]);
+    assert!(universe.is_alive(3) && universe.is_alive(4));
+}
+
+#[test]
+fn link_only_cupid() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Cupid, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Link(2, 0, 3)]);
+    assert!(!universe.is_lover(0) && !universe.is_lover(3));
+}
+
+#[test]
+fn lovers_win_together() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Cupid, Role::Villager]);
+    apply(&mut universe, [Op::Link(1, 0, 2), Op::EndNight, Op::Lynch(1)]);
+    // only the lovers are left, so they win even though they are on different factions
+    assert!(universe.wins(0) && universe.wins(2));
+    assert!(!universe.wins(1));
+}
+
+#[test]
+fn serial_kill() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::SerialKiller, Role::Healer, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Heal(2, 3), Op::SerialKill(1, 3), Op::SerialKill(0, 4), Op::EndNight]);
+    // the kill was healed, and the werewolf is not a serial killer
+    assert!(universe.is_alive(3) && universe.is_alive(4));
+    apply(&mut universe, [Op::SerialKill(1, 3), Op::EndNight]);
+    assert_eq!(universe.cause_of_death(3), Some(CauseOfDeath::SerialKiller));
+}
+
+#[test]
+fn werewolf_attack_takes_precedence() {
+    // regardless of the order, a player killed by both the werewolves and the serial killer died of the werewolf attack
+    for serial_kill_first in [false, true] {
+        let mut universe = Universe::from(vec![Role::Werewolf(0), Role::SerialKiller, Role::Villager]);
+        if serial_kill_first { apply(&mut universe, [Op::SerialKill(1, 2)]) }
+        apply(&mut universe, werewolf_kill(0, 2));
+        if !serial_kill_first { apply(&mut universe, [Op::SerialKill(1, 2)]) }
+        apply(&mut universe, [Op::EndNight]);
+        assert_eq!(universe.cause_of_death(2), Some(CauseOfDeath::Werewolves));
+    }
+}
+
+#[test]
+fn serial_killer_wins_alone() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::SerialKiller, Role::Villager]);
+    apply(&mut universe, [Op::SerialKill(1, 0), Op::EndNight, Op::Lynch(2)]);
+    assert!(universe.wins(1));
+    assert!(!universe.wins(0) && !universe.wins(2));
+}
+
+#[test]
+fn tanner_wins_when_lynched() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Tanner, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::EndNight]);
+    assert!(!universe.wins(1));
+    apply(&mut universe, [Op::Lynch(1)]);
+    assert!(universe.wins(1));
+}
+
+#[test]
+fn guard() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Bodyguard, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Guard(1, 2)]);
+    apply(&mut universe, werewolf_kill(0, 2));
+    apply(&mut universe, [Op::EndNight]);
+    assert!(universe.is_alive(2));
+    assert_eq!(universe.cause_of_death(1), Some(CauseOfDeath::Werewolves));
+    // the bodyguard is dead, so they can't guard anyone anymore
+    apply(&mut universe, [Op::Guard(1, 3)]);
+    apply(&mut universe, werewolf_kill(0, 3));
+    apply(&mut universe, [Op::EndNight]);
+    assert!(!universe.is_alive(3));
+}
+
+#[test]
+fn guard_only_werewolf_attacks() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Bodyguard, Role::SerialKiller, Role::Villager]);
+    apply(&mut universe, [Op::Guard(1, 3), Op::SerialKill(2, 3), Op::EndNight]);
+    assert_eq!(universe.cause_of_death(3), Some(CauseOfDeath::SerialKiller));
+    assert!(universe.is_alive(1));
+}
+
+#[test]
+fn guard_only_bodyguard() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Bodyguard, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Guard(2, 3)]);
+    apply(&mut universe, werewolf_kill(0, 3));
+    apply(&mut universe, [Op::EndNight]);
+    assert!(!universe.is_alive(3));
+    assert!(universe.is_alive(2));
+}
+
+#[test]
+fn heal_guarded_bodyguard() {
+    // the bodyguard is attacked in place of the guarded player, so healing the bodyguard saves both
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Bodyguard, Role::Healer, Role::Villager]);
+    apply(&mut universe, [Op::Heal(2, 1), Op::Guard(1, 3)]);
+    apply(&mut universe, werewolf_kill(0, 3));
+    apply(&mut universe, [Op::EndNight]);
+    assert!(universe.is_alive(1) && universe.is_alive(3));
+}
+
+#[test]
+fn save_bodyguard() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Bodyguard, Role::Witch, Role::Villager]);
+    apply(&mut universe, [Op::Guard(1, 3)]);
+    apply(&mut universe, werewolf_kill(0, 3));
+    apply(&mut universe, [Op::Save(2), Op::EndNight]);
+    assert!(universe.is_alive(1) && universe.is_alive(3));
+}
+
+#[test]
+fn see_exact_role() {
+    let universe = Universe::from(vec![Role::Seer, Role::Werewolf(0), Role::Werewolf(1), Role::Villager]);
+    // the seer learns the rank of a werewolf, not just the faction
+    for (target_idx, role, possible) in [(1, Role::Werewolf(0), true), (2, Role::Werewolf(0), false), (2, Role::Werewolf(1), true), (3, Role::Werewolf(0), false), (3, Role::Villager, true)] {
+        let mut universe = universe.clone();
+        assert_eq!(universe.apply(Op::See(0, target_idx, role)), possible, "{target_idx} as {role:?}");
+    }
+}
+
+#[test]
+fn see_eliminates_universes() {
+    let mut multiverse = Multiverse::new(vec![Role::Seer, Role::Werewolf(0), Role::Werewolf(1)], 4);
+    multiverse.apply(Op::See(0, 1, Role::Werewolf(1)));
+    assert!(multiverse.iter().any(|universe| universe.role(0) == Role::Seer), "all universes with the seer were eliminated");
+    for universe in multiverse.iter() {
+        if universe.role(0) == Role::Seer {
+            assert_eq!(universe.role(1), Role::Werewolf(1));
+        }
+    }
+    // universes in which someone else is the seer are unaffected
+    assert!(multiverse.iter().any(|universe| universe.role(0) != Role::Seer && universe.role(1) == Role::Werewolf(0)));
+}
+
+#[test]
+fn werewolves_need_a_survivor() {
+    // only the tanner is left, so the werewolves have not won
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Tanner, Role::Villager]);
+    apply(&mut universe, [Op::Lynch(0), Op::Lynch(2)]);
+    assert!(!Faction::Werewolves.wincon(&universe));
+    assert!(!universe.wins(0));
+    // but they have if a werewolf is left
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Tanner, Role::Villager]);
+    apply(&mut universe, [Op::Lynch(2)]);
+    assert!(Faction::Werewolves.wincon(&universe));
+    assert!(universe.wins(0));
+}
+
+#[test]
+fn seance_learns_cause_of_death() {
+    let mut universe = Universe::from(vec![Role::Medium, Role::Werewolf(0), Role::Witch, Role::Villager, Role::Villager, Role::Villager]);
+    apply(&mut universe, werewolf_kill(1, 3));
+    apply(&mut universe, [Op::Poison(2, 4), Op::EndNight, Op::Lynch(5)]);
+    let causes = [CauseOfDeath::Werewolves, CauseOfDeath::Poison, CauseOfDeath::Lynch];
+    for (target_idx, cause) in [3, 4, 5].into_iter().zip(causes) {
+        assert_eq!(universe.cause_of_death(target_idx), Some(cause));
+        // only the actual cause of death is consistent with this universe
+        for iter_cause in causes {
+            assert_eq!(universe.clone().apply(Op::Seance(0, target_idx, iter_cause)), iter_cause == cause, "{target_idx} died of {cause:?}, not {iter_cause:?}");
+        }
+    }
+}
+
+#[test]
+fn kill_rules() {
+    // werewolves 0 and 1 vote for different targets, the lower rank belongs to player 1
+    let roles = vec![Role::Werewolf(1), Role::Werewolf(0), Role::Villager, Role::Villager, Role::Villager];
+    for (kill_rule, victims) in [(KillRule::Dominant, vec![3]), (KillRule::Every, vec![2, 3]), (KillRule::Majority, vec![3])] {
+        let mut universe = Universe::from(roles.clone());
+        apply(&mut universe, [Op::Kill(0, 2), Op::Kill(1, 3), Op::WerewolfKill(kill_rule), Op::EndNight]);
+        for player_idx in 2..5 {
+            assert_eq!(!universe.is_alive(player_idx), victims.contains(&player_idx), "{kill_rule:?}: player {player_idx}");
+        }
+    }
+}
+
+#[test]
+fn majority_tie_goes_to_lowest_rank() {
+    let mut universe = Universe::from(vec![Role::Werewolf(2), Role::Werewolf(0), Role::Werewolf(1), Role::Werewolf(3), Role::Villager, Role::Villager, Role::Villager]);
+    // 2 votes each for players 4 and 5, the werewolf with rank 0 voted for 5
+    apply(&mut universe, [Op::Kill(0, 4), Op::Kill(1, 5), Op::Kill(2, 4), Op::Kill(3, 5), Op::WerewolfKill(KillRule::Majority), Op::EndNight]);
+    assert!(universe.is_alive(4));
+    assert!(!universe.is_alive(5));
+    assert!(universe.is_alive(6));
+    // a clear majority wins regardless of rank
+    let mut universe = Universe::from(vec![Role::Werewolf(2), Role::Werewolf(0), Role::Werewolf(1), Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Kill(0, 3), Op::Kill(1, 4), Op::Kill(2, 3), Op::WerewolfKill(KillRule::Majority), Op::EndNight]);
+    assert!(!universe.is_alive(3));
+    assert!(universe.is_alive(4));
+}
+
+#[test]
+fn save_every_victim() {
+    let mut universe = Universe::from(vec![Role::Werewolf(0), Role::Werewolf(1), Role::Witch, Role::Villager, Role::Villager]);
+    apply(&mut universe, [Op::Kill(0, 3), Op::Kill(1, 4), Op::WerewolfKill(KillRule::Every), Op::Save(2), Op::EndNight]);
+    // the one potion saves all werewolf victims of the night
+    assert!(universe.is_alive(3) && universe.is_alive(4));
+    apply(&mut universe, [Op::Kill(0, 3), Op::Kill(1, 4), Op::WerewolfKill(KillRule::Every), Op::Save(2), Op::EndNight]);
+    assert!(!universe.is_alive(3) && !universe.is_alive(4));
+}
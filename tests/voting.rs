@@ -0,0 +1,229 @@
+mod common;
+
+use std::{
+    collections::HashSet,
+    time::Instant
+};
+use quantum_werewolf::{
+    Handler,
+    game::{
+        self,
+        event::Event,
+        state::{
+            Rules,
+            Signups
+        }
+    },
+    handler::voting::{
+        Choice::{
+            self,
+            Lynch,
+            NoLynch
+        },
+        Poll,
+        TieRule,
+        VoteError,
+        VotingSystem
+    },
+    server::{
+        Phase,
+        Session,
+        SessionError,
+        SessionOptions
+    }
+};
+
+use crate::common::{
+    Bot,
+    Log,
+    NAMES,
+    Recorder
+};
+
+const PLAYERS: [&str; 5] = ["alice", "bob", "carol", "dave", "erin"];
+
+fn poll(system: VotingSystem) -> Poll<&'static str> {
+    Poll::new(system, PLAYERS.to_vec(), PLAYERS.to_vec(), true)
+}
+
+fn vote(poll: &mut Poll<&'static str>, votes: &[(&'static str, &[Choice<&'static str>])]) {
+    for &(voter, ballot) in votes {
+        poll.vote(&voter, ballot.to_vec()).expect("invalid vote");
+    }
+}
+
+#[test]
+fn majority() {
+    let mut poll = poll(VotingSystem::Majority);
+    vote(&mut poll, &[("alice", &[Lynch("erin")]), ("bob", &[Lynch("erin")]), ("carol", &[Lynch("dave")])]);
+    // 2 of 5 voters is not a majority, even though only 3 have voted
+    assert_eq!(poll.tally().decision, None);
+    vote(&mut poll, &[("dave", &[Lynch("erin")])]);
+    let tally = poll.tally();
+    assert_eq!(tally.decision, Some("erin"));
+    assert_eq!(tally.votes, vec![(Lynch("erin"), 3), (Lynch("dave"), 1)]);
+}
+
+#[test]
+fn majority_abstentions_count_against() {
+    let mut poll = poll(VotingSystem::Majority);
+    vote(&mut poll, &[("alice", &[Lynch("erin")]), ("bob", &[Lynch("erin")]), ("carol", &[]), ("dave", &[]), ("erin", &[])]);
+    assert!(poll.is_complete());
+    assert_eq!(poll.tally().decision, None);
+}
+
+#[test]
+fn plurality_ties() {
+    let votes: &[(&str, &[Choice<&str>])] = &[("alice", &[Lynch("dave")]), ("bob", &[Lynch("erin")]), ("carol", &[Lynch("erin")]), ("dave", &[Lynch("dave")]), ("erin", &[NoLynch])];
+    let mut no_lynch = poll(VotingSystem::Plurality);
+    vote(&mut no_lynch, votes);
+    assert_eq!(no_lynch.tally().decision, None);
+    let mut first_vote = poll(VotingSystem::Plurality).tie_rule(TieRule::FirstVote);
+    vote(&mut first_vote, votes);
+    assert_eq!(first_vote.tally().decision, Some("dave"));
+    // changing a ballot counts as casting it again
+    vote(&mut first_vote, &[("alice", &[Lynch("dave")])]);
+    assert_eq!(first_vote.tally().decision, Some("erin"));
+}
+
+#[test]
+fn plurality_no_lynch_wins() {
+    let mut poll = poll(VotingSystem::Plurality);
+    vote(&mut poll, &[("alice", &[NoLynch]), ("bob", &[NoLynch]), ("carol", &[Lynch("erin")])]);
+    assert_eq!(poll.tally().decision, None);
+    assert_eq!(poll.tally().votes, vec![(NoLynch, 2), (Lynch("erin"), 1)]);
+}
+
+#[test]
+fn approval() {
+    let mut poll = poll(VotingSystem::Approval);
+    vote(&mut poll, &[("alice", &[Lynch("dave"), Lynch("erin")]), ("bob", &[Lynch("erin"), Lynch("erin")]), ("carol", &[Lynch("dave"), NoLynch, Lynch("erin")])]);
+    let tally = poll.tally();
+    // duplicate options only count once
+    assert_eq!(tally.votes, vec![(Lynch("erin"), 3), (Lynch("dave"), 2), (NoLynch, 1)]);
+    assert_eq!(tally.decision, Some("erin"));
+}
+
+#[test]
+fn ranked_runoff() {
+    let mut poll = poll(VotingSystem::RankedRunoff);
+    vote(&mut poll, &[
+        ("alice", &[Lynch("alice"), Lynch("carol")]),
+        ("bob", &[Lynch("bob"), Lynch("carol")]),
+        ("carol", &[Lynch("carol")]),
+        ("dave", &[Lynch("carol"), Lynch("bob")]),
+        ("erin", &[Lynch("bob")]),
+    ]);
+    // alice is eliminated first, then her ballot counts for carol, who has 3 of 5 votes
+    let tally = poll.tally();
+    assert_eq!(tally.decision, Some("carol"));
+    assert_eq!(tally.votes, vec![(Lynch("carol"), 3), (Lynch("bob"), 2)]);
+}
+
+#[test]
+fn ranked_runoff_tie() {
+    let mut poll = poll(VotingSystem::RankedRunoff).tie_rule(TieRule::FirstVote);
+    vote(&mut poll, &[("alice", &[Lynch("dave")]), ("bob", &[Lynch("erin")]), ("carol", &[Lynch("erin")]), ("dave", &[Lynch("dave")])]);
+    assert_eq!(poll.tally().decision, Some("dave"));
+}
+
+#[test]
+fn invalid_votes() {
+    let mut poll = Poll::new(VotingSystem::Majority, vec!["alice", "bob"], vec!["bob", "carol"], false);
+    assert!(matches!(poll.vote(&"carol", vec![Lynch("bob")]), Err(VoteError::NotAVoter)));
+    assert!(matches!(poll.vote(&"alice", vec![Lynch("alice")]), Err(VoteError::InvalidCandidate)));
+    assert!(matches!(poll.vote(&"alice", vec![NoLynch]), Err(VoteError::NoLynchNotAllowed)));
+    assert!(matches!(poll.vote(&"alice", vec![Lynch("bob"), Lynch("carol")]), Err(VoteError::TooManyChoices)));
+    assert!(poll.ballot(&"alice").is_none());
+}
+
+#[test]
+fn deadline() {
+    let mut poll = poll(VotingSystem::Majority).deadline(Instant::now());
+    assert!(!poll.is_open());
+    assert!(matches!(poll.vote(&"alice", vec![Lynch("bob")]), Err(VoteError::Closed)));
+}
+
+#[test]
+fn session_deadline() {
+    let mut session = Session::new(SessionOptions { seed: Some(0), vote_duration: Some(0), ..SessionOptions::default() });
+    let admin_token = session.admin_token().to_owned();
+    let tokens = PLAYERS.map(|name| session.sign_up(name.to_owned()).expect("failed to sign up"));
+    session.start(&admin_token, None).expect("failed to start game");
+    session.advance(&admin_token).expect("failed to resolve night");
+    assert_eq!(session.phase(), Phase::Day);
+    let alive = session.public_state().alive.clone();
+    assert!(matches!(session.vote(&tokens[0], vec![Lynch(alive[0].clone())]), Err(SessionError::Vote(VoteError::Closed))));
+    assert!(session.tick());
+    assert_eq!(session.public_state().tallies.len(), 1);
+    // no one voted, so no one was lynched and the day is over
+    assert_eq!(session.phase(), Phase::Night);
+    assert!(!session.tick());
+}
+
+#[test]
+fn lynch_required() {
+    let mut poll = Poll::new(VotingSystem::Plurality, PLAYERS.to_vec(), PLAYERS.to_vec(), false);
+    assert!(!poll.allows_no_lynch());
+    // no-lynch votes are rejected, so they don't count towards the tally
+    for voter in ["alice", "bob", "carol"] {
+        assert!(matches!(poll.vote(&voter, vec![NoLynch]), Err(VoteError::NoLynchNotAllowed)));
+    }
+    vote(&mut poll, &[("dave", &[Lynch("erin")])]);
+    let tally = poll.tally();
+    assert_eq!(tally.decision, Some("erin"));
+    assert_eq!(tally.votes, vec![(Lynch("erin"), 1)]);
+}
+
+/// A handler which records the game like `Recorder`, but lets the players vote on the lynch target like the handlers in this crate.
+struct PollingRecorder<'a>(Recorder<'a>);
+
+impl Handler<Bot> for PollingRecorder<'_> {
+    fn record_event(&mut self, event: &Event<Bot>) {
+        self.0.record_event(event);
+    }
+
+    fn announce_votes<I: IntoIterator<Item = (Bot, Option<Bot>)>>(&mut self, votes: I) {
+        self.0.announce_votes(votes);
+    }
+
+    fn choose_lynch_target(&mut self, alive: HashSet<&Bot>) -> Option<Bot> {
+        let mut voters = alive.into_iter().cloned().collect::<Vec<_>>();
+        voters.sort();
+        let mut poll = Poll::new(VotingSystem::Majority, voters.clone(), voters, true);
+        let votes = poll.collect_votes();
+        self.record_event(&Event::Votes(votes.clone()));
+        self.announce_votes(votes);
+        poll.tally().decision
+    }
+}
+
+#[test]
+fn recorded_votes_match_cast_votes() {
+    let mut signups = Signups::with_seed(0);
+    for name in NAMES {
+        signups.sign_up(Bot(name.to_owned()));
+    }
+    let mut log = Log::default();
+    game::run_with_roles(PollingRecorder(Recorder(&mut log)), signups, common::roles(), Rules::default()).expect("game failed");
+    let logged_votes = log.events.iter().filter_map(|event| if let Event::Votes(votes) = event { Some(votes.clone()) } else { None }).collect::<Vec<_>>();
+    assert!(!logged_votes.is_empty(), "no votes were logged");
+    assert_eq!(logged_votes, log.announced_votes);
+    let mut dead = HashSet::new();
+    for (event_idx, event) in log.events.iter().enumerate() {
+        if let Event::Death(player, _) = event { dead.insert(player.clone()); }
+        let Event::Votes(votes) = event else { continue };
+        // everyone who may be alive votes in order, each for the first other player by name
+        let voters = votes.iter().map(|(voter, _)| voter).collect::<Vec<_>>();
+        let alive = NAMES.into_iter().map(|name| Bot(name.to_owned())).filter(|player| !dead.contains(player)).collect::<Vec<_>>();
+        assert_eq!(voters, alive.iter().collect::<Vec<_>>());
+        for (voter, vote) in votes {
+            let expected = voters.iter().copied().filter(|&candidate| candidate != voter).min();
+            assert_eq!(vote.as_ref(), expected, "{voter:?} cast a different vote");
+        }
+        // a majority of the players voted for the lynched player
+        let Some(Event::Lynch(lynched)) = log.events[event_idx + 1..].iter().find(|event| matches!(event, Event::Lynch(_))) else { panic!("no lynch after the votes") };
+        let num_votes = votes.iter().filter(|(_, vote)| vote == lynched).count();
+        assert!(lynched.is_none() || num_votes * 2 > votes.len(), "{lynched:?} was lynched with {num_votes} of {} votes", votes.len());
+    }
+}